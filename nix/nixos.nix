@@ -30,7 +30,7 @@ in
       description = ''
         Path to the environment file for munibot containing secrets for database, Discord, and Twitch authentication.
 
-        munibot requires the following variables to be set: DATABASE_PASS, DISCORD_APPLICATION_ID, DISCORD_CLIENT_SECRET, DISCORD_PUBLIC_KEY, DISCORD_TOKEN, TWITCH_CLIENT_ID, TWITCH_CLIENT_SECRET, and TWITCH_TOKEN.
+        munibot requires the following variables to be set: DATABASE_PASS, DISCORD_APPLICATION_ID, DISCORD_CLIENT_SECRET, DISCORD_PUBLIC_KEY, DISCORD_TOKEN, TWITCH_CLIENT_ID, and TWITCH_CLIENT_SECRET. Twitch tokens are obtained on first run and kept in the database.
       '';
     };
   };
@@ -64,7 +64,6 @@ in
               "DISCORD_TOKEN"
               "TWITCH_CLIENT_ID"
               "TWITCH_CLIENT_SECRET"
-              "TWITCH_TOKEN"
             ];
             Restart = "always";
             RestartSec = 10;
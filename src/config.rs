@@ -1,8 +1,8 @@
-use std::{fs, io::ErrorKind, path::Path};
+use std::{collections::HashMap, fs, io::ErrorKind, path::Path};
 
 use log::{info, warn};
-use poise::serenity_prelude::UserId;
-use serde::{Deserialize, Serialize};
+use poise::serenity_prelude::{ChannelId, GuildId, UserId};
+use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::MuniBotError;
 
@@ -15,10 +15,35 @@ pub struct Config {
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DbConfig {
+    /// Where the database lives. This can be a remote SurrealDB server
+    /// (`ws://host:port`, or just `host:port`), an in-memory database
+    /// (`mem://`), or an on-disk database (`surrealkv://path/to/dir`).
     pub url: String,
+
+    /// The user to sign in as. Only used for remote databases.
+    #[serde(default)]
     pub user: String,
 }
 
+impl DbConfig {
+    /// Returns the endpoint to connect to, assuming a websocket connection if
+    /// the url doesn't specify a scheme.
+    pub fn endpoint(&self) -> String {
+        if self.url.contains("://") {
+            self.url.clone()
+        } else {
+            format!("ws://{}", self.url)
+        }
+    }
+
+    /// Returns true if the database runs inside munibot rather than on a
+    /// server.
+    pub fn is_embedded(&self) -> bool {
+        let endpoint = self.endpoint();
+        endpoint.starts_with("mem://") || endpoint.starts_with("surrealkv://")
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct DiscordConfig {
     #[serde(default)]
@@ -35,6 +60,129 @@ pub struct TwitchConfig {
 
     #[serde(default)]
     pub initial_channels: Vec<String>,
+
+    /// Per-channel settings, keyed by channel login. Channels without an entry
+    /// here only get autoban protection. Configs written before this existed
+    /// keep every handler enabled in muni_corn's channel, like before.
+    #[serde(
+        default = "default_twitch_channels",
+        deserialize_with = "deserialize_twitch_channels"
+    )]
+    pub channels: HashMap<String, TwitchChannelConfig>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct TwitchChannelConfig {
+    /// Names of the message handlers enabled in this channel. `"*"` enables
+    /// every handler.
+    #[serde(default)]
+    pub handlers: Vec<String>,
+
+    /// The Discord server this channel belongs to. Chatters here earn coins
+    /// in its economy, and its admins can manage this channel's autoban
+    /// rules. The economy is off in channels without one. Also read from
+    /// `economy_guild`, which is what this used to be called.
+    #[serde(default, alias = "economy_guild")]
+    pub discord_guild: Option<GuildId>,
+
+    /// A Discord channel to announce things about this channel's stream in,
+    /// like content warnings.
+    #[serde(default)]
+    pub discord_announcements: Option<ChannelId>,
+
+    /// What happens when channel points rewards are redeemed in this channel.
+    #[serde(default)]
+    pub redemptions: Vec<RedemptionConfig>,
+}
+
+/// What munibot does when a channel points reward is redeemed.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RedemptionConfig {
+    /// The ID or title of the reward. Titles are matched case-insensitively.
+    pub reward: String,
+
+    /// The actions to take, in order.
+    #[serde(default)]
+    pub actions: Vec<RedemptionAction>,
+
+    /// Whether to mark the redemption fulfilled once every action succeeds.
+    #[serde(default)]
+    pub fulfill: bool,
+
+    /// Whether to give the viewer their points back if an action fails.
+    #[serde(default)]
+    pub refund_on_failure: bool,
+}
+
+impl RedemptionConfig {
+    /// Returns true if this config is for the given reward.
+    pub fn matches(&self, reward_id: &str, reward_title: &str) -> bool {
+        self.reward == reward_id || self.reward.eq_ignore_ascii_case(reward_title)
+    }
+}
+
+/// Something munibot does for a redemption. In messages, `{user}`, `{input}`
+/// and `{reward}` are replaced with the viewer's name, what they typed, and
+/// the reward's title.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RedemptionAction {
+    /// Sends a message in chat.
+    Chat { message: String },
+
+    /// Posts a message in a Discord channel.
+    Discord { channel: ChannelId, message: String },
+
+    /// Issues a content warning, or clears the active one. The warning is
+    /// `warning` if set, or what the viewer typed otherwise.
+    ContentWarning {
+        #[serde(default)]
+        warning: Option<String>,
+    },
+
+    /// Runs one of munibot's built-in handlers, by name.
+    Handler { name: String },
+}
+
+impl TwitchConfig {
+    /// Returns the logins of the channels that belong to the given Discord
+    /// server.
+    pub fn channels_for_guild(&self, guild_id: GuildId) -> Vec<&str> {
+        let mut channels: Vec<&str> = self
+            .channels
+            .iter()
+            .filter(|(_, channel)| channel.discord_guild == Some(guild_id))
+            .map(|(login, _)| login.as_str())
+            .collect();
+        channels.sort_unstable();
+        channels
+    }
+
+    /// Returns true if the handler with the given name should handle messages
+    /// in the given channel.
+    pub fn is_handler_enabled(&self, channel_login: &str, handler_name: &str) -> bool {
+        self.channels.get(channel_login).is_some_and(|channel| {
+            channel
+                .handlers
+                .iter()
+                .any(|h| h == "*" || h == handler_name)
+        })
+    }
+
+    /// Returns what to do for the given reward in the given channel, if
+    /// anything.
+    pub fn redemption_for(
+        &self,
+        channel_login: &str,
+        reward_id: &str,
+        reward_title: &str,
+    ) -> Option<&RedemptionConfig> {
+        self.channels
+            .get(channel_login)?
+            .redemptions
+            .iter()
+            .find(|redemption| redemption.matches(reward_id, reward_title))
+    }
 }
 
 impl Config {
@@ -121,6 +269,7 @@ impl Default for Config {
             twitch: TwitchConfig {
                 twitch_user: default_twitch_user(),
                 initial_channels: Vec::new(),
+                channels: default_twitch_channels(),
             },
         }
     }
@@ -129,3 +278,61 @@ impl Default for Config {
 fn default_twitch_user() -> String {
     "muni__bot".to_owned()
 }
+
+fn default_twitch_channels() -> HashMap<String, TwitchChannelConfig> {
+    HashMap::from([(
+        "muni_corn".to_owned(),
+        TwitchChannelConfig {
+            handlers: vec!["*".to_owned()],
+            ..Default::default()
+        },
+    )])
+}
+
+/// Reads the per-channel settings with their logins lowercased, since that's
+/// how they come in from chat.
+fn deserialize_twitch_channels<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, TwitchChannelConfig>, D::Error> {
+    let channels = HashMap::<String, TwitchChannelConfig>::deserialize(deserializer)?;
+    Ok(channels
+        .into_iter()
+        .map(|(login, channel)| (login.to_lowercase(), channel))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TwitchConfig;
+
+    #[test]
+    fn test_unconfigured_channels_keep_old_handlers() {
+        let config: TwitchConfig = toml::from_str("twitch_user = 'muni__bot'").unwrap();
+        assert!(config.is_handler_enabled("muni_corn", "quotes"));
+        assert!(!config.is_handler_enabled("someone_else", "quotes"));
+    }
+
+    #[test]
+    fn test_channel_logins_are_lowercased() {
+        let config: TwitchConfig = toml::from_str(
+            "[channels.Muni_Corn]
+             handlers = ['lurk']",
+        )
+        .unwrap();
+        assert!(config.is_handler_enabled("muni_corn", "lurk"));
+        assert!(!config.is_handler_enabled("muni_corn", "quotes"));
+    }
+
+    #[test]
+    fn test_economy_guild_is_still_read() {
+        let config: TwitchConfig = toml::from_str(
+            "[channels.muni_corn]
+             economy_guild = 1234",
+        )
+        .unwrap();
+        assert_eq!(
+            config.channels["muni_corn"].discord_guild,
+            Some(1234.into())
+        );
+    }
+}
@@ -1,6 +1,132 @@
+use std::{env, time::Duration};
+
 use async_trait::async_trait;
+use dotenvy::dotenv;
+use log::{info, warn};
 use serde::{de::DeserializeOwned, Serialize};
-use surrealdb::{opt::IntoResource, Connection, RecordIdKey, Surreal};
+use surrealdb::{
+    engine::any::{self, Any},
+    opt::{auth::Database, IntoResource},
+    Connection, RecordIdKey, Surreal,
+};
+use tokio::{task::JoinHandle, time::timeout};
+
+use crate::{config::DbConfig, MuniBotError};
+
+pub mod migrations;
+
+/// The database handle shared throughout munibot. The engine behind it is
+/// picked at runtime from the configured url, so it can be a remote server or
+/// an embedded database.
+pub type MuniBotDb = Surreal<Any>;
+
+const NAMESPACE: &str = "muni_bot";
+const DATABASE: &str = "muni_bot";
+
+/// The longest we'll wait between attempts to connect to the database.
+const MAX_CONNECT_BACKOFF: Duration = Duration::from_mins(1);
+
+/// How often the database connection is checked on.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a health check can take before the database is considered
+/// unreachable.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Connects to the database, retrying with exponential backoff until it
+/// works. Remote databases are signed in to with the `DATABASE_PASS`
+/// environment variable; this only fails if it isn't set.
+///
+/// Once connected, a remote client reconnects (and signs back in) on its own if
+/// the websocket drops. Use [`start_health_monitor`] to keep an eye on it.
+pub async fn connect(db_config: &DbConfig) -> Result<MuniBotDb, MuniBotError> {
+    let password = if db_config.is_embedded() {
+        None
+    } else {
+        dotenv().ok();
+        Some(
+            env::var("DATABASE_PASS")
+                .map_err(|e| MuniBotError::Other(format!("couldn't read DATABASE_PASS: {e}")))?,
+        )
+    };
+
+    let mut backoff = Duration::from_secs(1);
+    loop {
+        match try_connect(db_config, password.as_deref()).await {
+            Ok(db) => {
+                info!("connected to the database at {}", db_config.endpoint());
+                return Ok(db);
+            }
+            Err(e) => {
+                warn!(
+                    "couldn't connect to the database :< {e}. trying again in {}",
+                    humantime::format_duration(backoff)
+                );
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_CONNECT_BACKOFF);
+            }
+        }
+    }
+}
+
+async fn try_connect(
+    db_config: &DbConfig,
+    password: Option<&str>,
+) -> Result<MuniBotDb, surrealdb::Error> {
+    let db = any::connect(db_config.endpoint()).await?;
+
+    if let Some(password) = password {
+        db.signin(Database {
+            namespace: NAMESPACE,
+            database: DATABASE,
+            username: &db_config.user,
+            password,
+        })
+        .await?;
+    }
+    db.use_ns(NAMESPACE).use_db(DATABASE).await?;
+
+    Ok(db)
+}
+
+/// Creates a fresh in-memory database for tests, with every migration
+/// applied.
+#[cfg(test)]
+pub(crate) async fn test_db() -> MuniBotDb {
+    let db = empty_test_db().await;
+    migrations::run(&db).await.unwrap();
+    db
+}
+
+/// Creates a fresh in-memory database for tests, without applying any
+/// migrations.
+#[cfg(test)]
+async fn empty_test_db() -> MuniBotDb {
+    let db = any::connect("mem://").await.unwrap();
+    db.use_ns(NAMESPACE).use_db(DATABASE).await.unwrap();
+    db
+}
+
+/// Periodically checks that the database is reachable, logging when the
+/// connection is lost and when it comes back.
+pub fn start_health_monitor<C: Connection>(db: Surreal<C>) -> JoinHandle<!> {
+    tokio::spawn(async move {
+        let mut was_healthy = true;
+        loop {
+            tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+
+            let is_healthy = matches!(timeout(HEALTH_CHECK_TIMEOUT, db.health()).await, Ok(Ok(())));
+            match (was_healthy, is_healthy) {
+                (true, false) => {
+                    warn!("lost connection to the database! waiting for it to come back...")
+                }
+                (false, true) => info!("connection to the database is back ^-^"),
+                _ => (),
+            }
+            was_healthy = is_healthy;
+        }
+    })
+}
 
 #[async_trait]
 pub trait DbItem<C: Connection>: Serialize + DeserializeOwned {
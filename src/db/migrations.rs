@@ -0,0 +1,418 @@
+use log::{info, warn};
+use surrealdb::{Connection, Surreal};
+
+const SCHEMA_MIGRATION_TABLE: &str = "schema_migration";
+
+/// A versioned change to the database schema. Migrations are applied in order
+/// and only once, so once a migration has been released it should never be
+/// changed; add a new one instead.
+struct Migration {
+    version: u32,
+    description: &'static str,
+    query: &'static str,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "define tables",
+        query: "
+            DEFINE TABLE IF NOT EXISTS guild_wallet SCHEMALESS;
+            DEFINE FIELD IF NOT EXISTS guild_id ON guild_wallet TYPE string;
+            DEFINE FIELD IF NOT EXISTS user_id ON guild_wallet TYPE string;
+            DEFINE FIELD IF NOT EXISTS balance ON guild_wallet TYPE int;
+
+            DEFINE TABLE IF NOT EXISTS guild_payout SCHEMALESS;
+            DEFINE FIELD IF NOT EXISTS guild_id ON guild_payout TYPE string;
+            DEFINE FIELD IF NOT EXISTS user_id ON guild_payout TYPE string;
+            DEFINE FIELD IF NOT EXISTS balance ON guild_payout TYPE int;
+
+            DEFINE TABLE IF NOT EXISTS quote SCHEMALESS;
+            DEFINE TABLE IF NOT EXISTS autodelete_timer SCHEMALESS;
+            DEFINE TABLE IF NOT EXISTS logging_channel SCHEMALESS;
+            DEFINE TABLE IF NOT EXISTS twitch_joined_channel SCHEMALESS;
+        ",
+    },
+    Migration {
+        version: 2,
+        description: "number quotes and index them for searching",
+        // quotes used to be numbered by their position when ordered by creation
+        // time, so old quotes are numbered the same way
+        query: "
+            FOR $quote IN (
+                SELECT id, created_at FROM quote
+                WHERE type::is::none(number)
+                ORDER BY created_at
+            ) {
+                UPDATE $quote.id SET number =
+                    (math::max(SELECT VALUE number FROM quote WHERE type::is::number(number)) ?? 0) + 1;
+            };
+            DEFINE FIELD IF NOT EXISTS number ON quote TYPE int;
+            DEFINE INDEX IF NOT EXISTS quote_number ON quote FIELDS number UNIQUE;
+
+            DEFINE ANALYZER IF NOT EXISTS quote_analyzer
+                TOKENIZERS class FILTERS lowercase, ascii, snowball(english);
+            DEFINE INDEX IF NOT EXISTS quote_text_search ON quote
+                FIELDS quote SEARCH ANALYZER quote_analyzer BM25;
+            DEFINE INDEX IF NOT EXISTS quote_category_search ON quote
+                FIELDS stream_category SEARCH ANALYZER quote_analyzer BM25;
+            DEFINE INDEX IF NOT EXISTS quote_title_search ON quote
+                FIELDS stream_title SEARCH ANALYZER quote_analyzer BM25;
+        ",
+    },
+    Migration {
+        version: 3,
+        description: "merge duplicate wallets and payouts, and keep them unique",
+        // concurrent commands used to be able to create more than one wallet
+        // (or payout) for the same user, so their balances are combined
+        query: "
+            FOR $wallets IN (
+                SELECT guild_id, user_id, array::group(id) AS ids, math::sum(balance) AS balance
+                FROM guild_wallet
+                GROUP BY guild_id, user_id
+            ) {
+                IF array::len($wallets.ids) > 1 {
+                    UPDATE array::first($wallets.ids) SET balance = $wallets.balance;
+                    DELETE array::slice($wallets.ids, 1);
+                };
+            };
+            DEFINE INDEX IF NOT EXISTS guild_wallet_owner ON guild_wallet
+                FIELDS guild_id, user_id UNIQUE;
+
+            FOR $payouts IN (
+                SELECT guild_id, user_id, array::group(id) AS ids, math::sum(balance) AS balance
+                FROM guild_payout
+                GROUP BY guild_id, user_id
+            ) {
+                IF array::len($payouts.ids) > 1 {
+                    UPDATE array::first($payouts.ids) SET balance = $payouts.balance;
+                    DELETE array::slice($payouts.ids, 1);
+                };
+            };
+            DEFINE INDEX IF NOT EXISTS guild_payout_owner ON guild_payout
+                FIELDS guild_id, user_id UNIQUE;
+        ",
+    },
+    Migration {
+        version: 4,
+        description: "add the economy ledger",
+        query: "
+            DEFINE TABLE IF NOT EXISTS ledger_entry SCHEMALESS;
+            DEFINE FIELD IF NOT EXISTS guild_id ON ledger_entry TYPE string;
+            DEFINE FIELD IF NOT EXISTS user_id ON ledger_entry TYPE string;
+            DEFINE FIELD IF NOT EXISTS amount ON ledger_entry TYPE int;
+            DEFINE FIELD IF NOT EXISTS kind ON ledger_entry TYPE string;
+            DEFINE FIELD IF NOT EXISTS created_at ON ledger_entry TYPE datetime
+                DEFAULT time::now();
+            DEFINE INDEX IF NOT EXISTS ledger_entry_owner ON ledger_entry
+                FIELDS guild_id, user_id;
+        ",
+    },
+    Migration {
+        version: 5,
+        description: "add economy settings",
+        query: "
+            DEFINE TABLE IF NOT EXISTS guild_economy_settings SCHEMALESS;
+        ",
+    },
+    Migration {
+        version: 6,
+        description: "add the shop and inventories",
+        query: "
+            DEFINE TABLE IF NOT EXISTS shop_item SCHEMALESS;
+            DEFINE FIELD IF NOT EXISTS guild_id ON shop_item TYPE string;
+            DEFINE FIELD IF NOT EXISTS name ON shop_item TYPE string;
+            DEFINE FIELD IF NOT EXISTS price ON shop_item TYPE int;
+            DEFINE INDEX IF NOT EXISTS shop_item_guild ON shop_item FIELDS guild_id;
+
+            DEFINE TABLE IF NOT EXISTS inventory_item SCHEMALESS;
+            DEFINE FIELD IF NOT EXISTS guild_id ON inventory_item TYPE string;
+            DEFINE FIELD IF NOT EXISTS user_id ON inventory_item TYPE string;
+            DEFINE FIELD IF NOT EXISTS purchased_at ON inventory_item TYPE datetime
+                DEFAULT time::now();
+            DEFINE INDEX IF NOT EXISTS inventory_item_owner ON inventory_item
+                FIELDS guild_id, user_id;
+        ",
+    },
+    Migration {
+        version: 7,
+        description: "add links between discord and twitch accounts",
+        query: "
+            DEFINE TABLE IF NOT EXISTS account_link SCHEMALESS;
+            DEFINE FIELD IF NOT EXISTS discord_user_id ON account_link TYPE string;
+            DEFINE FIELD IF NOT EXISTS twitch_user_id ON account_link TYPE string;
+            DEFINE INDEX IF NOT EXISTS account_link_discord ON account_link
+                FIELDS discord_user_id UNIQUE;
+            DEFINE INDEX IF NOT EXISTS account_link_twitch ON account_link
+                FIELDS twitch_user_id UNIQUE;
+        ",
+    },
+    Migration {
+        version: 8,
+        description: "add one-time codes for linking accounts",
+        query: "
+            DEFINE TABLE IF NOT EXISTS account_link_code SCHEMALESS;
+            DEFINE FIELD IF NOT EXISTS code ON account_link_code TYPE string;
+            DEFINE FIELD IF NOT EXISTS discord_user_id ON account_link_code TYPE string;
+            DEFINE FIELD IF NOT EXISTS created_at ON account_link_code TYPE datetime
+                DEFAULT time::now();
+            DEFINE INDEX IF NOT EXISTS account_link_code_code ON account_link_code
+                FIELDS code UNIQUE;
+        ",
+    },
+    Migration {
+        version: 9,
+        description: "move autoban rules into the database",
+        query: "
+            DEFINE TABLE IF NOT EXISTS autoban_rule SCHEMALESS;
+            DEFINE INDEX IF NOT EXISTS autoban_rule_channel ON autoban_rule FIELDS channel;
+            CREATE autoban_rule CONTENT {
+                kind: 'name_contains',
+                pattern: 'isapred',
+                action: { type: 'ban' },
+                reason: 'user is suspected of harassment',
+            };
+            CREATE autoban_rule CONTENT {
+                kind: 'name_contains',
+                pattern: 'isabadstreamer',
+                action: { type: 'ban' },
+                reason: 'user is suspected of harassment',
+            };
+            CREATE autoban_rule CONTENT {
+                kind: 'message_contains',
+                pattern: 'cheap viewers on',
+                action: { type: 'ban' },
+                reason: 'likely viewer scam bot',
+            };
+            CREATE autoban_rule CONTENT {
+                kind: 'message_contains',
+                pattern: 'best viewers on',
+                action: { type: 'ban' },
+                reason: 'likely viewer scam bot',
+            };
+        ",
+    },
+    Migration {
+        version: 10,
+        description: "add a gentler autoban rule for possible scam messages",
+        query: "
+            CREATE autoban_rule CONTENT {
+                kind: 'message_regex',
+                pattern: '(buy|cheap)\\\\s+(viewers|followers|primes)',
+                action: { type: 'timeout', duration: { secs: 600, nanos: 0 } },
+                delete_message: true,
+                reason: 'possible viewer scam bot',
+            };
+        ",
+    },
+    Migration {
+        version: 11,
+        description: "add a record of moderation actions taken on twitch",
+        query: "
+            DEFINE TABLE IF NOT EXISTS twitch_moderation_record SCHEMALESS;
+            DEFINE FIELD IF NOT EXISTS created_at ON twitch_moderation_record TYPE datetime
+                DEFAULT time::now();
+            DEFINE INDEX IF NOT EXISTS twitch_moderation_record_number
+                ON twitch_moderation_record FIELDS channel, number UNIQUE;
+        ",
+    },
+    Migration {
+        version: 12,
+        description: "add saved twitch tokens",
+        query: "
+            DEFINE TABLE IF NOT EXISTS twitch_token SCHEMALESS;
+        ",
+    },
+    Migration {
+        version: 13,
+        description: "add saved content warnings for twitch streams",
+        query: "
+            DEFINE TABLE IF NOT EXISTS content_warning SCHEMALESS;
+        ",
+    },
+    Migration {
+        version: 14,
+        description: "add custom commands",
+        query: "
+            DEFINE TABLE IF NOT EXISTS custom_command SCHEMALESS;
+            DEFINE INDEX IF NOT EXISTS custom_command_name ON custom_command
+                FIELDS channel, name UNIQUE;
+        ",
+    },
+    Migration {
+        version: 15,
+        description: "count quote numbers so deleted numbers aren't reused",
+        query: "
+            UPSERT counter:quote SET value =
+                math::max(SELECT VALUE number FROM quote WHERE type::is::number(number)) ?? 0;
+        ",
+    },
+    Migration {
+        version: 16,
+        description: "let channels turn off autoban rules that apply everywhere",
+        query: "
+            DEFINE TABLE IF NOT EXISTS autoban_disabled_rule SCHEMALESS;
+            DEFINE INDEX IF NOT EXISTS autoban_disabled_rule_channel ON autoban_disabled_rule
+                FIELDS channel;
+        ",
+    },
+    Migration {
+        version: 17,
+        description: "only time out possible scam messages that link somewhere",
+        // the old pattern also caught people just talking about buying
+        // followers. scam messages always have a domain in them.
+        query: "
+            LET $old = '(buy|cheap)\\\\s+(viewers|followers|primes)';
+            LET $new = '(buy|cheap)\\\\s+(viewers|followers|primes)\\\\b.*?[a-z0-9-]+\\\\.[a-z]{2,}';
+            UPDATE autoban_rule SET pattern = $new
+                WHERE type::is::none(channel) AND pattern = $old;
+            FOR $disabled IN (SELECT * FROM autoban_disabled_rule WHERE pattern = $old) {
+                DELETE $disabled.id;
+                UPSERT type::thing('autoban_disabled_rule', [$disabled.channel, $new])
+                    SET channel = $disabled.channel, pattern = $new;
+            };
+        ",
+    },
+];
+
+/// Brings the database schema up to date, applying every migration that
+/// hasn't been applied yet. Each migration is applied in its own transaction
+/// along with a record of its version, so a failed migration leaves the
+/// database as it was.
+pub async fn run<C: Connection>(db: &Surreal<C>) -> Result<(), surrealdb::Error> {
+    let current_version = schema_version(db).await?;
+    let latest_version = MIGRATIONS.last().map(|m| m.version).unwrap_or(0);
+
+    if current_version > latest_version {
+        warn!(
+            "database schema is at version {current_version}, but i only know about versions up to {latest_version}. was the database used by a newer munibot?"
+        );
+        return Ok(());
+    }
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current_version) {
+        info!(
+            "migrating database to version {}: {}",
+            migration.version, migration.description
+        );
+
+        db.query("BEGIN TRANSACTION;")
+            .query(migration.query)
+            .query(format!(
+                "CREATE type::thing('{SCHEMA_MIGRATION_TABLE}', $version) CONTENT {{
+                    version: $version,
+                    description: $description,
+                    applied_at: time::now(),
+                }};"
+            ))
+            .query("COMMIT TRANSACTION;")
+            .bind(("version", migration.version))
+            .bind(("description", migration.description))
+            .await?
+            .check()?;
+    }
+
+    Ok(())
+}
+
+/// Returns the version of the latest migration applied to the database, or 0
+/// if none have been applied.
+pub async fn schema_version<C: Connection>(db: &Surreal<C>) -> Result<u32, surrealdb::Error> {
+    Ok(db
+        .query(format!(
+            "RETURN math::max(SELECT VALUE version FROM {SCHEMA_MIGRATION_TABLE}) ?? 0;"
+        ))
+        .await?
+        .take::<Option<u32>>(0)?
+        .unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{run, schema_version, MIGRATIONS};
+    use crate::db::empty_test_db;
+
+    #[test]
+    fn test_migration_versions_are_sequential() {
+        for (i, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, i + 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_migrations_only_run_once() {
+        let db = empty_test_db().await;
+        assert_eq!(schema_version(&db).await.unwrap(), 0);
+
+        run(&db).await.unwrap();
+        run(&db).await.unwrap();
+        assert_eq!(
+            schema_version(&db).await.unwrap(),
+            MIGRATIONS.last().unwrap().version
+        );
+    }
+
+    #[tokio::test]
+    async fn test_old_quotes_are_numbered() {
+        let db = empty_test_db().await;
+        db.query(
+            "CREATE quote CONTENT { quote: 'newer', created_at: d'2024-02-01T00:00:00Z' };
+             CREATE quote CONTENT { quote: 'older', created_at: d'2024-01-01T00:00:00Z' };",
+        )
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+        run(&db).await.unwrap();
+
+        let numbers: Vec<u32> = db
+            .query("SELECT number, created_at FROM quote ORDER BY created_at;")
+            .await
+            .unwrap()
+            .take((0, "number"))
+            .unwrap();
+        assert_eq!(numbers, vec![1, 2]);
+
+        // new quotes carry on from there
+        let counter: Option<u32> = db
+            .query("SELECT VALUE value FROM ONLY counter:quote;")
+            .await
+            .unwrap()
+            .take(0)
+            .unwrap();
+        assert_eq!(counter, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_wallets_are_merged() {
+        let db = empty_test_db().await;
+        db.query(
+            "CREATE guild_wallet CONTENT { guild_id: '1', user_id: '2', balance: 10 };
+             CREATE guild_wallet CONTENT { guild_id: '1', user_id: '2', balance: 5 };
+             CREATE guild_wallet CONTENT { guild_id: '1', user_id: '3', balance: 7 };",
+        )
+        .await
+        .unwrap()
+        .check()
+        .unwrap();
+
+        run(&db).await.unwrap();
+
+        let balances: Vec<u64> = db
+            .query("SELECT balance, user_id FROM guild_wallet ORDER BY user_id;")
+            .await
+            .unwrap()
+            .take((0, "balance"))
+            .unwrap();
+        assert_eq!(balances, vec![15, 7]);
+
+        // no more duplicates can be made
+        assert!(db
+            .query("CREATE guild_wallet CONTENT { guild_id: '1', user_id: '3', balance: 0 };")
+            .await
+            .unwrap()
+            .check()
+            .is_err());
+    }
+}
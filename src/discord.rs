@@ -2,6 +2,8 @@ pub mod admin;
 pub mod autodelete;
 pub mod commands;
 pub mod handler;
+pub mod pagination;
+pub mod relay;
 pub mod simple;
 pub mod state;
 pub mod utils;
@@ -10,7 +12,6 @@ pub mod vc_greeter;
 use std::{env, sync::Arc};
 
 use autodelete::AutoDeleteHandler;
-use dotenvy::dotenv;
 use log::{error, info};
 use poise::{
     samples::register_globally,
@@ -18,10 +19,23 @@ use poise::{
     Prefix, PrefixFrameworkOptions,
 };
 use state::DiscordState;
-use surrealdb::{engine::remote::ws, opt::auth::Database, Surreal};
 
-use self::{admin::AdminCommandProvider, commands::DiscordCommandProvider};
-use crate::{config::Config, handlers::DiscordMessageHandlerCollection, MuniBotError};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+use self::{
+    admin::AdminCommandProvider, commands::DiscordCommandProvider, relay::DiscordRelayMessage,
+};
+use crate::{
+    config::Config,
+    db::MuniBotDb,
+    handlers::{
+        autoban::audit::{self, ModerationNotice},
+        economy::shop,
+        DiscordMessageHandlerCollection,
+    },
+    twitch::channels::ChannelMembershipRequest,
+    MuniBotError,
+};
 
 pub type DiscordCommand = poise::Command<DiscordState, MuniBotError>;
 pub type DiscordContext<'a> = poise::Context<'a, DiscordState, MuniBotError>;
@@ -31,23 +45,11 @@ pub async fn start_discord_integration(
     handlers: DiscordMessageHandlerCollection,
     command_providers: Vec<Box<dyn DiscordCommandProvider>>,
     config: Config,
+    db: MuniBotDb,
+    twitch_membership: UnboundedSender<ChannelMembershipRequest>,
+    moderation_notices: UnboundedReceiver<ModerationNotice>,
+    relay_messages: UnboundedReceiver<DiscordRelayMessage>,
 ) {
-    dotenv().ok();
-
-    // login to the database first
-    let database_url = config.db.url.clone();
-    let db = Surreal::new::<ws::Ws>(&database_url)
-        .await
-        .expect("couldn't connect to database");
-    db.signin(Database {
-        namespace: "muni_bot",
-        database: "muni_bot",
-        username: &config.db.user,
-        password: &std::env::var("DATABASE_PASS").expect("expected DATABASE_PASS to be set"),
-    })
-    .await
-    .expect("couldn't log in to database");
-
     let mut commands: Vec<DiscordCommand> = command_providers
         .iter()
         .flat_map(|provider| provider.commands())
@@ -84,6 +86,9 @@ pub async fn start_discord_integration(
                 handlers,
                 config,
                 Arc::new(db),
+                twitch_membership,
+                moderation_notices,
+                relay_messages,
             ))
         })
         .options(options)
@@ -103,13 +108,17 @@ pub async fn start_discord_integration(
     client.start().await.unwrap();
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn on_ready(
     ctx: &serenity::Context,
     ready: &serenity::Ready,
     framework: &poise::Framework<DiscordState, MuniBotError>,
     handlers: DiscordMessageHandlerCollection,
     config: Config,
-    db: Arc<Surreal<ws::Client>>,
+    db: Arc<MuniBotDb>,
+    twitch_membership: UnboundedSender<ChannelMembershipRequest>,
+    moderation_notices: UnboundedReceiver<ModerationNotice>,
+    relay_messages: UnboundedReceiver<DiscordRelayMessage>,
 ) -> Result<DiscordState, MuniBotError> {
     register_globally(ctx, &framework.options().commands)
         .await
@@ -119,12 +128,28 @@ async fn on_ready(
 
     info!("discord: logged in as {}", ready.user.name);
 
-    let new_state =
-        DiscordState::new(handlers, &config, db, ctx.http.clone(), ctx.cache.clone()).await?;
+    let new_state = DiscordState::new(
+        handlers,
+        &config,
+        db,
+        ctx.http.clone(),
+        ctx.cache.clone(),
+        twitch_membership,
+    )
+    .await?;
 
     // start the autodeletion handler
     AutoDeleteHandler::start(new_state.autodeletion().clone());
 
+    // take away temporary roles bought from the shop once they run out
+    shop::start_role_expiry(new_state.access().clone());
+
+    // post what the twitch bot does to chatters in the servers' logging channels
+    audit::start_moderation_log_mirror(new_state.logging().clone(), moderation_notices);
+
+    // post messages the twitch bot sends our way
+    relay::start_relay(ctx.http.clone(), relay_messages);
+
     Ok(new_state)
 }
 
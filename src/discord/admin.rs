@@ -7,7 +7,14 @@ use super::{
     autodelete::AutoDeleteHandler, DiscordCommand, DiscordCommandProvider, DiscordContext,
 };
 use crate::{
-    db::DbItem, discord::autodelete::AutoDeleteMode, handlers::logging::LoggingChannel,
+    db::DbItem,
+    discord::autodelete::AutoDeleteMode,
+    handlers::{
+        autoban::admin::autoban,
+        economy::admin::{economy, shop},
+        logging::LoggingChannel,
+    },
+    twitch::channels::{self, ChannelMembershipRequest, JoinedChannel},
     MuniBotError,
 };
 
@@ -24,7 +31,17 @@ impl DiscordCommandProvider for AdminCommandProvider {
     hide_in_help,
     required_permissions = "MANAGE_GUILD",
     subcommand_required,
-    subcommands("set_log_channel", "stop_logging", "set_autodelete", "stop_autodelete"),
+    subcommands(
+        "set_log_channel",
+        "stop_logging",
+        "set_autodelete",
+        "stop_autodelete",
+        "twitch_join",
+        "twitch_part",
+        "economy",
+        "shop",
+        "autoban"
+    ),
     ephemeral
 )]
 async fn admin(ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
@@ -218,3 +235,86 @@ async fn stop_autodelete(ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
     ctx.send(reply).await?;
     Ok(())
 }
+
+/// have munibot join a twitch channel. only munibot's owners can use this.
+#[poise::command(
+    rename = "twitch-join",
+    slash_command,
+    hide_in_help,
+    owners_only,
+    ephemeral
+)]
+async fn twitch_join(
+    ctx: DiscordContext<'_>,
+
+    #[description = "the login name of the twitch channel to join"] channel: String,
+) -> Result<(), MuniBotError> {
+    let channel_login = channel.trim().trim_start_matches('@').to_lowercase();
+    if !channels::is_valid_login(&channel_login) {
+        let reply = CreateReply::default().ephemeral(true).content(format!(
+            "**{channel_login}** can't be a twitch login. they're 3 to 25 letters, numbers and underscores."
+        ));
+        ctx.send(reply).await?;
+        return Ok(());
+    }
+
+    // save the channel so it's rejoined on restart
+    let joined = JoinedChannel::new(&channel_login);
+    joined
+        .upsert_in_db(ctx.data().access().db(), joined.clone())
+        .await?;
+
+    let reply_content = if ctx
+        .data()
+        .twitch_membership()
+        .send(ChannelMembershipRequest::Join(channel_login.clone()))
+        .is_ok()
+    {
+        format!("okay! joining **{channel_login}** on twitch now.")
+    } else {
+        format!("my twitch integration isn't running right now, but i'll join **{channel_login}** next time it starts.")
+    };
+
+    let reply = CreateReply::default()
+        .ephemeral(true)
+        .content(reply_content);
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+/// have munibot leave a twitch channel. only munibot's owners can use this.
+#[poise::command(
+    rename = "twitch-part",
+    slash_command,
+    hide_in_help,
+    owners_only,
+    ephemeral
+)]
+async fn twitch_part(
+    ctx: DiscordContext<'_>,
+
+    #[description = "the login name of the twitch channel to leave"] channel: String,
+) -> Result<(), MuniBotError> {
+    let channel_login = channel.trim().trim_start_matches('@').to_lowercase();
+
+    JoinedChannel::new(&channel_login)
+        .delete_from_db(ctx.data().access().db())
+        .await?;
+
+    let reply_content = if ctx
+        .data()
+        .twitch_membership()
+        .send(ChannelMembershipRequest::Part(channel_login.clone()))
+        .is_ok()
+    {
+        format!("done! i've left **{channel_login}** on twitch.")
+    } else {
+        format!("my twitch integration isn't running right now, but i won't join **{channel_login}** next time it starts.")
+    };
+
+    let reply = CreateReply::default()
+        .ephemeral(true)
+        .content(reply_content);
+    ctx.send(reply).await?;
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use std::time::Duration;
+
+use poise::{
+    serenity_prelude::{
+        self as serenity, ComponentInteractionCollector, CreateActionRow, CreateButton,
+        CreateEmbed, CreateEmbedFooter, CreateInteractionResponse,
+        CreateInteractionResponseMessage,
+    },
+    CreateReply,
+};
+
+use super::DiscordContext;
+
+/// how long to wait for a page button to be pressed before giving up.
+const PAGINATION_TIMEOUT: Duration = Duration::from_mins(10);
+
+/// sends the given embeds as pages, with buttons to flip between them. this
+/// only returns once the buttons haven't been pressed for a while, at which
+/// point the buttons are removed.
+pub async fn paginate_embeds(
+    ctx: DiscordContext<'_>,
+    pages: Vec<CreateEmbed>,
+) -> Result<(), serenity::Error> {
+    let page_count = pages.len();
+    let pages: Vec<CreateEmbed> = pages
+        .into_iter()
+        .enumerate()
+        .map(|(i, page)| {
+            page.footer(CreateEmbedFooter::new(format!(
+                "page {} of {page_count}",
+                i + 1
+            )))
+        })
+        .collect();
+
+    let Some(first_page) = pages.first() else {
+        return Ok(());
+    };
+
+    // a single page doesn't need any buttons
+    if page_count == 1 {
+        ctx.send(CreateReply::default().embed(first_page.clone()))
+            .await?;
+        return Ok(());
+    }
+
+    // button ids are prefixed with the context id so that we only listen to our
+    // own buttons
+    let ctx_id = ctx.id();
+    let prev_button_id = format!("{ctx_id}prev");
+    let next_button_id = format!("{ctx_id}next");
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(&prev_button_id).emoji('◀'),
+        CreateButton::new(&next_button_id).emoji('▶'),
+    ]);
+
+    let handle = ctx
+        .send(
+            CreateReply::default()
+                .embed(first_page.clone())
+                .components(vec![buttons]),
+        )
+        .await?;
+
+    let mut current_page = 0;
+    while let Some(press) = ComponentInteractionCollector::new(ctx)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(PAGINATION_TIMEOUT)
+        .await
+    {
+        if press.data.custom_id == next_button_id {
+            current_page = (current_page + 1) % page_count;
+        } else if press.data.custom_id == prev_button_id {
+            current_page = current_page.checked_sub(1).unwrap_or(page_count - 1);
+        } else {
+            continue;
+        }
+
+        press
+            .create_response(
+                ctx.serenity_context(),
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new().embed(pages[current_page].clone()),
+                ),
+            )
+            .await?;
+    }
+
+    // remove the buttons now that nobody's listening to them
+    handle
+        .edit(
+            ctx,
+            CreateReply::default()
+                .embed(pages[current_page].clone())
+                .components(vec![]),
+        )
+        .await?;
+
+    Ok(())
+}
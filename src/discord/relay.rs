@@ -0,0 +1,35 @@
+use std::sync::Arc;
+
+use log::error;
+use poise::serenity_prelude::{ChannelId, CreateAllowedMentions, CreateMessage, Http};
+use tokio::{sync::mpsc::UnboundedReceiver, task::JoinHandle};
+
+/// A message another integration wants posted in a Discord channel.
+#[derive(Clone, Debug)]
+pub struct DiscordRelayMessage {
+    pub channel_id: ChannelId,
+    pub content: String,
+}
+
+/// Posts messages sent from other integrations (like Twitch) to the Discord
+/// channels they're meant for. Their content often comes from viewers, so
+/// nothing in them is allowed to ping anyone.
+pub fn start_relay(
+    http: Arc<Http>,
+    mut messages: UnboundedReceiver<DiscordRelayMessage>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(DiscordRelayMessage {
+            channel_id,
+            content,
+        }) = messages.recv().await
+        {
+            let message = CreateMessage::new()
+                .content(content)
+                .allowed_mentions(CreateAllowedMentions::new());
+            if let Err(e) = channel_id.send_message(&http, message).await {
+                error!("couldn't relay a message to discord channel {channel_id}: {e}");
+            }
+        }
+    })
+}
@@ -1,29 +1,30 @@
 use std::sync::Arc;
 
 use poise::serenity_prelude::{Cache, Http, Result};
-use surrealdb::{engine::remote::ws, Surreal};
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc::UnboundedSender, Mutex};
 
 use super::{autodelete::AutoDeleteHandler, handler::DiscordEventHandler};
 use crate::{
-    config::{Config, DiscordConfig},
+    config::{Config, DiscordConfig, TwitchConfig},
+    db::MuniBotDb,
     handlers::{logging::LoggingHandler, DiscordMessageHandlerCollection},
+    twitch::channels::ChannelMembershipRequest,
     MuniBotError,
 };
 
 #[derive(Clone, Debug)]
 pub struct GlobalAccess {
-    db: Arc<Surreal<ws::Client>>,
+    db: Arc<MuniBotDb>,
     http: Arc<Http>,
     cache: Arc<Cache>,
 }
 
 impl GlobalAccess {
-    pub fn new(http: Arc<Http>, cache: Arc<Cache>, db: Arc<Surreal<ws::Client>>) -> Self {
+    pub fn new(http: Arc<Http>, cache: Arc<Cache>, db: Arc<MuniBotDb>) -> Self {
         Self { http, cache, db }
     }
 
-    pub fn db(&self) -> &Surreal<ws::Client> {
+    pub fn db(&self) -> &MuniBotDb {
         &self.db
     }
 
@@ -42,11 +43,15 @@ impl GlobalAccess {
 
 pub struct DiscordState {
     pub config: DiscordConfig,
+    twitch_config: TwitchConfig,
     handlers: DiscordMessageHandlerCollection,
     access: GlobalAccess,
 
     logging: Arc<Mutex<LoggingHandler>>,
     autodeletion: Arc<Mutex<AutoDeleteHandler>>,
+
+    /// used to ask the twitch integration to join or leave channels.
+    twitch_membership: UnboundedSender<ChannelMembershipRequest>,
 }
 impl DiscordState {
     /// creates a new `DiscordState` struct. the `LoggingHandler` and
@@ -54,9 +59,10 @@ impl DiscordState {
     pub async fn new(
         mut handlers: DiscordMessageHandlerCollection,
         config: &Config,
-        db: Arc<Surreal<ws::Client>>,
+        db: Arc<MuniBotDb>,
         http: Arc<Http>,
         cache: Arc<Cache>,
+        twitch_membership: UnboundedSender<ChannelMembershipRequest>,
     ) -> Result<Self, MuniBotError> {
         let global_access = GlobalAccess { db, http, cache };
 
@@ -73,9 +79,11 @@ impl DiscordState {
         Ok(Self {
             handlers,
             config: config.discord.clone(),
+            twitch_config: config.twitch.clone(),
             access: global_access,
             logging,
             autodeletion,
+            twitch_membership,
         })
     }
 
@@ -94,4 +102,12 @@ impl DiscordState {
     pub fn autodeletion(&self) -> &Arc<Mutex<AutoDeleteHandler>> {
         &self.autodeletion
     }
+
+    pub fn twitch_config(&self) -> &TwitchConfig {
+        &self.twitch_config
+    }
+
+    pub fn twitch_membership(&self) -> &UnboundedSender<ChannelMembershipRequest> {
+        &self.twitch_membership
+    }
 }
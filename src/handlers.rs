@@ -4,14 +4,16 @@ use tokio::sync::Mutex;
 
 use crate::{
     discord::{commands::DiscordCommandProvider, handler::DiscordEventHandler},
-    twitch::handler::TwitchMessageHandler,
+    twitch::handler::{TwitchEventHandler, TwitchMessageHandler},
 };
 
+pub mod account_link;
 pub mod affection;
 pub mod autoban;
 pub mod bonk;
 pub mod bot_affection;
 pub mod content_warning;
+pub mod custom_commands;
 pub mod dice;
 pub mod economy;
 pub mod eight_ball;
@@ -21,11 +23,13 @@ pub mod logging;
 pub mod lurk;
 pub mod magical;
 pub mod quotes;
+pub mod redemptions;
 pub mod shoutout;
 pub mod socials;
 pub mod temperature;
 pub mod ventriloquize;
 
 pub type TwitchHandlerCollection = Vec<Box<dyn TwitchMessageHandler>>;
+pub type TwitchEventHandlerCollection = Vec<Box<dyn TwitchEventHandler>>;
 pub type DiscordMessageHandlerCollection = Vec<Arc<Mutex<dyn DiscordEventHandler>>>;
 pub type DiscordCommandProviderCollection = Vec<Box<dyn DiscordCommandProvider>>;
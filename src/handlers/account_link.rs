@@ -0,0 +1,390 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use poise::serenity_prelude::{MessageBuilder, UserId};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+use surrealdb::{Connection, Surreal};
+use twitch_irc::message::{PrivmsgMessage, ServerMessage};
+
+use crate::{
+    config::Config,
+    db::MuniBotDb,
+    discord::{commands::DiscordCommandProvider, DiscordCommand, DiscordContext},
+    handlers::economy::{
+        ledger::LEDGER_TABLE, owner::WalletOwner, payout::GUILD_PAYOUT_TABLE,
+        wallet::GUILD_WALLET_TABLE,
+    },
+    twitch::{
+        agent::TwitchAgent,
+        bot::MuniBotTwitchIRCClient,
+        commands::TwitchCommand,
+        handler::{TwitchHandlerError, TwitchMessageHandler},
+    },
+    MuniBotError,
+};
+
+pub const ACCOUNT_LINK_TABLE: &str = "account_link";
+const LINK_CODE_TABLE: &str = "account_link_code";
+
+/// How long a link code can be used for.
+const LINK_CODE_LIFETIME: Duration = Duration::from_mins(10);
+
+/// The characters link codes are made of. Characters that look alike (like 0
+/// and O) are left out.
+const LINK_CODE_CHARS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// How many characters are in a link code.
+const LINK_CODE_LENGTH: usize = 6;
+
+/// A Discord account and a Twitch account that belong to the same person.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AccountLink {
+    pub discord_user_id: UserId,
+    pub twitch_user_id: String,
+
+    /// The Twitch account's login at the time it was linked.
+    pub twitch_login: String,
+}
+
+impl AccountLink {
+    /// Returns the link for a Twitch account, if it's linked to a Discord
+    /// account.
+    pub async fn get_by_twitch_id<C: Connection>(
+        db: &Surreal<C>,
+        twitch_user_id: &str,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        db.query(format!(
+            "SELECT * FROM {ACCOUNT_LINK_TABLE} WHERE twitch_user_id = $twitch_user_id;"
+        ))
+        .bind(("twitch_user_id", twitch_user_id.to_string()))
+        .await?
+        .take(0)
+    }
+
+    /// Returns the link for a Discord account, if it's linked to a Twitch
+    /// account.
+    pub async fn get_by_discord_id<C: Connection>(
+        db: &Surreal<C>,
+        discord_user_id: UserId,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        db.query(format!(
+            "SELECT * FROM {ACCOUNT_LINK_TABLE} WHERE discord_user_id = $discord_user_id;"
+        ))
+        .bind(("discord_user_id", discord_user_id))
+        .await?
+        .take(0)
+    }
+
+    /// Removes the link for a Discord account, returning it if there was one.
+    pub async fn remove<C: Connection>(
+        db: &Surreal<C>,
+        discord_user_id: UserId,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        db.query(format!(
+            "DELETE {ACCOUNT_LINK_TABLE}
+             WHERE discord_user_id = $discord_user_id
+             RETURN BEFORE;"
+        ))
+        .bind(("discord_user_id", discord_user_id))
+        .await?
+        .take::<Vec<Self>>(0)
+        .map(|links| links.into_iter().next())
+    }
+
+    /// Creates a new one-time code that links the given Discord account to
+    /// whichever Twitch account sends it in chat. Any older codes for the
+    /// Discord account stop working.
+    pub async fn create_code<C: Connection>(
+        db: &Surreal<C>,
+        discord_user_id: UserId,
+    ) -> Result<String, surrealdb::Error> {
+        let code = {
+            let mut rng = rand::thread_rng();
+            (0..LINK_CODE_LENGTH)
+                .map(|_| *LINK_CODE_CHARS.choose(&mut rng).unwrap() as char)
+                .collect::<String>()
+        };
+
+        db.query(format!(
+            "BEGIN TRANSACTION;
+             DELETE {LINK_CODE_TABLE} WHERE discord_user_id = $discord_user_id;
+             CREATE {LINK_CODE_TABLE} SET code = $code, discord_user_id = $discord_user_id;
+             COMMIT TRANSACTION;"
+        ))
+        .bind(("discord_user_id", discord_user_id))
+        .bind(("code", code.clone()))
+        .await?
+        .check()?;
+
+        Ok(code)
+    }
+
+    /// Uses a link code to link a Twitch account to the Discord account that
+    /// created the code, replacing any links either account already had.
+    /// Coins the Twitch account earned on its own are moved to the Discord
+    /// account. Returns the new link, or `None` if the code doesn't exist or
+    /// has expired.
+    pub async fn redeem_code<C: Connection>(
+        db: &Surreal<C>,
+        code: &str,
+        twitch_user_id: &str,
+        twitch_login: &str,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        let discord_user_id: Option<UserId> = db
+            .query(format!(
+                "BEGIN TRANSACTION;
+                 LET $pending = (
+                     DELETE {LINK_CODE_TABLE}
+                     WHERE code = $code AND created_at > time::now() - {lifetime}s
+                     RETURN BEFORE
+                 )[0];
+                 IF $pending {{
+                     LET $discord_owner = $pending.discord_user_id;
+                     DELETE {ACCOUNT_LINK_TABLE}
+                     WHERE discord_user_id = $discord_owner OR twitch_user_id = $twitch_user_id;
+                     CREATE {ACCOUNT_LINK_TABLE} SET
+                         discord_user_id = $discord_owner,
+                         twitch_user_id = $twitch_user_id,
+                         twitch_login = $twitch_login;
+                     {merge_wallets}
+                     {merge_payouts}
+                     UPDATE {LEDGER_TABLE} SET user_id = $discord_owner WHERE user_id = $twitch_owner;
+                 }};
+                 RETURN $pending.discord_user_id;
+                 COMMIT TRANSACTION;",
+                lifetime = LINK_CODE_LIFETIME.as_secs(),
+                merge_wallets = merge_balances(GUILD_WALLET_TABLE),
+                merge_payouts = merge_balances(GUILD_PAYOUT_TABLE),
+            ))
+            .bind(("code", code.trim().to_uppercase()))
+            .bind(("twitch_user_id", twitch_user_id.to_string()))
+            .bind(("twitch_login", twitch_login.to_string()))
+            .bind((
+                "twitch_owner",
+                WalletOwner::Twitch(twitch_user_id.to_string()),
+            ))
+            .await?
+            .take(0)?;
+
+        Ok(discord_user_id.map(|discord_user_id| Self {
+            discord_user_id,
+            twitch_user_id: twitch_user_id.to_string(),
+            twitch_login: twitch_login.to_string(),
+        }))
+    }
+}
+
+/// Returns SurrealQL that moves every balance in `table` owned by
+/// `$twitch_owner` to `$discord_owner`, adding to any balance they already
+/// have in the same guild.
+fn merge_balances(table: &str) -> String {
+    format!(
+        "FOR $row IN (SELECT * FROM {table} WHERE user_id = $twitch_owner) {{
+             LET $existing = (
+                 SELECT VALUE id FROM {table}
+                 WHERE guild_id = $row.guild_id AND user_id = $discord_owner
+             )[0];
+             IF $existing {{
+                 UPDATE $existing SET balance += $row.balance;
+                 DELETE $row.id;
+             }} ELSE {{
+                 UPDATE $row.id SET user_id = $discord_owner;
+             }};
+         }};"
+    )
+}
+
+pub struct AccountLinkProvider;
+
+impl DiscordCommandProvider for AccountLinkProvider {
+    fn commands(&self) -> Vec<DiscordCommand> {
+        vec![link(), unlink()]
+    }
+}
+
+/// link your discord account to another account of yours.
+#[poise::command(slash_command, subcommand_required, subcommands("twitch"))]
+async fn link(_ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    Ok(())
+}
+
+/// link your twitch account, so you earn coins from twitch chat too.
+#[poise::command(slash_command, ephemeral)]
+async fn twitch(ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    let db = ctx.data().access().db();
+    let code = AccountLink::create_code(db, ctx.author().id).await?;
+
+    let mut msg = MessageBuilder::new();
+    if let Some(existing) = AccountLink::get_by_discord_id(db, ctx.author().id).await? {
+        msg.push("you're linked to ")
+            .push_bold_safe(&existing.twitch_login)
+            .push_line(" on twitch right now. using a new code will replace that link.");
+    }
+    msg.push("send ")
+        .push_mono(format!("!link {code}"))
+        .push(format!(
+            " in any twitch chat i'm in within {}, from the twitch account you want to link. don't share this code with anyone!",
+            humantime::format_duration(LINK_CODE_LIFETIME)
+        ));
+
+    ctx.say(msg.build()).await?;
+    Ok(())
+}
+
+/// unlink your twitch account from your discord account.
+#[poise::command(slash_command, ephemeral)]
+async fn unlink(ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    match AccountLink::remove(ctx.data().access().db(), ctx.author().id).await? {
+        Some(link) => {
+            ctx.say(
+                MessageBuilder::new()
+                    .push("done! ")
+                    .push_bold_safe(&link.twitch_login)
+                    .push(" isn't linked to your discord account anymore. your coins stay here with you.")
+                    .build(),
+            )
+            .await?
+        }
+        None => ctx.say("you don't have a twitch account linked :3").await?,
+    };
+
+    Ok(())
+}
+
+const LINK: TwitchCommand = TwitchCommand::new("link");
+
+/// Handles `!link <code>` in Twitch chat, linking the sender's Twitch account
+/// to the Discord account that created the code.
+pub struct AccountLinkHandler {
+    db: MuniBotDb,
+}
+
+impl AccountLinkHandler {
+    pub fn new(db: MuniBotDb) -> Self {
+        Self { db }
+    }
+
+    async fn redeem(
+        &mut self,
+        m: &PrivmsgMessage,
+        client: &MuniBotTwitchIRCClient,
+        code: &str,
+    ) -> Result<(), TwitchHandlerError> {
+        let reply = if code.is_empty() {
+            format!(
+                "@{} use /link twitch on discord to get a code first!",
+                m.sender.name
+            )
+        } else if AccountLink::redeem_code(&self.db, code, &m.sender.id, &m.sender.login)
+            .await?
+            .is_some()
+        {
+            format!(
+                "@{} your twitch account is linked to discord now! ^w^ coins you earn here go to your discord wallet.",
+                m.sender.name
+            )
+        } else {
+            format!(
+                "@{} that code doesn't work. it might have expired! use /link twitch on discord to get a new one.",
+                m.sender.name
+            )
+        };
+
+        self.send_twitch_message(client, &m.channel_login, &reply)
+            .await
+    }
+}
+
+#[async_trait]
+impl TwitchMessageHandler for AccountLinkHandler {
+    fn twitch_handler_name(&self) -> &'static str {
+        "link"
+    }
+
+    fn twitch_commands(&self) -> &'static [TwitchCommand] {
+        &[LINK]
+    }
+
+    async fn handle_twitch_message(
+        &mut self,
+        message: &ServerMessage,
+        client: &MuniBotTwitchIRCClient,
+        _agent: &TwitchAgent,
+        _config: &Config,
+    ) -> Result<bool, TwitchHandlerError> {
+        let ServerMessage::Privmsg(m) = message else {
+            return Ok(false);
+        };
+
+        let mut words = m.message_text.split_whitespace();
+        if words.next() != Some("!link") {
+            return Ok(false);
+        }
+
+        self.redeem(m, client, words.next().unwrap_or_default())
+            .await?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::{GuildId, UserId};
+
+    use super::AccountLink;
+    use crate::{
+        db::test_db,
+        handlers::economy::{owner::WalletOwner, wallet::Wallet},
+    };
+
+    #[tokio::test]
+    async fn test_account_linking() {
+        let db = test_db().await;
+        let guild_id = GuildId::new(1);
+        let discord_user_id = UserId::new(2);
+
+        // coins earned before linking follow the twitch account
+        Wallet::create_in_db(&db, guild_id, WalletOwner::Twitch("30".to_string()), 15)
+            .await
+            .unwrap();
+        Wallet::create_in_db(&db, guild_id, discord_user_id, 10)
+            .await
+            .unwrap();
+
+        let code = AccountLink::create_code(&db, discord_user_id)
+            .await
+            .unwrap();
+        assert!(AccountLink::redeem_code(&db, "nope", "30", "muni")
+            .await
+            .unwrap()
+            .is_none());
+        let link = AccountLink::redeem_code(&db, &code.to_lowercase(), "30", "muni")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(link.discord_user_id, discord_user_id);
+
+        // codes only work once
+        assert!(AccountLink::redeem_code(&db, &code, "31", "someone_else")
+            .await
+            .unwrap()
+            .is_none());
+
+        assert_eq!(
+            WalletOwner::for_twitch_user(&db, "30").await.unwrap(),
+            WalletOwner::Discord(discord_user_id)
+        );
+        let wallet = Wallet::get_from_db(&db, guild_id, discord_user_id)
+            .await
+            .unwrap();
+        assert_eq!(wallet.balance(), 25);
+
+        let removed = AccountLink::remove(&db, discord_user_id).await.unwrap();
+        assert_eq!(removed.map(|l| l.twitch_login).as_deref(), Some("muni"));
+        assert!(AccountLink::get_by_twitch_id(&db, "30")
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
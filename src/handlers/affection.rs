@@ -6,15 +6,31 @@ use crate::{
     twitch::{
         agent::TwitchAgent,
         bot::MuniBotTwitchIRCClient,
+        commands::TwitchCommand,
         handler::{TwitchHandlerError, TwitchMessageHandler},
     },
 };
 
+const COMMANDS: [TwitchCommand; 4] = [
+    TwitchCommand::new("hug"),
+    TwitchCommand::new("glomp"),
+    TwitchCommand::new("nuzzle"),
+    TwitchCommand::new("boop"),
+];
+
 #[derive(Default)]
 pub struct AffectionHandler;
 
 #[async_trait]
 impl TwitchMessageHandler for AffectionHandler {
+    fn twitch_handler_name(&self) -> &'static str {
+        "affection"
+    }
+
+    fn twitch_commands(&self) -> &'static [TwitchCommand] {
+        &COMMANDS
+    }
+
     async fn handle_twitch_message(
         &mut self,
         message: &ServerMessage,
@@ -1,106 +1,676 @@
-use twitch_irc::message::ServerMessage;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use log::{error, warn};
+use tokio::sync::mpsc::UnboundedSender;
+use twitch_api::{helix::users::User, types::UserId};
+use twitch_irc::message::{PrivmsgMessage, ServerMessage};
 
+use self::{
+    audit::{ModerationAction, ModerationNotice, ModerationRecord},
+    rules::{harshest_match, AutoBanRule, CompiledRule, RuleAction, RuleKind, RuleResponse},
+};
 use crate::{
     config::Config,
+    db::MuniBotDb,
     twitch::{
         agent::TwitchAgent,
         bot::MuniBotTwitchIRCClient,
+        commands::TwitchCommand,
         handler::{TwitchHandlerError, TwitchMessageHandler},
+        utils::is_moderator,
     },
 };
 
-pub struct AutoBanHandler;
+pub mod admin;
+pub mod audit;
+pub mod rules;
+
+/// How long a channel's rules are kept before they're loaded from the
+/// database again. Rules changed from chat are picked up right away, but rules
+/// changed from Discord can take this long to apply.
+const RULES_RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The longest `!autoban list` reply, so it fits in a chat message.
+const MAX_LIST_LENGTH: usize = 450;
+
+/// How many moderation records `!autoban log` shows.
+const LOG_LENGTH: usize = 5;
+
+const AUTOBAN: TwitchCommand = TwitchCommand::new("autoban");
+
+/// Deals with unwanted chatters, following the [`AutoBanRule`]s set up for
+/// each channel. Moderators can manage their channel's rules with `!autoban`.
+///
+/// Every action taken is recorded as a [`ModerationRecord`] and mirrored to
+/// the logging channel of the channel's Discord server, if it has one.
+pub struct AutoBanHandler {
+    db: MuniBotDb,
+
+    /// Each channel's rules, and when they were loaded.
+    rules: HashMap<String, (Instant, Vec<CompiledRule>)>,
+
+    /// Where records are sent to be mirrored to Discord.
+    notices: UnboundedSender<ModerationNotice>,
+}
+
+impl AutoBanHandler {
+    pub fn new(db: MuniBotDb, notices: UnboundedSender<ModerationNotice>) -> Self {
+        Self {
+            db,
+            rules: HashMap::new(),
+            notices,
+        }
+    }
+
+    /// Saves a moderation record and mirrors it to Discord.
+    async fn record(
+        &self,
+        record: &mut ModerationRecord,
+        config: &Config,
+    ) -> Result<(), TwitchHandlerError> {
+        record.save(&self.db).await?;
+
+        if let Some(guild_id) = config
+            .twitch
+            .channels
+            .get(&record.channel)
+            .and_then(|channel| channel.discord_guild)
+        {
+            // if discord isn't running, there's nowhere to mirror to anyway
+            let _ = self.notices.send(ModerationNotice {
+                guild_id,
+                record: record.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the rules for a channel, loading them from the database if
+    /// they haven't been loaded recently.
+    async fn rules_for(
+        &mut self,
+        channel_login: &str,
+    ) -> Result<&[CompiledRule], TwitchHandlerError> {
+        let stale = self
+            .rules
+            .get(channel_login)
+            .is_none_or(|(loaded_at, _)| loaded_at.elapsed() > RULES_RELOAD_INTERVAL);
+
+        if stale {
+            let rules = AutoBanRule::get_for_channel(&self.db, channel_login)
+                .await?
+                .into_iter()
+                .filter_map(|rule| {
+                    CompiledRule::new(rule)
+                        .inspect_err(|e| warn!("skipping autoban rule in {channel_login}: {e}"))
+                        .ok()
+                })
+                .collect();
+            self.rules
+                .insert(channel_login.to_string(), (Instant::now(), rules));
+        }
+
+        Ok(&self.rules[channel_login].1)
+    }
+
+    /// Handles `!autoban` commands from moderators. Returns true if the
+    /// message was a command.
+    async fn handle_command(
+        &mut self,
+        m: &PrivmsgMessage,
+        client: &MuniBotTwitchIRCClient,
+        agent: &TwitchAgent<'_>,
+        config: &Config,
+    ) -> Result<bool, TwitchHandlerError> {
+        let mut words = m.message_text.split_whitespace();
+        if words.next() != Some("!autoban") || !is_moderator(m) {
+            return Ok(false);
+        }
+
+        let reply = match words.next() {
+            Some("add") => {
+                let (Some(kind), Some(response)) = (words.next(), words.next()) else {
+                    return self.reply_usage(m, client).await;
+                };
+                let pattern = words.collect::<Vec<_>>().join(" ");
+                if pattern.is_empty() {
+                    return self.reply_usage(m, client).await;
+                }
+
+                match parse_rule(&m.channel_login, kind, response, &pattern) {
+                    Ok(rule) => {
+                        rule.add(&self.db).await?;
+                        self.rules.remove(&m.channel_login);
+                        format!("got it! new rule: {}", describe_rule(&rule))
+                    }
+                    Err(e) => e,
+                }
+            }
+            Some("remove") => {
+                let pattern = words.collect::<Vec<_>>().join(" ");
+                if pattern.is_empty() {
+                    return self.reply_usage(m, client).await;
+                }
+
+                let removed = AutoBanRule::remove(&self.db, &m.channel_login, &pattern).await?;
+                self.rules.remove(&m.channel_login);
+                match removed {
+                    0 => "there's no rule for this channel with that pattern. rules that apply everywhere can be turned off here with !autoban disable <pattern>.".to_string(),
+                    1 => "rule removed!".to_string(),
+                    n => format!("{n} rules removed!"),
+                }
+            }
+            Some(toggle @ ("disable" | "enable")) => {
+                let pattern = words.collect::<Vec<_>>().join(" ");
+                if pattern.is_empty() {
+                    return self.reply_usage(m, client).await;
+                }
+
+                let changed = if toggle == "disable" {
+                    AutoBanRule::disable(&self.db, &m.channel_login, &pattern).await?
+                } else {
+                    AutoBanRule::enable(&self.db, &m.channel_login, &pattern).await?
+                };
+                self.rules.remove(&m.channel_login);
+                match changed {
+                    0 if toggle == "disable" => {
+                        "there's no rule that applies everywhere with that pattern.".to_string()
+                    }
+                    0 => "that rule isn't disabled here.".to_string(),
+                    _ => format!("okay! that rule is {toggle}d here."),
+                }
+            }
+            Some("list") => {
+                let rules = AutoBanRule::get_for_channel(&self.db, &m.channel_login).await?;
+                let disabled = AutoBanRule::get_disabled(&self.db, &m.channel_login).await?;
+                if rules.is_empty() && disabled.is_empty() {
+                    "there are no autoban rules here.".to_string()
+                } else {
+                    let mut list = rules
+                        .iter()
+                        .map(describe_rule)
+                        .collect::<Vec<_>>()
+                        .join(" | ");
+                    if !disabled.is_empty() {
+                        list.push_str(&format!(" | disabled here: {}", disabled.join(", ")));
+                    }
+                    if list.chars().count() > MAX_LIST_LENGTH {
+                        format!(
+                            "{}...",
+                            list.chars().take(MAX_LIST_LENGTH).collect::<String>()
+                        )
+                    } else {
+                        list
+                    }
+                }
+            }
+            Some("log") => {
+                let records =
+                    ModerationRecord::get_recent(&self.db, &m.channel_login, LOG_LENGTH).await?;
+                if records.is_empty() {
+                    "i haven't moderated anyone here yet.".to_string()
+                } else {
+                    records
+                        .iter()
+                        .map(ModerationRecord::summary)
+                        .collect::<Vec<_>>()
+                        .join(" | ")
+                }
+            }
+            Some("undo") => {
+                let Some(number) = words
+                    .next()
+                    .and_then(|n| n.trim_start_matches('#').parse().ok())
+                else {
+                    return self.reply_usage(m, client).await;
+                };
+                self.undo(m, agent, config, number).await?
+            }
+            _ => return self.reply_usage(m, client).await,
+        };
+
+        self.send_twitch_message(
+            client,
+            &m.channel_login,
+            &format!("@{} {reply}", m.sender.name),
+        )
+        .await?;
+        Ok(true)
+    }
+
+    /// Undoes the ban or timeout with the given record number, returning a
+    /// reply for the moderator who asked.
+    async fn undo(
+        &self,
+        m: &PrivmsgMessage,
+        agent: &TwitchAgent<'_>,
+        config: &Config,
+        number: u32,
+    ) -> Result<String, TwitchHandlerError> {
+        let Some(mut record) = ModerationRecord::get(&self.db, &m.channel_login, number).await?
+        else {
+            return Ok(format!("there's no moderation record #{number} here."));
+        };
+        if record.reversed {
+            return Ok(format!("#{number} was already undone."));
+        }
+        if !record.action.is_reversible() {
+            return Ok(format!(
+                "#{number} can't be undone, since nobody was banned or timed out."
+            ));
+        }
+
+        match agent
+            .unban_user(
+                &UserId::from(record.user_id.clone()),
+                &UserId::from(m.channel_id.clone()),
+            )
+            .await
+        {
+            Ok(()) => {}
+            Err(e) if e.is_not_banned() => {
+                // their timeout ran out or someone else unbanned them already
+                record.mark_reversed(&self.db).await?;
+                return Ok(format!(
+                    "{} isn't banned or timed out anymore, so i marked #{number} as undone.",
+                    record.user_login
+                ));
+            }
+            Err(e) => {
+                error!("couldn't undo moderation record #{number}: {e}");
+                return Ok(format!("i couldn't undo #{number}: {e}"));
+            }
+        }
+        record.mark_reversed(&self.db).await?;
+
+        let mut unban = ModerationRecord::new(
+            &record.channel,
+            &record.user_login,
+            &record.user_id,
+            ModerationAction::Unban,
+            &format!("undid #{number}"),
+        );
+        unban.moderator = Some(m.sender.login.clone());
+        self.record(&mut unban, config).await?;
+
+        Ok(format!("undone! {} can chat again.", record.user_login))
+    }
+
+    /// Carries out a rule's response on a chatter: deleting their message if
+    /// the rule says to, then warning, timing out, or banning them. Returns
+    /// true if anything was done.
+    async fn enforce_rule(
+        &self,
+        agent: &TwitchAgent<'_>,
+        config: &Config,
+        rule: &AutoBanRule,
+        user_login: &str,
+        channel_login: &str,
+        message: Option<&PrivmsgMessage>,
+    ) -> Result<bool, TwitchHandlerError> {
+        let response = rule.response();
+
+        // there's nothing to delete when someone's name matches as they join
+        if response.action == RuleAction::Delete && message.is_none() {
+            return Ok(false);
+        }
+
+        let (broadcaster_id, user_id) = match message {
+            // chat messages already say who everyone is
+            Some(m) => (
+                UserId::from(m.channel_id.clone()),
+                UserId::from(m.sender.id.clone()),
+            ),
+            // but joins only have logins
+            None => (
+                get_user(agent, channel_login).await?.id,
+                get_user(agent, user_login).await?.id,
+            ),
+        };
+
+        let mut message_deleted = false;
+        if let (true, Some(message)) = (response.delete_message, message) {
+            agent
+                .delete_message(&message.message_id, &broadcaster_id)
+                .await?;
+            message_deleted = true;
+        }
+
+        match response.action {
+            RuleAction::Delete => (),
+            RuleAction::Warn => {
+                agent
+                    .warn_user(&user_id, &rule.reason, &broadcaster_id)
+                    .await?
+            }
+            RuleAction::Timeout { duration } => {
+                agent
+                    .timeout_user(&user_id, duration, &rule.reason, &broadcaster_id)
+                    .await?
+            }
+            RuleAction::Ban => {
+                agent
+                    .ban_user(&user_id, &rule.reason, &broadcaster_id)
+                    .await?
+            }
+        }
+
+        let mut record = ModerationRecord::new(
+            channel_login,
+            user_login,
+            user_id.as_str(),
+            response.action.into(),
+            &rule.reason,
+        );
+        record.message_deleted = message_deleted;
+        record.rule = Some(describe_rule(rule));
+        record.message = message.map(|m| m.message_text.clone());
+        self.record(&mut record, config).await?;
+
+        Ok(true)
+    }
+
+    async fn reply_usage(
+        &mut self,
+        m: &PrivmsgMessage,
+        client: &MuniBotTwitchIRCClient,
+    ) -> Result<bool, TwitchHandlerError> {
+        self.send_twitch_message(
+            client,
+            &m.channel_login,
+            &format!(
+                "@{} usage: !autoban add <name|name-regex|phrase|regex> <ban|warn|delete|timeout|timeout:DURATION> (add delete+ to also delete the message, like delete+timeout:5m) <pattern>, !autoban remove <pattern>, !autoban disable/enable <pattern> (for rules that apply everywhere), !autoban list, !autoban log, or !autoban undo <number>",
+                m.sender.name
+            ),
+        )
+        .await?;
+        Ok(true)
+    }
+}
 
 #[async_trait::async_trait]
 impl TwitchMessageHandler for AutoBanHandler {
+    fn twitch_handler_name(&self) -> &'static str {
+        "autoban"
+    }
+
+    fn twitch_commands(&self) -> &'static [TwitchCommand] {
+        &[AUTOBAN]
+    }
+
     /// Handle a new message from chat. Returns `true` if something was done to
     /// handle the message, or `false` if the message was ignored (or if the
     /// message is allowed to also be handled by other handlers).
     async fn handle_twitch_message(
         &mut self,
         message: &ServerMessage,
-        _client: &MuniBotTwitchIRCClient,
+        client: &MuniBotTwitchIRCClient,
         agent: &TwitchAgent,
-        _config: &Config,
+        config: &Config,
     ) -> Result<bool, TwitchHandlerError> {
-        let (user_login, channel_login, msg_content) = match message {
+        let (user_login, channel_login, privmsg) = match message {
             ServerMessage::Join(join_msg) => (
-                join_msg.user_login.to_lowercase(),
+                join_msg.user_login.as_str(),
                 join_msg.channel_login.as_str(),
-                "",
-            ),
-            ServerMessage::Privmsg(privmsg) => (
-                privmsg.sender.login.to_lowercase(),
-                privmsg.channel_login.as_str(),
-                privmsg.message_text.as_str(),
+                None,
             ),
+            ServerMessage::Privmsg(privmsg) => {
+                if self.handle_command(privmsg, client, agent, config).await? {
+                    return Ok(true);
+                }
+
+                // moderators are trusted not to be bots or scammers
+                if is_moderator(privmsg) {
+                    return Ok(false);
+                }
+
+                (
+                    privmsg.sender.login.as_str(),
+                    privmsg.channel_login.as_str(),
+                    Some(privmsg),
+                )
+            }
             _ => return Ok(false),
         };
 
-        if user_login.contains("isapred") || user_login.contains("isabadstreamer") {
-            yeet_user(
-                agent,
-                &user_login,
-                channel_login,
-                "user is suspected of harassment",
-            )
-            .await?;
-            Ok(true)
-        } else if matches_scam_message(msg_content).map_err(|e| {
-            TwitchHandlerError::Other(format!("couldn't sanitize homoglyphed message: {e}"))
-        })? {
-            yeet_user(agent, &user_login, channel_login, "likely viewer scam bot").await?;
-            Ok(true)
-        } else {
-            Ok(false)
+        if user_login.eq_ignore_ascii_case(&config.twitch.twitch_user) {
+            return Ok(false);
         }
+
+        // match by "cured" text (free of homoglyphs)
+        let cure_error =
+            |e| TwitchHandlerError::Other(format!("couldn't sanitize homoglyphed text: {e}"));
+        let cured_login = decancer::cure!(user_login).map_err(cure_error)?;
+        let cured_message = privmsg
+            .map(|m| decancer::cure!(&m.message_text))
+            .transpose()
+            .map_err(cure_error)?;
+
+        let rules = self.rules_for(channel_login).await?;
+        let Some(rule) = harshest_match(rules, &cured_login, cured_message.as_ref()).cloned()
+        else {
+            return Ok(false);
+        };
+
+        self.enforce_rule(agent, config, &rule, user_login, channel_login, privmsg)
+            .await
     }
 }
 
-async fn yeet_user(
-    agent: &TwitchAgent<'_>,
-    user_login: &str,
+/// Parses a rule from the words of an `!autoban add` command.
+fn parse_rule(
     channel_login: &str,
-    reason: &str,
-) -> Result<(), TwitchHandlerError> {
-    let Some(broadcaster_id) = agent.get_user_from_login(channel_login).await? else {
-        return Err(TwitchHandlerError::Other(format!(
-            "could not get broadcaster id for channel {}",
-            channel_login
-        )));
-    };
+    kind: &str,
+    response: &str,
+    pattern: &str,
+) -> Result<AutoBanRule, String> {
+    let kind: RuleKind = kind.parse()?;
+    let response: RuleResponse = response.parse()?;
+    AutoBanRule::new(
+        Some(channel_login.to_string()),
+        kind,
+        pattern,
+        response,
+        None,
+    )
+}
 
-    let Some(ban_user_id) = agent.get_user_from_login(user_login).await? else {
-        return Err(TwitchHandlerError::Other(format!(
-            "could not get user id for user {} on channel {}",
-            user_login, channel_login
-        )));
+/// Describes a rule in a few words, for listing rules.
+pub fn describe_rule(rule: &AutoBanRule) -> String {
+    let scope = if rule.channel.is_none() {
+        " (everywhere)"
+    } else {
+        ""
     };
-
-    agent
-        .ban_user(&ban_user_id.id, reason, &broadcaster_id.id)
-        .await?;
-
-    Ok(())
+    format!(
+        "{} \"{}\" -> {}{scope}",
+        rule.kind,
+        rule.pattern,
+        rule.response()
+    )
 }
 
-fn matches_scam_message(msg_content: &str) -> Result<bool, decancer::Error> {
-    // match by "cured" message (free of homoglyphs)
-    let cured_string = decancer::cure!(msg_content)?;
-
-    Ok(!cured_string
-        .find_multiple(["cheap viewers on", "best viewers on"])
-        .is_empty())
+/// Looks up a Twitch user by their login.
+async fn get_user(agent: &TwitchAgent<'_>, login: &str) -> Result<User, TwitchHandlerError> {
+    agent
+        .get_user_from_login(login)
+        .await?
+        .ok_or_else(|| TwitchHandlerError::Other(format!("could not get user id for {login}")))
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    use super::rules::{
+        harshest_match, AutoBanRule, CompiledRule, RuleAction, RuleKind, RuleResponse,
+    };
+    use crate::db::test_db;
+
+    async fn channel_rules(db: &crate::db::MuniBotDb, channel: &str) -> Vec<CompiledRule> {
+        AutoBanRule::get_for_channel(db, channel)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| CompiledRule::new(r).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_default_rules() {
+        let db = test_db().await;
+        let rules = channel_rules(&db, "somechannel").await;
+        let login = decancer::cure!("somebody").unwrap();
+
+        let msg = decancer::cure!("𝕔𝕙𝕖𝕒𝕡 𝕧𝕚𝕖𝕨𝕖𝕣𝕤 𝕠𝕟 scam.url").unwrap();
+        let rule = harshest_match(&rules, &login, Some(&msg)).unwrap();
+        assert_eq!(rule.action, RuleAction::Ban);
+
+        let msg = decancer::cure!("b︢e︢st v︢ie︢we︣rs o︣n scam.url").unwrap();
+        assert!(harshest_match(&rules, &login, Some(&msg)).is_some());
+
+        // less certain matches get a gentler response
+        let msg = decancer::cure!("ᴄʜᴇᴀᴘ followers at scam.url").unwrap();
+        let rule = harshest_match(&rules, &login, Some(&msg)).unwrap();
+        assert_eq!(
+            rule.response(),
+            "delete+timeout:10m".parse::<RuleResponse>().unwrap()
+        );
+
+        let msg = decancer::cure!("hello there").unwrap();
+        assert!(harshest_match(&rules, &login, Some(&msg)).is_none());
+
+        // talking about buying followers isn't a scam without a link
+        let msg = decancer::cure!("never buy followers lol. it's not worth it").unwrap();
+        assert!(harshest_match(&rules, &login, Some(&msg)).is_none());
+
+        let login = decancer::cure!("someone_isapred").unwrap();
+        assert!(harshest_match(&rules, &login, None).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_channel_rules() {
+        let db = test_db().await;
+        let channel = Some("muni_corn".to_string());
+        AutoBanRule::new(
+            channel.clone(),
+            RuleKind::MessageContains,
+            "Free Nitro",
+            "delete".parse().unwrap(),
+            None,
+        )
+        .unwrap()
+        .add(&db)
+        .await
+        .unwrap();
+        AutoBanRule::new(
+            channel.clone(),
+            RuleKind::MessageRegex,
+            r"nitro\s+at",
+            "warn".parse().unwrap(),
+            None,
+        )
+        .unwrap()
+        .add(&db)
+        .await
+        .unwrap();
+        assert!(AutoBanRule::new(
+            channel,
+            RuleKind::NameRegex,
+            "(",
+            "ban".parse().unwrap(),
+            None
+        )
+        .is_err());
+
+        // the harshest matching rule wins
+        let rules = channel_rules(&db, "muni_corn").await;
+        let login = decancer::cure!("somebody").unwrap();
+        let msg = decancer::cure!("𝐟𝐫𝐞𝐞 nitro at example.com").unwrap();
+        let rule = harshest_match(&rules, &login, Some(&msg)).unwrap();
+        assert_eq!(rule.action, RuleAction::Warn);
+
+        // other channels aren't affected
+        let rules = channel_rules(&db, "someone_else").await;
+        assert!(harshest_match(&rules, &login, Some(&msg)).is_none());
+
+        // rules can be removed by pattern, no matter the case
+        assert_eq!(
+            AutoBanRule::remove(&db, "muni_corn", "free nitro")
+                .await
+                .unwrap(),
+            1
+        );
+        let rules = channel_rules(&db, "muni_corn").await;
+        let msg = decancer::cure!("free nitro for you").unwrap();
+        assert!(harshest_match(&rules, &login, Some(&msg)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_disabling_default_rules() {
+        let db = test_db().await;
+        let login = decancer::cure!("someone_isapred").unwrap();
+
+        // only rules that apply everywhere can be disabled
+        assert_eq!(
+            AutoBanRule::disable(&db, "muni_corn", "nonexistent")
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            AutoBanRule::disable(&db, "muni_corn", "ISAPRED")
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            AutoBanRule::disable(&db, "muni_corn", "isapred")
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            AutoBanRule::get_disabled(&db, "muni_corn").await.unwrap(),
+            vec!["isapred".to_string()]
+        );
+
+        let rules = channel_rules(&db, "muni_corn").await;
+        assert!(harshest_match(&rules, &login, None).is_none());
+
+        // other channels still have the rule
+        let rules = channel_rules(&db, "someone_else").await;
+        assert!(harshest_match(&rules, &login, None).is_some());
+
+        assert_eq!(
+            AutoBanRule::enable(&db, "muni_corn", "isapred")
+                .await
+                .unwrap(),
+            1
+        );
+        let rules = channel_rules(&db, "muni_corn").await;
+        assert!(harshest_match(&rules, &login, None).is_some());
+    }
+
     #[test]
-    fn test_matches_scam_message() {
-        let msg = "𝕔𝕙𝕖𝕒𝕡 𝕧𝕚𝕖𝕨𝕖𝕣𝕤 𝕠𝕟 scam.url";
-        assert!(super::matches_scam_message(msg).unwrap());
+    fn test_parse_responses() {
+        let response: RuleResponse = "delete+timeout:5m".parse().unwrap();
+        assert!(response.delete_message);
+        assert_eq!(
+            response.action,
+            RuleAction::Timeout {
+                duration: Duration::from_secs(300)
+            }
+        );
+        assert_eq!(response.to_string(), "delete+timeout:5m");
+
+        let response: RuleResponse = "warn".parse().unwrap();
+        assert!(!response.delete_message);
+        assert_eq!(response.to_string(), "warn");
 
-        let msg = "b︢e︢st v︢ie︢we︣rs o︣n scam.url";
-        assert!(super::matches_scam_message(msg).unwrap());
+        assert!("delete+yeet".parse::<RuleResponse>().is_err());
     }
 }
@@ -0,0 +1,280 @@
+use poise::{serenity_prelude::MessageBuilder, CreateReply};
+
+use super::{
+    describe_rule,
+    rules::{AutoBanRule, RuleAction, RuleKind, RuleResponse, DEFAULT_TIMEOUT},
+};
+use crate::{discord::DiscordContext, MuniBotError};
+
+/// What to do to chatters who match a rule, as picked in Discord.
+#[derive(Clone, Copy, Debug, poise::ChoiceParameter)]
+enum ActionChoice {
+    #[name = "ban"]
+    Ban,
+    #[name = "timeout"]
+    Timeout,
+    #[name = "warn"]
+    Warn,
+    #[name = "delete message"]
+    Delete,
+}
+
+/// manage autoban rules for this server's twitch channels.
+#[poise::command(
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommand_required,
+    subcommands("add", "remove", "disable", "enable", "list"),
+    ephemeral
+)]
+pub async fn autoban(_ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    Ok(())
+}
+
+/// add an autoban rule to a twitch channel.
+#[poise::command(
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+#[allow(clippy::too_many_arguments)]
+async fn add(
+    ctx: DiscordContext<'_>,
+    #[description = "the twitch channel this rule is for"]
+    #[autocomplete = "autocomplete_channel"]
+    channel: String,
+    #[description = "what the rule looks at"] kind: RuleKind,
+    #[description = "the phrase or regex to look for. look-alike characters are handled for you."]
+    pattern: String,
+    #[description = "what to do to chatters who match"] action: ActionChoice,
+    #[description = "how long timeouts last, like \"10m\" or \"1h\". defaults to 10 minutes."]
+    timeout: Option<String>,
+    #[description = "whether to also delete the matching message. defaults to no."]
+    delete_message: Option<bool>,
+    #[description = "the reason given to twitch"] reason: Option<String>,
+) -> Result<(), MuniBotError> {
+    let Some(channel) = guild_channel(ctx, &channel).await? else {
+        return Ok(());
+    };
+
+    let action = match action {
+        ActionChoice::Ban => RuleAction::Ban,
+        ActionChoice::Warn => RuleAction::Warn,
+        ActionChoice::Delete => RuleAction::Delete,
+        ActionChoice::Timeout => match timeout.as_deref().map(humantime::parse_duration) {
+            None => RuleAction::Timeout {
+                duration: DEFAULT_TIMEOUT,
+            },
+            Some(Ok(duration)) => RuleAction::Timeout { duration },
+            Some(Err(e)) => {
+                return say_ephemeral(ctx, format!("that isn't a duration i understand: {e}"))
+                    .await;
+            }
+        },
+    };
+
+    let response = RuleResponse {
+        action,
+        delete_message: delete_message.unwrap_or_default(),
+    };
+    let rule = match AutoBanRule::new(Some(channel.clone()), kind, &pattern, response, reason) {
+        Ok(rule) => rule,
+        Err(e) => return say_ephemeral(ctx, e).await,
+    };
+    rule.add(ctx.data().access().db()).await?;
+
+    say_ephemeral(
+        ctx,
+        MessageBuilder::new()
+            .push("done! added ")
+            .push_mono_safe(describe_rule(&rule))
+            .push(" to ")
+            .push_bold_safe(&channel)
+            .push(". it'll take effect within a minute.")
+            .build(),
+    )
+    .await
+}
+
+/// remove a twitch channel's autoban rules with the given pattern.
+#[poise::command(
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn remove(
+    ctx: DiscordContext<'_>,
+    #[description = "the twitch channel to remove the rule from"]
+    #[autocomplete = "autocomplete_channel"]
+    channel: String,
+    #[description = "the pattern of the rule to remove"] pattern: String,
+) -> Result<(), MuniBotError> {
+    let Some(channel) = guild_channel(ctx, &channel).await? else {
+        return Ok(());
+    };
+
+    let removed = AutoBanRule::remove(ctx.data().access().db(), &channel, &pattern).await?;
+    let reply = match removed {
+        0 => "there's no rule for that channel with that pattern. rules that apply everywhere can be turned off with /admin autoban disable.".to_string(),
+        1 => "done! the rule was removed.".to_string(),
+        n => format!("done! {n} rules were removed."),
+    };
+    say_ephemeral(ctx, reply).await
+}
+
+/// turn off an autoban rule that applies everywhere, just for one twitch channel.
+#[poise::command(
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn disable(
+    ctx: DiscordContext<'_>,
+    #[description = "the twitch channel to turn the rule off in"]
+    #[autocomplete = "autocomplete_channel"]
+    channel: String,
+    #[description = "the pattern of the rule to turn off"] pattern: String,
+) -> Result<(), MuniBotError> {
+    let Some(channel) = guild_channel(ctx, &channel).await? else {
+        return Ok(());
+    };
+
+    let reply = if AutoBanRule::disable(ctx.data().access().db(), &channel, &pattern).await? > 0 {
+        "done! that rule is off for that channel. it'll take effect within a minute."
+    } else {
+        "there's no rule that applies everywhere with that pattern."
+    };
+    say_ephemeral(ctx, reply).await
+}
+
+/// turn an autoban rule that applies everywhere back on for a twitch channel.
+#[poise::command(
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn enable(
+    ctx: DiscordContext<'_>,
+    #[description = "the twitch channel to turn the rule back on in"]
+    #[autocomplete = "autocomplete_channel"]
+    channel: String,
+    #[description = "the pattern of the rule to turn back on"] pattern: String,
+) -> Result<(), MuniBotError> {
+    let Some(channel) = guild_channel(ctx, &channel).await? else {
+        return Ok(());
+    };
+
+    let reply = if AutoBanRule::enable(ctx.data().access().db(), &channel, &pattern).await? > 0 {
+        "done! that rule is back on for that channel. it'll take effect within a minute."
+    } else {
+        "that rule isn't turned off for that channel."
+    };
+    say_ephemeral(ctx, reply).await
+}
+
+/// list a twitch channel's autoban rules.
+#[poise::command(
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn list(
+    ctx: DiscordContext<'_>,
+    #[description = "the twitch channel to list rules for"]
+    #[autocomplete = "autocomplete_channel"]
+    channel: String,
+) -> Result<(), MuniBotError> {
+    let Some(channel) = guild_channel(ctx, &channel).await? else {
+        return Ok(());
+    };
+
+    let db = ctx.data().access().db();
+    let rules = AutoBanRule::get_for_channel(db, &channel).await?;
+    let disabled = AutoBanRule::get_disabled(db, &channel).await?;
+    if rules.is_empty() && disabled.is_empty() {
+        return say_ephemeral(ctx, "there are no autoban rules for that channel.").await;
+    }
+
+    let mut msg = MessageBuilder::new();
+    msg.push("autoban rules for ")
+        .push_bold_safe(&channel)
+        .push_line(":");
+    for rule in &rules {
+        msg.push("- ")
+            .push_mono_safe(describe_rule(rule))
+            .push(": ")
+            .push_line_safe(&rule.reason);
+    }
+    for pattern in &disabled {
+        msg.push("- turned off here: ").push_mono_line_safe(pattern);
+    }
+    say_ephemeral(ctx, msg.build()).await
+}
+
+/// Returns the login of the given channel if it belongs to this server, or
+/// tells the user it doesn't and returns `None`.
+async fn guild_channel(
+    ctx: DiscordContext<'_>,
+    channel: &str,
+) -> Result<Option<String>, MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(None);
+    };
+
+    let channel = channel.trim().trim_start_matches('@').to_lowercase();
+    if ctx
+        .data()
+        .twitch_config()
+        .channels_for_guild(guild_id)
+        .contains(&channel.as_str())
+    {
+        Ok(Some(channel))
+    } else {
+        say_ephemeral(
+            ctx,
+            MessageBuilder::new()
+                .push_bold_safe(&channel)
+                .push(" isn't one of this server's twitch channels. ask munibot's owners to set it up!")
+                .build(),
+        )
+        .await?;
+        Ok(None)
+    }
+}
+
+async fn autocomplete_channel(ctx: DiscordContext<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Vec::new();
+    };
+
+    let partial = partial.to_lowercase();
+    ctx.data()
+        .twitch_config()
+        .channels_for_guild(guild_id)
+        .into_iter()
+        .filter(|login| login.contains(&partial))
+        .take(25)
+        .map(str::to_string)
+        .collect()
+}
+
+async fn say_ephemeral(
+    ctx: DiscordContext<'_>,
+    content: impl Into<String>,
+) -> Result<(), MuniBotError> {
+    ctx.send(CreateReply::default().ephemeral(true).content(content))
+        .await?;
+    Ok(())
+}
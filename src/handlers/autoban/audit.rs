@@ -0,0 +1,324 @@
+use std::{fmt::Display, sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use log::error;
+use poise::serenity_prelude::{GuildId, MessageBuilder};
+use serde::{Deserialize, Serialize};
+use surrealdb::{Connection, Surreal};
+use tokio::{
+    sync::{mpsc::UnboundedReceiver, Mutex},
+    task::JoinHandle,
+};
+
+use super::rules::RuleAction;
+use crate::handlers::logging::LoggingHandler;
+
+pub const MODERATION_RECORD_TABLE: &str = "twitch_moderation_record";
+
+/// Something munibot did to a Twitch chatter.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ModerationAction {
+    Delete,
+    Warn,
+    Timeout { duration: Duration },
+    Ban,
+    Unban,
+}
+
+impl ModerationAction {
+    /// Returns true if this action can be undone by unbanning the chatter.
+    pub fn is_reversible(&self) -> bool {
+        matches!(
+            self,
+            ModerationAction::Timeout { .. } | ModerationAction::Ban
+        )
+    }
+
+    /// A short title for this action, for logs.
+    fn title(&self) -> &'static str {
+        match self {
+            ModerationAction::Delete => "twitch message deleted",
+            ModerationAction::Warn => "twitch user warned",
+            ModerationAction::Timeout { .. } => "twitch user timed out",
+            ModerationAction::Ban => "twitch user banned",
+            ModerationAction::Unban => "twitch user unbanned",
+        }
+    }
+}
+
+impl From<RuleAction> for ModerationAction {
+    fn from(action: RuleAction) -> Self {
+        match action {
+            RuleAction::Delete => ModerationAction::Delete,
+            RuleAction::Warn => ModerationAction::Warn,
+            RuleAction::Timeout { duration } => ModerationAction::Timeout { duration },
+            RuleAction::Ban => ModerationAction::Ban,
+        }
+    }
+}
+
+impl Display for ModerationAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModerationAction::Delete => write!(f, "delete"),
+            ModerationAction::Warn => write!(f, "warn"),
+            ModerationAction::Timeout { duration } => {
+                write!(f, "timeout ({})", humantime::format_duration(*duration))
+            }
+            ModerationAction::Ban => write!(f, "ban"),
+            ModerationAction::Unban => write!(f, "unban"),
+        }
+    }
+}
+
+/// A record of a moderation action munibot took in a Twitch channel, either on
+/// its own (because of an autoban rule) or because a moderator asked.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ModerationRecord {
+    /// The number of this record in its channel. Moderators use this to undo
+    /// actions.
+    pub number: u32,
+
+    pub channel: String,
+    pub user_login: String,
+    pub user_id: String,
+    pub action: ModerationAction,
+
+    /// Whether the chatter's message was deleted too.
+    #[serde(default)]
+    pub message_deleted: bool,
+
+    /// The rule that was matched, if the action was automatic.
+    #[serde(default)]
+    pub rule: Option<String>,
+
+    /// The message that matched the rule, if any.
+    #[serde(default)]
+    pub message: Option<String>,
+
+    pub reason: String,
+
+    /// Who asked for the action: `None` if munibot acted on its own, or the
+    /// moderator's login otherwise.
+    #[serde(default)]
+    pub moderator: Option<String>,
+
+    /// Whether the action has since been undone.
+    #[serde(default)]
+    pub reversed: bool,
+
+    /// When the action was taken. This is set by the database.
+    #[serde(skip_serializing)]
+    pub created_at: DateTime<Utc>,
+}
+
+impl ModerationRecord {
+    /// Makes a new record. Its number is assigned when it's saved.
+    pub fn new(
+        channel: &str,
+        user_login: &str,
+        user_id: &str,
+        action: ModerationAction,
+        reason: &str,
+    ) -> Self {
+        Self {
+            number: 0,
+            channel: channel.to_lowercase(),
+            user_login: user_login.to_lowercase(),
+            user_id: user_id.to_string(),
+            action,
+            message_deleted: false,
+            rule: None,
+            message: None,
+            reason: reason.to_string(),
+            moderator: None,
+            reversed: false,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Saves this record with the next number in its channel.
+    pub async fn save<C: Connection>(&mut self, db: &Surreal<C>) -> Result<(), surrealdb::Error> {
+        self.number = db
+            .query(format!(
+                "SELECT VALUE number FROM {MODERATION_RECORD_TABLE}
+                 WHERE channel = $channel
+                 ORDER BY number DESC
+                 LIMIT 1;"
+            ))
+            .bind(("channel", self.channel.clone()))
+            .await?
+            .take::<Option<u32>>(0)?
+            .map(|n| n + 1)
+            .unwrap_or(1);
+
+        db.query(format!("CREATE {MODERATION_RECORD_TABLE} CONTENT $record;"))
+            .bind(("record", self.clone()))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    /// Gets a channel's record with the given number.
+    pub async fn get<C: Connection>(
+        db: &Surreal<C>,
+        channel: &str,
+        number: u32,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        db.query(format!(
+            "SELECT * FROM {MODERATION_RECORD_TABLE}
+             WHERE channel = $channel AND number = $number;"
+        ))
+        .bind(("channel", channel.to_lowercase()))
+        .bind(("number", number))
+        .await?
+        .take(0)
+    }
+
+    /// Returns a channel's most recent records, newest first.
+    pub async fn get_recent<C: Connection>(
+        db: &Surreal<C>,
+        channel: &str,
+        limit: usize,
+    ) -> Result<Vec<Self>, surrealdb::Error> {
+        db.query(format!(
+            "SELECT * FROM {MODERATION_RECORD_TABLE}
+             WHERE channel = $channel
+             ORDER BY number DESC
+             LIMIT $limit;"
+        ))
+        .bind(("channel", channel.to_lowercase()))
+        .bind(("limit", limit))
+        .await?
+        .take(0)
+    }
+
+    /// Marks this record as undone.
+    pub async fn mark_reversed<C: Connection>(
+        &mut self,
+        db: &Surreal<C>,
+    ) -> Result<(), surrealdb::Error> {
+        db.query(format!(
+            "UPDATE {MODERATION_RECORD_TABLE} SET reversed = true
+             WHERE channel = $channel AND number = $number;"
+        ))
+        .bind(("channel", self.channel.clone()))
+        .bind(("number", self.number))
+        .await?
+        .check()?;
+        self.reversed = true;
+        Ok(())
+    }
+
+    /// Describes this record in a few words, for listing records in chat.
+    pub fn summary(&self) -> String {
+        let mut summary = format!("#{} {} {}", self.number, self.action, self.user_login);
+        if self.reversed {
+            summary.push_str(" (undone)");
+        }
+        summary
+    }
+
+    /// Describes this record in full, for Discord logs.
+    fn log_message(&self) -> String {
+        let mut msg = MessageBuilder::new();
+        msg.push("**")
+            .push_safe(&self.user_login)
+            .push("** in **")
+            .push_safe(&self.channel)
+            .push_line("**'s chat");
+
+        match &self.moderator {
+            Some(moderator) => msg.push("by ").push_line_safe(moderator),
+            None => msg.push_line("by munibot, automatically"),
+        };
+        if let Some(rule) = &self.rule {
+            msg.push("rule: ").push_mono_line_safe(rule);
+        }
+        if let Some(message) = &self.message {
+            msg.push("message: ").push_mono_line_safe(message);
+        }
+        if self.message_deleted {
+            msg.push_line("the message was deleted.");
+        }
+        msg.push("reason: ").push_line_safe(&self.reason);
+        if self.action.is_reversible() {
+            msg.push("undo with ")
+                .push_mono_safe(format!("!autoban undo {}", self.number))
+                .push(" in chat.");
+        }
+        msg.build()
+    }
+}
+
+/// A moderation record to be mirrored to a Discord server's logging channel.
+#[derive(Clone, Debug)]
+pub struct ModerationNotice {
+    pub guild_id: GuildId,
+    pub record: ModerationRecord,
+}
+
+/// Posts moderation records sent from the Twitch integration to the logging
+/// channels of the Discord servers they belong to.
+pub fn start_moderation_log_mirror(
+    logging: Arc<Mutex<LoggingHandler>>,
+    mut notices: UnboundedReceiver<ModerationNotice>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(ModerationNotice { guild_id, record }) = notices.recv().await {
+            if let Err(e) = logging
+                .lock()
+                .await
+                .send_simple_log(guild_id, record.action.title(), &record.log_message())
+                .await
+            {
+                error!("couldn't mirror twitch moderation record to discord: {e}");
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ModerationAction, ModerationRecord};
+    use crate::db::test_db;
+
+    #[tokio::test]
+    async fn test_moderation_records() {
+        let db = test_db().await;
+
+        let mut first =
+            ModerationRecord::new("muni_corn", "Scammer", "1", ModerationAction::Ban, "scam");
+        first.save(&db).await.unwrap();
+        let mut second =
+            ModerationRecord::new("muni_corn", "spammer", "2", ModerationAction::Warn, "spam");
+        second.save(&db).await.unwrap();
+        let mut elsewhere = ModerationRecord::new(
+            "someone_else",
+            "spammer",
+            "2",
+            ModerationAction::Ban,
+            "spam",
+        );
+        elsewhere.save(&db).await.unwrap();
+
+        // records are numbered per channel
+        assert_eq!((first.number, second.number, elsewhere.number), (1, 2, 1));
+
+        let recent = ModerationRecord::get_recent(&db, "muni_corn", 10)
+            .await
+            .unwrap();
+        let numbers: Vec<u32> = recent.iter().map(|r| r.number).collect();
+        assert_eq!(numbers, vec![2, 1]);
+
+        first.mark_reversed(&db).await.unwrap();
+        let first = ModerationRecord::get(&db, "muni_corn", 1)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(first.reversed);
+        assert_eq!(first.user_login, "scammer");
+        assert_eq!(first.summary(), "#1 ban scammer (undone)");
+    }
+}
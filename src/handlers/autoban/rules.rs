@@ -0,0 +1,407 @@
+use std::{fmt::Display, str::FromStr, time::Duration};
+
+use decancer::CuredString;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use surrealdb::{Connection, Surreal};
+
+pub const AUTOBAN_RULE_TABLE: &str = "autoban_rule";
+
+/// Rules that apply everywhere, turned off in a single channel.
+pub const AUTOBAN_DISABLED_RULE_TABLE: &str = "autoban_disabled_rule";
+
+/// How long a timeout lasts when a rule doesn't say.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_mins(10);
+
+/// What part of a chatter a rule looks at, and how.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, poise::ChoiceParameter)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleKind {
+    /// The chatter's login contains a phrase.
+    #[name = "name contains"]
+    NameContains,
+
+    /// The chatter's login matches a regex.
+    #[name = "name regex"]
+    NameRegex,
+
+    /// A chat message contains a phrase.
+    #[name = "message contains"]
+    MessageContains,
+
+    /// A chat message matches a regex.
+    #[name = "message regex"]
+    MessageRegex,
+}
+
+impl RuleKind {
+    /// Returns true if rules of this kind look at chat messages rather than
+    /// logins.
+    pub fn is_message_rule(&self) -> bool {
+        matches!(self, RuleKind::MessageContains | RuleKind::MessageRegex)
+    }
+
+    /// Returns true if rules of this kind use regexes rather than phrases.
+    pub fn is_regex(&self) -> bool {
+        matches!(self, RuleKind::NameRegex | RuleKind::MessageRegex)
+    }
+}
+
+impl FromStr for RuleKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(RuleKind::NameContains),
+            "name-regex" => Ok(RuleKind::NameRegex),
+            "phrase" => Ok(RuleKind::MessageContains),
+            "regex" => Ok(RuleKind::MessageRegex),
+            _ => Err(format!(
+                "{s} isn't a kind of rule. try name, name-regex, phrase, or regex"
+            )),
+        }
+    }
+}
+
+impl Display for RuleKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleKind::NameContains => write!(f, "name"),
+            RuleKind::NameRegex => write!(f, "name-regex"),
+            RuleKind::MessageContains => write!(f, "phrase"),
+            RuleKind::MessageRegex => write!(f, "regex"),
+        }
+    }
+}
+
+/// What happens to a chatter who matches a rule, from least to most harsh.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RuleAction {
+    /// Delete the matching message. Chatters matched by their name alone are
+    /// left alone.
+    Delete,
+
+    /// Send the chatter a warning they have to acknowledge before chatting
+    /// again.
+    Warn,
+
+    /// Time the chatter out.
+    Timeout { duration: Duration },
+
+    /// Ban the chatter.
+    Ban,
+}
+
+impl RuleAction {
+    /// How harsh this action is, so the harshest matching rule wins.
+    fn severity(&self) -> u8 {
+        match self {
+            RuleAction::Delete => 0,
+            RuleAction::Warn => 1,
+            RuleAction::Timeout { .. } => 2,
+            RuleAction::Ban => 3,
+        }
+    }
+}
+
+impl FromStr for RuleAction {
+    type Err = String;
+
+    /// Parses `ban`, `warn`, `delete`, `timeout`, or `timeout:<duration>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "ban" => Ok(RuleAction::Ban),
+            None if s == "warn" => Ok(RuleAction::Warn),
+            None if s == "delete" => Ok(RuleAction::Delete),
+            None if s == "timeout" => Ok(RuleAction::Timeout {
+                duration: DEFAULT_TIMEOUT,
+            }),
+            Some(("timeout", duration)) => humantime::parse_duration(duration)
+                .map(|duration| RuleAction::Timeout { duration })
+                .map_err(|e| format!("{duration} isn't a duration: {e}")),
+            _ => Err(format!(
+                "{s} isn't an action. try ban, warn, delete, timeout, or timeout:<duration>"
+            )),
+        }
+    }
+}
+
+impl Display for RuleAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuleAction::Delete => write!(f, "delete"),
+            RuleAction::Warn => write!(f, "warn"),
+            RuleAction::Timeout { duration } => {
+                write!(f, "timeout:{}", humantime::format_duration(*duration))
+            }
+            RuleAction::Ban => write!(f, "ban"),
+        }
+    }
+}
+
+/// How a rule responds to a match: an action, and whether the matching
+/// message is deleted first. Written as `delete+<action>` when the message is
+/// deleted too.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RuleResponse {
+    pub action: RuleAction,
+    pub delete_message: bool,
+}
+
+impl FromStr for RuleResponse {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("delete+") {
+            Some(action) => Ok(Self {
+                action: action.parse()?,
+                delete_message: true,
+            }),
+            None => Ok(Self {
+                action: s.parse()?,
+                delete_message: false,
+            }),
+        }
+    }
+}
+
+impl Display for RuleResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.delete_message && self.action != RuleAction::Delete {
+            write!(f, "delete+")?;
+        }
+        write!(f, "{}", self.action)
+    }
+}
+
+/// A rule for automatically dealing with unwanted chatters.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct AutoBanRule {
+    /// The channel this rule applies to, or `None` if it applies everywhere.
+    #[serde(default)]
+    pub channel: Option<String>,
+
+    pub kind: RuleKind,
+
+    /// The phrase or regex to look for. Phrases are matched against
+    /// homoglyph-free ("cured") text, and so are regexes.
+    pub pattern: String,
+
+    pub action: RuleAction,
+
+    /// Whether the matching message is deleted before the action is taken.
+    #[serde(default)]
+    pub delete_message: bool,
+
+    /// Why chatters are dealt with when they match this rule.
+    pub reason: String,
+}
+
+impl AutoBanRule {
+    /// Makes a new rule. Phrases are lowercased, since cured text is always
+    /// lowercase. Returns an error message if the rule's regex doesn't work.
+    pub fn new(
+        channel: Option<String>,
+        kind: RuleKind,
+        pattern: &str,
+        response: RuleResponse,
+        reason: Option<String>,
+    ) -> Result<Self, String> {
+        let pattern = if kind.is_regex() {
+            pattern.to_string()
+        } else {
+            pattern.to_lowercase()
+        };
+        let reason = reason.unwrap_or_else(|| format!("matched autoban {kind} rule \"{pattern}\""));
+
+        let rule = Self {
+            channel: channel.map(|c| c.to_lowercase()),
+            kind,
+            pattern,
+            action: response.action,
+            delete_message: response.delete_message,
+            reason,
+        };
+        CompiledRule::new(rule.clone())?;
+        Ok(rule)
+    }
+
+    /// Returns how this rule responds to a match.
+    pub fn response(&self) -> RuleResponse {
+        RuleResponse {
+            action: self.action,
+            delete_message: self.delete_message || self.action == RuleAction::Delete,
+        }
+    }
+
+    /// Returns every rule that applies to a channel, including rules that
+    /// apply everywhere, unless they've been disabled in the channel.
+    pub async fn get_for_channel<C: Connection>(
+        db: &Surreal<C>,
+        channel: &str,
+    ) -> Result<Vec<Self>, surrealdb::Error> {
+        db.query(format!(
+            "SELECT * FROM {AUTOBAN_RULE_TABLE}
+             WHERE channel = $channel
+                OR (type::is::none(channel) AND pattern NOTINSIDE
+                    (SELECT VALUE pattern FROM {AUTOBAN_DISABLED_RULE_TABLE}
+                     WHERE channel = $channel));"
+        ))
+        .bind(("channel", channel.to_lowercase()))
+        .await?
+        .take(0)
+    }
+
+    /// Returns the patterns of the rules that apply everywhere but have been
+    /// disabled in a channel.
+    pub async fn get_disabled<C: Connection>(
+        db: &Surreal<C>,
+        channel: &str,
+    ) -> Result<Vec<String>, surrealdb::Error> {
+        db.query(format!(
+            "SELECT VALUE pattern FROM {AUTOBAN_DISABLED_RULE_TABLE}
+             WHERE channel = $channel;"
+        ))
+        .bind(("channel", channel.to_lowercase()))
+        .await?
+        .take(0)
+    }
+
+    /// Turns off the rules that apply everywhere with the given pattern, just
+    /// for a channel. Returns how many rules were turned off.
+    pub async fn disable<C: Connection>(
+        db: &Surreal<C>,
+        channel: &str,
+        pattern: &str,
+    ) -> Result<usize, surrealdb::Error> {
+        let disabled: Vec<String> = db
+            .query(format!(
+                "LET $patterns = array::distinct(
+                    SELECT VALUE pattern FROM {AUTOBAN_RULE_TABLE}
+                    WHERE type::is::none(channel)
+                        AND (pattern = $pattern OR pattern = string::lowercase($pattern))
+                 );
+                 FOR $disabled IN $patterns {{
+                    UPSERT type::thing('{AUTOBAN_DISABLED_RULE_TABLE}', [$channel, $disabled])
+                        SET channel = $channel, pattern = $disabled;
+                 }};
+                 RETURN $patterns;"
+            ))
+            .bind(("channel", channel.to_lowercase()))
+            .bind(("pattern", pattern.to_string()))
+            .await?
+            .check()?
+            .take(2)?;
+        Ok(disabled.len())
+    }
+
+    /// Turns rules that apply everywhere back on in a channel. Returns how
+    /// many rules were turned back on.
+    pub async fn enable<C: Connection>(
+        db: &Surreal<C>,
+        channel: &str,
+        pattern: &str,
+    ) -> Result<usize, surrealdb::Error> {
+        let enabled: Vec<String> = db
+            .query(format!(
+                "DELETE {AUTOBAN_DISABLED_RULE_TABLE}
+                 WHERE channel = $channel
+                     AND (pattern = $pattern OR pattern = string::lowercase($pattern))
+                 RETURN VALUE $before.pattern;"
+            ))
+            .bind(("channel", channel.to_lowercase()))
+            .bind(("pattern", pattern.to_string()))
+            .await?
+            .take(0)?;
+        Ok(enabled.len())
+    }
+
+    /// Saves this rule.
+    pub async fn add<C: Connection>(&self, db: &Surreal<C>) -> Result<(), surrealdb::Error> {
+        db.query(format!("CREATE {AUTOBAN_RULE_TABLE} CONTENT $rule;"))
+            .bind(("rule", self.clone()))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    /// Removes a channel's rules with the given pattern, returning how many
+    /// were removed. Rules that apply everywhere aren't removed, but they can
+    /// be disabled in the channel with [`AutoBanRule::disable`].
+    pub async fn remove<C: Connection>(
+        db: &Surreal<C>,
+        channel: &str,
+        pattern: &str,
+    ) -> Result<usize, surrealdb::Error> {
+        let removed: Vec<Self> = db
+            .query(format!(
+                "DELETE {AUTOBAN_RULE_TABLE}
+                 WHERE channel = $channel
+                     AND (pattern = $pattern OR pattern = string::lowercase($pattern))
+                 RETURN BEFORE;"
+            ))
+            .bind(("channel", channel.to_lowercase()))
+            .bind(("pattern", pattern.to_string()))
+            .await?
+            .take(0)?;
+        Ok(removed.len())
+    }
+}
+
+/// An [`AutoBanRule`] that's ready to check chatters against.
+#[derive(Clone, Debug)]
+pub struct CompiledRule {
+    pub rule: AutoBanRule,
+    regex: Option<Regex>,
+}
+
+impl CompiledRule {
+    pub fn new(rule: AutoBanRule) -> Result<Self, String> {
+        let regex = if rule.kind.is_regex() {
+            Some(
+                RegexBuilder::new(&rule.pattern)
+                    .case_insensitive(true)
+                    .size_limit(1 << 16)
+                    .build()
+                    .map_err(|e| format!("that regex doesn't work: {e}"))?,
+            )
+        } else {
+            None
+        };
+
+        Ok(Self { rule, regex })
+    }
+
+    /// Returns true if a chatter with the given cured login, who sent the
+    /// given cured message (if any), matches this rule.
+    pub fn matches(&self, login: &CuredString, message: Option<&CuredString>) -> bool {
+        let text = if self.rule.kind.is_message_rule() {
+            match message {
+                Some(message) => message,
+                None => return false,
+            }
+        } else {
+            login
+        };
+
+        match &self.regex {
+            Some(regex) => regex.is_match(text),
+            None => text.contains(&self.rule.pattern),
+        }
+    }
+}
+
+/// Returns the harshest rule that a chatter matches, if any. Between rules
+/// with the same action, one that deletes the message is harsher.
+pub fn harshest_match<'a>(
+    rules: &'a [CompiledRule],
+    login: &CuredString,
+    message: Option<&CuredString>,
+) -> Option<&'a AutoBanRule> {
+    rules
+        .iter()
+        .filter(|r| r.matches(login, message))
+        .map(|r| &r.rule)
+        .max_by_key(|r| (r.action.severity(), r.delete_message))
+}
@@ -7,14 +7,25 @@ use crate::{
     twitch::{
         agent::TwitchAgent,
         bot::MuniBotTwitchIRCClient,
+        commands::TwitchCommand,
         handler::{TwitchHandlerError, TwitchMessageHandler},
     },
 };
 
+const BONK: TwitchCommand = TwitchCommand::new("bonk");
+
 pub struct BonkHandler;
 
 #[async_trait]
 impl TwitchMessageHandler for BonkHandler {
+    fn twitch_handler_name(&self) -> &'static str {
+        "bonk"
+    }
+
+    fn twitch_commands(&self) -> &'static [TwitchCommand] {
+        &[BONK]
+    }
+
     async fn handle_twitch_message(
         &mut self,
         message: &ServerMessage,
@@ -1,38 +1,197 @@
-use std::collections::HashSet;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
 
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::error;
+use poise::serenity_prelude::MessageBuilder;
+use serde::{Deserialize, Serialize};
+use surrealdb::{Connection, RecordId, Surreal};
+use tokio::sync::{mpsc::UnboundedSender, Mutex};
 use twitch_irc::message::ServerMessage;
 
 use crate::{
     config::Config,
+    db::{DbItem, MuniBotDb},
+    discord::relay::DiscordRelayMessage,
     twitch::{
         agent::TwitchAgent,
         bot::MuniBotTwitchIRCClient,
+        commands::TwitchCommand,
         handler::{TwitchHandlerError, TwitchMessageHandler},
     },
 };
 
+const CONTENT_WARNING_TABLE: &str = "content_warning";
+
+/// A content/trigger warning issued for a Twitch channel's stream. Warnings
+/// are saved so they survive restarts mid-stream.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ContentWarning {
+    channel_login: String,
+    pub warning: String,
+
+    /// When the warning stops being in effect on its own, if ever.
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[async_trait]
+impl<C: Connection> DbItem<C> for ContentWarning {
+    type GetQuery = String;
+    type Id = String;
+    type UpsertContent = Self;
+
+    const NAME: &'static str = CONTENT_WARNING_TABLE;
+
+    fn get_id(&self) -> Self::Id {
+        self.channel_login.clone()
+    }
+
+    async fn get_from_db(
+        db: &Surreal<C>,
+        channel_login: Self::GetQuery,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        let mut result = db
+            .query("SELECT * FROM $thing;")
+            .bind((
+                "thing",
+                RecordId::from_table_key(CONTENT_WARNING_TABLE, channel_login),
+            ))
+            .await?;
+
+        result.take::<Option<Self>>(0)
+    }
+}
+
+impl ContentWarning {
+    pub fn new(channel_login: &str, warning: &str, duration: Option<Duration>) -> Self {
+        Self {
+            channel_login: channel_login.to_lowercase(),
+            warning: warning.to_string(),
+            expires_at: duration
+                .and_then(|d| chrono::Duration::from_std(d).ok())
+                .map(|d| Utc::now() + d),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= Utc::now())
+    }
+
+    /// Returns the warning in effect in the channel, if there is one. Expired
+    /// warnings are cleaned up.
+    pub async fn get_active<C: Connection>(
+        db: &Surreal<C>,
+        channel_login: &str,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        match Self::get_from_db(db, channel_login.to_lowercase()).await? {
+            Some(warning) if warning.is_expired() => {
+                warning.delete_from_db(db).await?;
+                Ok(None)
+            }
+            warning => Ok(warning),
+        }
+    }
+}
+
+/// Splits an optional duration off the front of a `!cw` command, like
+/// `!cw 2h spiders`.
+fn parse_warning(content: &str) -> (Option<Duration>, &str) {
+    if let Some((first, rest)) = content.split_once(char::is_whitespace)
+        && let Ok(duration) = humantime::parse_duration(first)
+        && !rest.trim().is_empty()
+    {
+        (Some(duration), rest.trim())
+    } else {
+        (None, content.trim())
+    }
+}
+
+const CONTENT_WARNING: TwitchCommand = TwitchCommand::new("cw").aliases(&["tw"]);
+
+/// Keeps track of the content/trigger warnings for streams. Clones share the
+/// same state, so warnings can also be issued from channel point redemptions.
+#[derive(Clone)]
 pub struct ContentWarningHandler {
-    active_warning: Option<String>,
-    users_greeted: HashSet<String>,
+    db: MuniBotDb,
+    discord_messages: UnboundedSender<DiscordRelayMessage>,
+
+    /// The warning in effect in each channel, by channel login. Channels are
+    /// loaded from the database the first time they're needed.
+    active: Arc<Mutex<HashMap<String, Option<ContentWarning>>>>,
+
+    /// Chatters already told about the active warning, by channel login.
+    users_greeted: Arc<Mutex<HashMap<String, HashSet<String>>>>,
 }
 
 impl ContentWarningHandler {
-    #[allow(dead_code)]
-    pub fn new() -> Self {
+    pub fn new(db: MuniBotDb, discord_messages: UnboundedSender<DiscordRelayMessage>) -> Self {
         Self {
-            active_warning: None,
-            users_greeted: HashSet::new(),
+            db,
+            discord_messages,
+            active: Arc::new(Mutex::new(HashMap::new())),
+            users_greeted: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Returns the warning in effect in the channel, if there is one. Expired
+    /// warnings are cleaned up, and Discord is told they're over.
+    async fn active_warning(
+        &self,
+        config: &Config,
+        channel: &str,
+    ) -> Result<Option<ContentWarning>, TwitchHandlerError> {
+        let mut active = self.active.lock().await;
+        let warning = match active.get(channel) {
+            Some(warning) => warning.clone(),
+            None => ContentWarning::get_from_db(&self.db, channel.to_string()).await?,
+        };
+
+        if let Some(expired) = warning.as_ref().filter(|warning| warning.is_expired()) {
+            expired.delete_from_db(&self.db).await?;
+            active.insert(channel.to_string(), None);
+            self.mirror_to_discord(
+                config,
+                channel,
+                format!("the content/trigger warning for {channel}'s stream has expired."),
+            );
+            return Ok(None);
+        }
+
+        active.insert(channel.to_string(), warning.clone());
+        Ok(warning)
+    }
+
+    /// Posts to the Discord announcement channel linked to the Twitch channel,
+    /// if there is one.
+    fn mirror_to_discord(&self, config: &Config, channel_login: &str, content: String) {
+        if let Some(channel_id) = config
+            .twitch
+            .channels
+            .get(channel_login)
+            .and_then(|channel| channel.discord_announcements)
+        {
+            // if discord isn't running, there's nobody to tell anyway
+            let _ = self.discord_messages.send(DiscordRelayMessage {
+                channel_id,
+                content,
+            });
         }
     }
 
     async fn say_user_requested_warning(
         &mut self,
         client: &MuniBotTwitchIRCClient,
+        config: &Config,
         channel: &str,
         addressee: &str,
     ) -> Result<(), TwitchHandlerError> {
-        if let Some(warning) = &self.active_warning {
+        if let Some(ContentWarning { warning, .. }) = self.active_warning(config, channel).await? {
             self.send_twitch_message(client, channel, &format!("hey {}, muni has issued a content/trigger warning for this stream: {}. please take care of yourself! it's okay to leave or mute if this content will make you uncomfortable. and you are loved no matter what!", addressee, warning)).await
         } else {
             self.send_twitch_message(client, channel, &format!("hey {}, there is no active content/trigger warning in effect. enjoy the stream ^-^ if current conversation is making you uncomfortable, you can use the 'subject change /srs' redeem to change the subject!", addressee)).await
@@ -42,9 +201,10 @@ impl ContentWarningHandler {
     async fn say_streamer_requested_warning(
         &mut self,
         client: &MuniBotTwitchIRCClient,
+        config: &Config,
         channel: &str,
     ) -> Result<(), TwitchHandlerError> {
-        if let Some(warning) = &self.active_warning {
+        if let Some(ContentWarning { warning, .. }) = self.active_warning(config, channel).await? {
             self.send_twitch_message(
                 client,
                 channel,
@@ -67,34 +227,155 @@ impl ContentWarningHandler {
     async fn greet_user(
         &mut self,
         client: &MuniBotTwitchIRCClient,
+        config: &Config,
         channel: &str,
         user_name: &str,
     ) -> Result<(), TwitchHandlerError> {
-        if !self.users_greeted.contains(user_name)
-            && let Some(warning) = &self.active_warning
-        {
+        let Some(ContentWarning { warning, .. }) = self.active_warning(config, channel).await?
+        else {
+            return Ok(());
+        };
+        let is_new = self
+            .users_greeted
+            .lock()
+            .await
+            .entry(channel.to_string())
+            .or_default()
+            .insert(user_name.to_string());
+
+        if is_new {
             self.send_twitch_message(client, channel, &format!("welcome, {}! just so you know, muni has issued a content/trigger warning for this stream: {}. please take care of yourself! it's okay to leave or mute if this content will make you uncomfortable. and you are loved no matter what!", user_name, warning)).await?;
-            self.users_greeted.insert(user_name.to_string());
         }
 
         Ok(())
     }
-}
 
-impl Default for ContentWarningHandler {
-    fn default() -> Self {
-        Self::new()
+    async fn issue_warning(
+        &mut self,
+        client: &MuniBotTwitchIRCClient,
+        config: &Config,
+        channel: &str,
+        warning: &str,
+        duration: Option<Duration>,
+    ) -> Result<(), TwitchHandlerError> {
+        let content_warning = ContentWarning::new(channel, warning, duration);
+        content_warning
+            .upsert_in_db(&self.db, content_warning.clone())
+            .await?;
+        self.active
+            .lock()
+            .await
+            .insert(channel.to_string(), Some(content_warning.clone()));
+        self.users_greeted.lock().await.remove(channel);
+
+        // let discord know when the warning is over, unless it's replaced or
+        // cleared first
+        if let Some(duration) = duration {
+            let handler = self.clone();
+            let config = config.clone();
+            let channel = channel.to_string();
+            tokio::spawn(async move {
+                tokio::time::sleep(duration).await;
+                if let Err(e) = handler.active_warning(&config, &channel).await {
+                    error!("couldn't expire the content warning in {channel} :( {e}");
+                }
+            });
+        }
+
+        let expiry = duration
+            .map(|d| format!(" for the next {}", humantime::format_duration(d)))
+            .unwrap_or_default();
+        self.send_twitch_message(
+            client,
+            channel,
+            &format!(
+                "okay! issued a content/trigger warning{} with the following reason: \"{}\"",
+                expiry, warning
+            ),
+        )
+        .await?;
+
+        let until = content_warning
+            .expires_at
+            .map(|t| format!(" (until <t:{}:t>)", t.timestamp()))
+            .unwrap_or_default();
+        // the warning can come from a viewer's redemption, so it's escaped
+        self.mirror_to_discord(
+            config,
+            channel,
+            MessageBuilder::new()
+                .push("**content/trigger warning** for ")
+                .push_safe(channel)
+                .push(format!("'s stream{until}: "))
+                .push_safe(warning)
+                .build(),
+        );
+        Ok(())
+    }
+
+    async fn clear_warning(
+        &mut self,
+        client: &MuniBotTwitchIRCClient,
+        config: &Config,
+        channel: &str,
+    ) -> Result<(), TwitchHandlerError> {
+        ContentWarning::new(channel, "", None)
+            .delete_from_db(&self.db)
+            .await?;
+        self.active.lock().await.insert(channel.to_string(), None);
+        self.send_twitch_message(
+            client,
+            channel,
+            "okay! content/trigger warning has been cleared.",
+        )
+        .await?;
+
+        self.mirror_to_discord(
+            config,
+            channel,
+            format!("the content/trigger warning for {channel}'s stream has been cleared."),
+        );
+        Ok(())
+    }
+
+    /// Clears the active warning if there is one, or issues the given warning
+    /// otherwise. Returns false if there was no warning to clear and the given
+    /// one is empty.
+    pub async fn toggle_warning(
+        &mut self,
+        client: &MuniBotTwitchIRCClient,
+        config: &Config,
+        channel: &str,
+        warning: &str,
+    ) -> Result<bool, TwitchHandlerError> {
+        if self.active_warning(config, channel).await?.is_some() {
+            self.clear_warning(client, config, channel).await?;
+        } else if warning.trim().is_empty() {
+            return Ok(false);
+        } else {
+            self.issue_warning(client, config, channel, warning.trim(), None)
+                .await?;
+        }
+        Ok(true)
     }
 }
 
 #[async_trait]
 impl TwitchMessageHandler for ContentWarningHandler {
+    fn twitch_handler_name(&self) -> &'static str {
+        "content_warning"
+    }
+
+    fn twitch_commands(&self) -> &'static [TwitchCommand] {
+        &[CONTENT_WARNING]
+    }
+
     async fn handle_twitch_message(
         &mut self,
         message: &ServerMessage,
         client: &MuniBotTwitchIRCClient,
         _agent: &TwitchAgent,
-        _config: &Config,
+        config: &Config,
     ) -> Result<bool, TwitchHandlerError> {
         let handled = match message {
             ServerMessage::Privmsg(m) => {
@@ -106,28 +387,27 @@ impl TwitchMessageHandler for ContentWarningHandler {
                 {
                     if m.sender.login == m.channel_login {
                         if content.trim().is_empty() {
-                            self.say_streamer_requested_warning(client, &m.channel_login)
+                            self.say_streamer_requested_warning(client, config, &m.channel_login)
                                 .await?;
                         } else if content == "clear" || content == "reset" {
-                            self.active_warning = None;
-                            self.send_twitch_message(
-                                client,
-                                &m.channel_login,
-                                "okay! content/trigger warning has been cleared.",
-                            )
-                            .await?;
+                            self.clear_warning(client, config, &m.channel_login).await?;
                         } else {
-                            self.active_warning = Some(content.to_string());
-                            self.users_greeted.clear();
-                            self.send_twitch_message(client, &m.channel_login, &format!("okay! issued a content/trigger warning with the following reason: \"{}\"", content)).await?;
+                            let (duration, warning) = parse_warning(content);
+                            self.issue_warning(client, config, &m.channel_login, warning, duration)
+                                .await?;
                         }
                     } else {
-                        self.say_user_requested_warning(client, &m.channel_login, &m.sender.name)
-                            .await?;
+                        self.say_user_requested_warning(
+                            client,
+                            config,
+                            &m.channel_login,
+                            &m.sender.name,
+                        )
+                        .await?;
                     }
                     true
                 } else {
-                    self.greet_user(client, &m.channel_login, &m.sender.name)
+                    self.greet_user(client, config, &m.channel_login, &m.sender.name)
                         .await?;
                     false
                 }
@@ -138,3 +418,95 @@ impl TwitchMessageHandler for ContentWarningHandler {
         Ok(handled)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use poise::serenity_prelude::ChannelId;
+    use tokio::sync::mpsc;
+
+    use super::{parse_warning, ContentWarning, ContentWarningHandler};
+    use crate::{
+        config::{Config, TwitchChannelConfig},
+        db::{test_db, DbItem},
+    };
+
+    #[test]
+    fn test_parse_warning() {
+        assert_eq!(
+            parse_warning("2h spiders and snakes"),
+            (Some(Duration::from_secs(7200)), "spiders and snakes")
+        );
+        assert_eq!(parse_warning("spiders"), (None, "spiders"));
+        assert_eq!(parse_warning("30m"), (None, "30m"));
+    }
+
+    #[tokio::test]
+    async fn test_active_warnings() {
+        let db = test_db().await;
+        assert!(ContentWarning::get_active(&db, "muni_corn")
+            .await
+            .unwrap()
+            .is_none());
+
+        let warning = ContentWarning::new("Muni_Corn", "spiders", None);
+        warning.upsert_in_db(&db, warning.clone()).await.unwrap();
+        let active = ContentWarning::get_active(&db, "muni_corn").await.unwrap();
+        assert_eq!(active.unwrap().warning, "spiders");
+
+        // expired warnings go away on their own
+        let mut expired = ContentWarning::new("muni_corn", "snakes", None);
+        expired.expires_at = Some(chrono::Utc::now() - chrono::Duration::minutes(1));
+        expired.upsert_in_db(&db, expired.clone()).await.unwrap();
+        assert!(ContentWarning::get_active(&db, "muni_corn")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(ContentWarning::get_from_db(&db, "muni_corn".to_string())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_expired_warnings_are_announced() {
+        let db = test_db().await;
+        let (discord_tx, mut discord_rx) = mpsc::unbounded_channel();
+        let handler = ContentWarningHandler::new(db.clone(), discord_tx);
+
+        let mut config = Config::default();
+        config.twitch.channels.insert(
+            "muni_corn".to_string(),
+            TwitchChannelConfig {
+                discord_announcements: Some(ChannelId::new(1)),
+                ..Default::default()
+            },
+        );
+
+        let mut expired = ContentWarning::new("muni_corn", "spiders", None);
+        expired.expires_at = Some(chrono::Utc::now() - chrono::Duration::minutes(1));
+        expired.upsert_in_db(&db, expired.clone()).await.unwrap();
+
+        assert!(handler
+            .active_warning(&config, "muni_corn")
+            .await
+            .unwrap()
+            .is_none());
+        let notice = discord_rx.try_recv().unwrap();
+        assert_eq!(notice.channel_id, ChannelId::new(1));
+        assert!(notice.content.contains("expired"));
+        assert!(ContentWarning::get_from_db(&db, "muni_corn".to_string())
+            .await
+            .unwrap()
+            .is_none());
+
+        // it's only announced once
+        assert!(handler
+            .active_warning(&config, "muni_corn")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(discord_rx.try_recv().is_err());
+    }
+}
@@ -0,0 +1,651 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use poise::serenity_prelude::{Context, CreateAllowedMentions, CreateMessage, FullEvent};
+use rand::seq::SliceRandom;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use surrealdb::{Connection, Surreal};
+use twitch_irc::message::{PrivmsgMessage, ServerMessage};
+
+use crate::{
+    config::Config,
+    db::MuniBotDb,
+    discord::{
+        handler::{DiscordEventHandler, DiscordHandlerError},
+        utils::display_name_from_message,
+        DiscordFrameworkContext,
+    },
+    twitch::{
+        agent::TwitchAgent,
+        bot::MuniBotTwitchIRCClient,
+        commands::{CommandCooldowns, Cooldown, TwitchCommand},
+        handler::{TwitchHandlerError, TwitchMessageHandler},
+        utils::{is_moderator, ChatRole},
+    },
+};
+
+const CUSTOM_COMMAND_TABLE: &str = "custom_command";
+
+/// Names that can't be used for custom commands, since they manage them.
+const RESERVED_NAMES: [&str; 3] = ["addcmd", "editcmd", "delcmd"];
+
+const USAGE: &str =
+    "usage: !addcmd/!editcmd !name [role=<role>] [cooldown=<duration>] <response>, or !delcmd !name";
+
+static VARIABLE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\{([^{}]+)\}").unwrap());
+
+/// A chat command made by a channel's moderators. Responses can use these
+/// variables:
+///
+/// - `{sender}`: whoever used the command
+/// - `{target}`: the first word after the command, or the sender if there
+///   isn't one
+/// - `{count}`: how many times the command has been used
+/// - `{random:a|b|c}`: one of the given options, picked at random
+/// - `{channel.game}`: what the channel is streaming
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CustomCommand {
+    /// The login of the Twitch channel this command belongs to.
+    pub channel: String,
+
+    /// The name of the command, without the `!`.
+    pub name: String,
+
+    pub response: String,
+
+    /// Who can use the command.
+    #[serde(default)]
+    pub role: ChatRole,
+
+    /// How long after being used the command can be used again.
+    #[serde(default)]
+    pub cooldown: Duration,
+
+    /// How many times the command has been used.
+    #[serde(default)]
+    pub uses: u64,
+}
+
+impl CustomCommand {
+    pub fn new(channel: &str, definition: CommandDefinition) -> Self {
+        Self {
+            channel: channel.to_lowercase(),
+            name: definition.name,
+            response: definition.response,
+            role: definition.role.unwrap_or_default(),
+            cooldown: definition.cooldown.unwrap_or_default(),
+            uses: 0,
+        }
+    }
+
+    /// Gets a channel's command by name.
+    pub async fn get<C: Connection>(
+        db: &Surreal<C>,
+        channel: &str,
+        name: &str,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        Self::get_from_channels(db, &[channel], name).await
+    }
+
+    /// Gets a command by name from any of the given channels.
+    pub async fn get_from_channels<C: Connection>(
+        db: &Surreal<C>,
+        channels: &[&str],
+        name: &str,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        let channels: Vec<String> = channels.iter().map(|c| c.to_lowercase()).collect();
+        db.query(format!(
+            "SELECT * FROM {CUSTOM_COMMAND_TABLE}
+             WHERE channel IN $channels AND name = $name
+             LIMIT 1;"
+        ))
+        .bind(("channels", channels))
+        .bind(("name", name.to_lowercase()))
+        .await?
+        .take(0)
+    }
+
+    /// Saves this command.
+    pub async fn add<C: Connection>(&self, db: &Surreal<C>) -> Result<(), surrealdb::Error> {
+        db.query(format!("CREATE {CUSTOM_COMMAND_TABLE} CONTENT $command;"))
+            .bind(("command", self.clone()))
+            .await?
+            .check()?;
+        Ok(())
+    }
+
+    /// Saves changes to this command's response, role and cooldown.
+    pub async fn update<C: Connection>(&self, db: &Surreal<C>) -> Result<(), surrealdb::Error> {
+        db.query(format!(
+            "UPDATE {CUSTOM_COMMAND_TABLE}
+             MERGE {{ response: $response, role: $role, cooldown: $cooldown }}
+             WHERE channel = $channel AND name = $name;"
+        ))
+        .bind(("response", self.response.clone()))
+        .bind(("role", self.role))
+        .bind(("cooldown", self.cooldown))
+        .bind(("channel", self.channel.clone()))
+        .bind(("name", self.name.clone()))
+        .await?
+        .check()?;
+        Ok(())
+    }
+
+    /// Removes a channel's command, returning true if it existed.
+    pub async fn remove<C: Connection>(
+        db: &Surreal<C>,
+        channel: &str,
+        name: &str,
+    ) -> Result<bool, surrealdb::Error> {
+        let removed: Vec<Self> = db
+            .query(format!(
+                "DELETE {CUSTOM_COMMAND_TABLE}
+                 WHERE channel = $channel AND name = $name
+                 RETURN BEFORE;"
+            ))
+            .bind(("channel", channel.to_lowercase()))
+            .bind(("name", name.to_lowercase()))
+            .await?
+            .take(0)?;
+        Ok(!removed.is_empty())
+    }
+
+    /// Counts a use of this command, returning how many times it's been used
+    /// now.
+    pub async fn record_use<C: Connection>(
+        &self,
+        db: &Surreal<C>,
+    ) -> Result<u64, surrealdb::Error> {
+        let uses: Option<u64> = db
+            .query(format!(
+                "UPDATE {CUSTOM_COMMAND_TABLE} SET uses += 1
+                 WHERE channel = $channel AND name = $name
+                 RETURN VALUE uses;"
+            ))
+            .bind(("channel", self.channel.clone()))
+            .bind(("name", self.name.clone()))
+            .await?
+            .take(0)?;
+        Ok(uses.unwrap_or(self.uses + 1))
+    }
+}
+
+/// What a moderator asked for with `!addcmd` or `!editcmd`. Options that
+/// weren't given are `None`, and so is an empty response.
+#[derive(Debug, PartialEq)]
+pub struct CommandDefinition {
+    pub name: String,
+    pub role: Option<ChatRole>,
+    pub cooldown: Option<Duration>,
+    pub response: String,
+}
+
+/// Parses `!name [role=<role>] [cooldown=<duration>] <response>`, returning
+/// an error message if something's off.
+fn parse_definition(text: &str) -> Result<CommandDefinition, String> {
+    let mut words = text.split_whitespace().peekable();
+    let name = words
+        .next()
+        .map(|name| name.trim_start_matches('!').to_lowercase())
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| USAGE.to_string())?;
+    if !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        return Err(format!("!{name} isn't a valid name for a command."));
+    }
+    if RESERVED_NAMES.contains(&name.as_str()) {
+        return Err(format!("!{name} can't be used as a custom command."));
+    }
+
+    let mut role = None;
+    let mut cooldown = None;
+    while let Some(word) = words.peek() {
+        if let Some(r) = word.strip_prefix("role=") {
+            role = Some(r.parse()?);
+        } else if let Some(c) = word.strip_prefix("cooldown=") {
+            cooldown = Some(
+                humantime::parse_duration(c).map_err(|e| format!("{c} isn't a duration: {e}"))?,
+            );
+        } else {
+            break;
+        }
+        words.next();
+    }
+
+    Ok(CommandDefinition {
+        name,
+        role,
+        cooldown,
+        response: words.collect::<Vec<_>>().join(" "),
+    })
+}
+
+/// Splits a message like `!hug @someone` into the command name and its
+/// target, if it looks like a command.
+fn parse_invocation(text: &str) -> Option<(String, Option<&str>)> {
+    let mut words = text.strip_prefix('!')?.split_whitespace();
+    let name = words.next()?.to_lowercase();
+    let target = words.next().map(|t| t.trim_start_matches('@'));
+    Some((name, target))
+}
+
+/// What a command's response variables are filled in with.
+struct TemplateContext<'a> {
+    sender: &'a str,
+    target: &'a str,
+    count: u64,
+    game: Option<&'a str>,
+}
+
+/// Fills in the variables in a command's response. Variables that aren't
+/// known are left alone.
+fn render(template: &str, context: &TemplateContext) -> String {
+    VARIABLE_REGEX
+        .replace_all(template, |caps: &Captures| match &caps[1] {
+            "sender" => context.sender.to_string(),
+            "target" => context.target.to_string(),
+            "count" => context.count.to_string(),
+            "channel.game" => context.game.unwrap_or("something").to_string(),
+            variable => variable
+                .strip_prefix("random:")
+                .and_then(|options| {
+                    options
+                        .split('|')
+                        .collect::<Vec<_>>()
+                        .choose(&mut rand::thread_rng())
+                        .map(|option| option.to_string())
+                })
+                .unwrap_or_else(|| caps[0].to_string()),
+        })
+        .into_owned()
+}
+
+/// Serves custom commands in Twitch chat and on Discord, and lets Twitch
+/// moderators manage them with `!addcmd`, `!editcmd` and `!delcmd`.
+///
+/// On Discord, the commands of the Twitch channels linked to the server (see
+/// `discord_guild` in the channel config) are served. Discord has no
+/// subscribers or VIPs, so only commands everyone can use work there.
+pub struct CustomCommandHandler {
+    db: MuniBotDb,
+    cooldowns: CommandCooldowns,
+
+    /// Names of munibot's own chat commands, which custom commands would be
+    /// shadowed by.
+    builtin_commands: Vec<&'static str>,
+}
+
+impl CustomCommandHandler {
+    pub fn new(db: MuniBotDb) -> Self {
+        Self {
+            db,
+            cooldowns: CommandCooldowns::default(),
+            builtin_commands: Vec::new(),
+        }
+    }
+
+    /// Keeps custom commands from being added with the names or aliases of
+    /// the given built-in commands.
+    pub fn with_builtin_commands<'a>(
+        mut self,
+        commands: impl IntoIterator<Item = &'a TwitchCommand>,
+    ) -> Self {
+        self.builtin_commands = commands
+            .into_iter()
+            .flat_map(|command| {
+                std::iter::once(command.name).chain(command.aliases.iter().copied())
+            })
+            .collect();
+        self
+    }
+
+    /// Handles `!addcmd`, `!editcmd` and `!delcmd` from moderators. Returns
+    /// true if the message was one of them.
+    async fn handle_management(
+        &mut self,
+        m: &PrivmsgMessage,
+        client: &MuniBotTwitchIRCClient,
+    ) -> Result<bool, TwitchHandlerError> {
+        let (action, rest) = m
+            .message_text
+            .split_once(char::is_whitespace)
+            .unwrap_or((&m.message_text, ""));
+        if !matches!(action, "!addcmd" | "!editcmd" | "!delcmd") || !is_moderator(m) {
+            return Ok(false);
+        }
+
+        let reply = match parse_definition(rest) {
+            Err(e) => e,
+            Ok(definition) => match action {
+                "!addcmd" => {
+                    if self.builtin_commands.contains(&definition.name.as_str()) {
+                        format!(
+                            "!{} is one of my own commands, so it can't be a custom command.",
+                            definition.name
+                        )
+                    } else if CustomCommand::get(&self.db, &m.channel_login, &definition.name)
+                        .await?
+                        .is_some()
+                    {
+                        format!(
+                            "!{} already exists. use !editcmd to change it.",
+                            definition.name
+                        )
+                    } else if definition.response.is_empty() {
+                        USAGE.to_string()
+                    } else {
+                        let command = CustomCommand::new(&m.channel_login, definition);
+                        command.add(&self.db).await?;
+                        format!("added !{}!", command.name)
+                    }
+                }
+                "!editcmd" => {
+                    match CustomCommand::get(&self.db, &m.channel_login, &definition.name).await? {
+                        None => format!("there's no !{} command here.", definition.name),
+                        Some(mut command) => {
+                            if !definition.response.is_empty() {
+                                command.response = definition.response;
+                            }
+                            if let Some(role) = definition.role {
+                                command.role = role;
+                            }
+                            if let Some(cooldown) = definition.cooldown {
+                                command.cooldown = cooldown;
+                            }
+                            command.update(&self.db).await?;
+                            format!("updated !{}!", command.name)
+                        }
+                    }
+                }
+                _ => {
+                    if CustomCommand::remove(&self.db, &m.channel_login, &definition.name).await? {
+                        format!("deleted !{}!", definition.name)
+                    } else {
+                        format!("there's no !{} command here.", definition.name)
+                    }
+                }
+            },
+        };
+
+        self.send_twitch_message(
+            client,
+            &m.channel_login,
+            &format!("@{} {reply}", m.sender.name),
+        )
+        .await?;
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl TwitchMessageHandler for CustomCommandHandler {
+    fn twitch_handler_name(&self) -> &'static str {
+        "custom_commands"
+    }
+
+    async fn handle_twitch_message(
+        &mut self,
+        message: &ServerMessage,
+        client: &MuniBotTwitchIRCClient,
+        agent: &TwitchAgent,
+        _config: &Config,
+    ) -> Result<bool, TwitchHandlerError> {
+        let ServerMessage::Privmsg(m) = message else {
+            return Ok(false);
+        };
+        if self.handle_management(m, client).await? {
+            return Ok(true);
+        }
+
+        let Some((name, target)) = parse_invocation(&m.message_text) else {
+            return Ok(false);
+        };
+        let Some(command) = CustomCommand::get(&self.db, &m.channel_login, &name).await? else {
+            return Ok(false);
+        };
+
+        // chatters who can't use the command, or use it too soon, are ignored
+        if ChatRole::of(m) < command.role
+            || !self
+                .cooldowns
+                .is_ready(&command.channel, &command.name, &m.sender.login)
+        {
+            return Ok(true);
+        }
+        self.cooldowns.start(
+            &command.channel,
+            &command.name,
+            &m.sender.login,
+            Cooldown::global(command.cooldown),
+        );
+
+        let game = if command.response.contains("{channel.game}") {
+            agent
+                .get_channel_info(&m.channel_id)
+                .await?
+                .map(|channel| channel.game_name.to_string())
+        } else {
+            None
+        };
+        let count = command.record_use(&self.db).await?;
+        let response = render(
+            &command.response,
+            &TemplateContext {
+                sender: &m.sender.name,
+                target: target.unwrap_or(&m.sender.name),
+                count,
+                game: game.as_deref(),
+            },
+        );
+
+        self.send_twitch_message(client, &m.channel_login, &response)
+            .await?;
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl DiscordEventHandler for CustomCommandHandler {
+    fn name(&self) -> &'static str {
+        "custom_commands"
+    }
+
+    async fn handle_discord_event(
+        &mut self,
+        context: &Context,
+        framework: DiscordFrameworkContext<'_>,
+        event: &FullEvent,
+    ) -> Result<(), DiscordHandlerError> {
+        let FullEvent::Message { new_message: msg } = event else {
+            return Ok(());
+        };
+        let (Some(guild_id), false) = (msg.guild_id, msg.author.bot) else {
+            return Ok(());
+        };
+        let Some((name, target)) = parse_invocation(&msg.content) else {
+            return Ok(());
+        };
+
+        let channels = framework
+            .user_data
+            .twitch_config()
+            .channels_for_guild(guild_id);
+        if channels.is_empty() {
+            return Ok(());
+        }
+        let Some(command) = CustomCommand::get_from_channels(&self.db, &channels, &name)
+            .await
+            .map_err(|e| DiscordHandlerError::from_display(self.name(), e))?
+        else {
+            return Ok(());
+        };
+        let user = msg.author.id.to_string();
+        if command.role > ChatRole::Everyone
+            || !self
+                .cooldowns
+                .is_ready(&command.channel, &command.name, &user)
+        {
+            return Ok(());
+        }
+        self.cooldowns.start(
+            &command.channel,
+            &command.name,
+            &user,
+            Cooldown::global(command.cooldown),
+        );
+
+        let count = command
+            .record_use(&self.db)
+            .await
+            .map_err(|e| DiscordHandlerError::from_display(self.name(), e))?;
+        let sender = display_name_from_message(msg, &context.http).await;
+        let response = render(
+            &command.response,
+            &TemplateContext {
+                sender: &sender,
+                target: target.unwrap_or(&sender),
+                count,
+                game: None,
+            },
+        );
+
+        // responses can include what people typed, so they can't ping anyone
+        let reply = CreateMessage::new()
+            .content(response)
+            .allowed_mentions(CreateAllowedMentions::new());
+        msg.channel_id
+            .send_message(&context.http, reply)
+            .await
+            .map_err(|e| DiscordHandlerError::from_display(self.name(), e))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{
+        parse_definition, parse_invocation, render, CommandDefinition, CustomCommand,
+        CustomCommandHandler, TemplateContext,
+    };
+    use crate::{
+        db::test_db,
+        twitch::{commands::TwitchCommand, utils::ChatRole},
+    };
+
+    #[test]
+    fn test_parse_definition() {
+        assert_eq!(
+            parse_definition("!Hug role=sub cooldown=30s {sender} hugs {target}"),
+            Ok(CommandDefinition {
+                name: "hug".to_string(),
+                role: Some(ChatRole::Subscriber),
+                cooldown: Some(Duration::from_secs(30)),
+                response: "{sender} hugs {target}".to_string(),
+            })
+        );
+        assert_eq!(
+            parse_definition("hug"),
+            Ok(CommandDefinition {
+                name: "hug".to_string(),
+                role: None,
+                cooldown: None,
+                response: String::new(),
+            })
+        );
+        assert!(parse_definition("").is_err());
+        assert!(parse_definition("!addcmd oops").is_err());
+        assert!(parse_definition("!hug role=king hi").is_err());
+        assert!(parse_definition("!h.u.g hi").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_builtin_commands() {
+        let handler = CustomCommandHandler::new(test_db().await).with_builtin_commands(&[
+            TwitchCommand::new("so").aliases(&["shoutout"]),
+            TwitchCommand::new("quote"),
+        ]);
+        assert_eq!(handler.builtin_commands, ["so", "shoutout", "quote"]);
+    }
+
+    #[test]
+    fn test_parse_invocation() {
+        assert_eq!(
+            parse_invocation("!Hug @muni_corn please"),
+            Some(("hug".to_string(), Some("muni_corn")))
+        );
+        assert_eq!(parse_invocation("!hug"), Some(("hug".to_string(), None)));
+        assert_eq!(parse_invocation("hug"), None);
+        assert_eq!(parse_invocation("!"), None);
+    }
+
+    #[test]
+    fn test_render() {
+        let context = TemplateContext {
+            sender: "muni_corn",
+            target: "linokii",
+            count: 3,
+            game: None,
+        };
+        assert_eq!(
+            render(
+                "{sender} hugs {target} ({count} hugs, playing {channel.game}) {unknown}",
+                &context
+            ),
+            "muni_corn hugs linokii (3 hugs, playing something) {unknown}"
+        );
+
+        let picked = render("{random:a|b|c}", &context);
+        assert!(["a", "b", "c"].contains(&picked.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_custom_commands() {
+        let db = test_db().await;
+        let command = CustomCommand::new("Muni_Corn", parse_definition("!hug hi").unwrap());
+        command.add(&db).await.unwrap();
+
+        // names are unique per channel
+        assert!(command.add(&db).await.is_err());
+        CustomCommand::new("someone_else", parse_definition("!hug hey").unwrap())
+            .add(&db)
+            .await
+            .unwrap();
+
+        let mut saved = CustomCommand::get(&db, "muni_corn", "HUG")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(saved.response, "hi");
+        assert_eq!(saved.record_use(&db).await.unwrap(), 1);
+        assert_eq!(saved.record_use(&db).await.unwrap(), 2);
+
+        saved.response = "hello".to_string();
+        saved.role = ChatRole::Vip;
+        saved.update(&db).await.unwrap();
+        let updated = CustomCommand::get(&db, "muni_corn", "hug")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(updated.response, "hello");
+        assert_eq!(updated.role, ChatRole::Vip);
+        assert_eq!(updated.uses, 2);
+
+        assert!(CustomCommand::remove(&db, "muni_corn", "hug")
+            .await
+            .unwrap());
+        assert!(!CustomCommand::remove(&db, "muni_corn", "hug")
+            .await
+            .unwrap());
+        assert!(CustomCommand::get(&db, "muni_corn", "hug")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(CustomCommand::get(&db, "someone_else", "hug")
+            .await
+            .unwrap()
+            .is_some());
+    }
+}
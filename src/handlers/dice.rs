@@ -12,22 +12,30 @@ use crate::{
 pub struct DiceHandler;
 
 impl DiceHandler {
+    /// Rolls a die with the given number of sides, returning a number from 1
+    /// to `sides`.
+    pub fn roll(sides: u8) -> u8 {
+        rand::thread_rng().gen_range(1..=sides.max(1))
+    }
+
     /// Returns a prefix, result, and a suffix for the resulting roll message.
     pub fn roll_for_message(sides: u8) -> RollResult {
         match sides {
             0 => RollResult::SingleMessage("what.".to_string()),
             1 => RollResult::SingleMessage("you roll a one-sided die. it's a 1.".to_string()),
-            _ => {
-                let result = rand::thread_rng().gen_range(1..=sides);
-                if sides == 2 {
-                    RollResult::SingleMessage(format!(
-                        "coin flip. it's {}!",
-                        if result == 1 { "heads" } else { "tails" }
-                    ))
-                } else {
-                    number_to_message(result, sides)
-                }
-            }
+            _ => Self::message_for_roll(Self::roll(sides), sides),
+        }
+    }
+
+    /// Returns the message for a roll that has already been made.
+    pub fn message_for_roll(result: u8, sides: u8) -> RollResult {
+        if sides == 2 {
+            RollResult::SingleMessage(format!(
+                "coin flip. it's {}!",
+                if result == 1 { "heads" } else { "tails" }
+            ))
+        } else {
+            number_to_message(result, sides)
         }
     }
 }
@@ -119,7 +127,7 @@ pub enum RollResult {
 }
 
 impl RollResult {
-    fn add_to_message_builder(&self, builder: &mut MessageBuilder) {
+    pub fn add_to_message_builder(&self, builder: &mut MessageBuilder) {
         match self {
             RollResult::SingleMessage(msg) => builder.push(msg),
             RollResult::Full(prefix, result, suffix) => builder
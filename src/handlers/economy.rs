@@ -1,13 +1,24 @@
+use std::time::Instant;
+
 use async_trait::async_trait;
 use num_format::{Locale, ToFormattedString};
-use poise::serenity_prelude::{Context, FullEvent, Message, UserId};
+use poise::serenity_prelude::{Context, CreateEmbed, FullEvent, MessageBuilder, UserId};
 use wallet::Wallet;
 
-use self::wallet::WalletError;
+use self::{
+    leaderboard::{LeaderboardEntry, Rank},
+    ledger::LedgerEntry,
+    owner::WalletOwner,
+    settings::EconomySettings,
+    shop::{InventoryItem, ShopError, ShopItem, ShopItemKind},
+    spam::SpamFilter,
+    wallet::WalletError,
+};
 use crate::{
     discord::{
         commands::{DiscordCommandError, DiscordCommandProvider},
         handler::{DiscordEventHandler, DiscordHandlerError},
+        pagination::paginate_embeds,
         utils::display_name_from_command_context,
         DiscordCommand, DiscordContext, DiscordFrameworkContext,
     },
@@ -15,16 +26,44 @@ use crate::{
     MuniBotError,
 };
 
-mod payout;
-mod wallet;
+pub(crate) mod admin;
+mod betting;
+mod leaderboard;
+pub(crate) mod ledger;
+pub(crate) mod owner;
+pub(crate) mod payout;
+mod settings;
+pub(crate) mod shop;
+mod spam;
+mod twitch;
+pub(crate) mod wallet;
+
+pub use twitch::TwitchEconomyHandler;
+
+/// How many transactions `/history` shows, at most.
+const HISTORY_LENGTH: usize = 50;
+
+/// How many transactions are shown on each page of `/history`.
+const HISTORY_PER_PAGE: usize = 10;
 
-pub struct EconomyProvider;
+/// How many people `/leaderboard` shows, at most.
+const LEADERBOARD_LENGTH: usize = 100;
+
+/// How many people are shown on each page of `/leaderboard`.
+const LEADERBOARD_PER_PAGE: usize = 10;
+
+/// How many items are shown on each page of `/shop` and `/inventory`.
+const ITEMS_PER_PAGE: usize = 10;
+
+#[derive(Default)]
+pub struct EconomyProvider {
+    spam_filter: SpamFilter,
+}
 
 impl EconomyProvider {
-    fn calc_salary(msg: &Message) -> u64 {
+    fn calc_salary(content: &str) -> u64 {
         // determine a salary based on words
-        let valid_char_count: i32 = msg
-            .content
+        let valid_char_count: i32 = content
             .split_whitespace()
             .filter_map(|w| {
                 // ignore words containing symbols
@@ -59,9 +98,36 @@ impl DiscordEventHandler for EconomyProvider {
     ) -> Result<(), DiscordHandlerError> {
         if let FullEvent::Message { new_message } = event {
             let msg = new_message;
+
+            // only real people chatting earn coins
+            if msg.author.bot || msg.author.system || msg.webhook_id.is_some() {
+                return Ok(());
+            }
+
             if let Some(guild_id) = msg.guild_id {
-                let salary = Self::calc_salary(msg);
                 let db = &framework.user_data().await.access().db();
+                let settings = EconomySettings::get_or_default(db, guild_id)
+                    .await
+                    .map_err(|e| DiscordHandlerError {
+                        handler_name: self.name(),
+                        message: format!("error getting economy settings from db: {e}"),
+                    })?;
+                if settings.is_channel_excluded(msg.channel_id) {
+                    return Ok(());
+                }
+
+                let salary = (Self::calc_salary(&msg.content) as f64 * settings.salary_multiplier)
+                    .round() as u64;
+                if salary == 0
+                    || !self.spam_filter.should_pay(
+                        guild_id,
+                        msg.author.id,
+                        &msg.content,
+                        Instant::now(),
+                    )
+                {
+                    return Ok(());
+                }
 
                 Payout::get_from_db(db, guild_id, msg.author.id)
                     .await
@@ -84,7 +150,19 @@ impl DiscordEventHandler for EconomyProvider {
 
 impl DiscordCommandProvider for EconomyProvider {
     fn commands(&self) -> Vec<DiscordCommand> {
-        vec![wallet(), claim(), transfer()]
+        vec![
+            wallet(),
+            claim(),
+            transfer(),
+            history(),
+            leaderboard(),
+            shop(),
+            buy(),
+            inventory(),
+            use_item(),
+            betting::bet(),
+            betting::slots(),
+        ]
     }
 }
 
@@ -96,10 +174,14 @@ async fn wallet(ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
 
         let db = &ctx.data().access().db();
         let wallet = Wallet::get_from_db(db, guild_id, ctx.author().id).await?;
+        let title = InventoryItem::latest_title(db, guild_id, ctx.author().id)
+            .await?
+            .map(|title| format!(", {title}"))
+            .unwrap_or_default();
 
         // send the wallet balance
         ctx.reply(format!(
-            "hey {author_name}! you have **{}** coins in your wallet.",
+            "hey {author_name}{title}! you have **{}** coins in your wallet.",
             wallet.balance().to_formatted_string(&Locale::en)
         ))
         .await?;
@@ -123,8 +205,11 @@ async fn claim(ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
         let db = &ctx.data().access().db();
 
         let mut payout = Payout::get_from_db(db, guild_id, ctx.author().id).await?;
+        let interval = EconomySettings::get_or_default(db, guild_id)
+            .await?
+            .payout_interval;
 
-        let claim_result = payout.claim_to_wallet(db).await;
+        let claim_result = payout.claim_to_wallet(db, interval).await;
 
         match claim_result {
             Ok(ClaimResult {
@@ -140,7 +225,7 @@ async fn claim(ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
                 .await?
             }
             Err(PayoutError::TooSoon) => {
-                let timestamp = payout.next_payout_time().timestamp();
+                let timestamp = payout.next_payout_time(interval).timestamp();
                 ctx.say(format!(
                     "you can't claim your payout yet! you can claim it again <t:{timestamp}:R>."
                 ))
@@ -190,8 +275,11 @@ async fn transfer(
         let mut author_wallet = Wallet::get_from_db(db, guild_id, ctx.author().id).await?;
         let mut recipient_wallet = Wallet::get_from_db(db, guild_id, to).await?;
 
-        // try to spend from the author wallet
-        if let Err(e) = author_wallet.spend(db, amount).await {
+        // move the coins over, all at once
+        if let Err(e) = author_wallet
+            .transfer_to(db, &mut recipient_wallet, amount)
+            .await
+        {
             match e {
                 WalletError::InsufficientFunds => {
                     let message = format!("you want to transfer **{}** coins, but you only have **{}** coins in your wallet :<", amount.to_formatted_string(&Locale::en), author_wallet.balance().to_formatted_string(&Locale::en));
@@ -201,7 +289,7 @@ async fn transfer(
                 }
                 _ => {
                     return Err(DiscordCommandError {
-                        message: format!("error spending from author wallet: {e}"),
+                        message: format!("error transferring coins: {e}"),
                         command_identifier: "transfer".to_string(),
                     }
                     .into())
@@ -209,9 +297,6 @@ async fn transfer(
             }
         }
 
-        // deposit into the recipient wallet
-        recipient_wallet.deposit(db, amount).await?;
-
         // send a confirmation message
         ctx.say(format!(
             "**{}** coins have been transferred to <@{}>! ^w^ you have **{}** coins left.",
@@ -229,3 +314,402 @@ async fn transfer(
         Ok(())
     }
 }
+
+/// see your recent transactions.
+#[poise::command(slash_command, prefix_command)]
+async fn history(ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        ctx.say("this command can only be used in a server! each server has their own economy, so check your history in a server you're in ^w^")
+            .await?;
+        return Ok(());
+    };
+
+    let db = &ctx.data().access().db();
+    let entries = LedgerEntry::get_recent(db, guild_id, ctx.author().id, HISTORY_LENGTH).await?;
+
+    if entries.is_empty() {
+        ctx.say(
+            "you don't have any transactions yet! chat a bit and use /claim to get some coins ^w^",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let author_name = display_name_from_command_context(ctx).await;
+    let pages = entries
+        .chunks(HISTORY_PER_PAGE)
+        .map(|chunk| {
+            let mut msg = MessageBuilder::new();
+            for entry in chunk {
+                msg.push(format!("<t:{}:R> ", entry.created_at.timestamp()))
+                    .push_bold(format!(
+                        "{}{}",
+                        if entry.amount < 0 { "-" } else { "+" },
+                        entry.amount.unsigned_abs().to_formatted_string(&Locale::en)
+                    ))
+                    .push(format!(" {}", entry.kind));
+
+                if let Some(counterparty) = entry
+                    .counterparty
+                    .as_ref()
+                    .and_then(WalletOwner::discord_id)
+                {
+                    msg.push(if entry.amount < 0 { " to " } else { " from " })
+                        .mention(&counterparty);
+                }
+
+                msg.push_line("");
+            }
+
+            CreateEmbed::new()
+                .title(format!("{author_name}'s transactions"))
+                .description(msg.build())
+        })
+        .collect();
+
+    paginate_embeds(ctx, pages).await?;
+
+    Ok(())
+}
+
+/// see who's on top!
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommand_required,
+    subcommands("coins", "weekly")
+)]
+async fn leaderboard(_ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    Ok(())
+}
+
+/// see who has the most coins in this server.
+#[poise::command(slash_command, guild_only)]
+async fn coins(ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let db = &ctx.data().access().db();
+    let entries = leaderboard::top_balances(db, guild_id, LEADERBOARD_LENGTH).await?;
+    let rank = leaderboard::balance_rank(db, guild_id, ctx.author().id).await?;
+
+    send_leaderboard(ctx, "richest in the server", entries, rank).await
+}
+
+/// see who has earned the most coins from chatting this week.
+#[poise::command(slash_command, guild_only)]
+async fn weekly(ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let db = &ctx.data().access().db();
+    let entries = leaderboard::top_weekly_earners(db, guild_id, LEADERBOARD_LENGTH).await?;
+    let rank = leaderboard::weekly_earner_rank(db, guild_id, ctx.author().id).await?;
+
+    send_leaderboard(ctx, "top earners this week", entries, rank).await
+}
+
+/// Sends a leaderboard as pages of embeds, with the author's own rank on
+/// every page.
+async fn send_leaderboard(
+    ctx: DiscordContext<'_>,
+    title: &str,
+    entries: Vec<LeaderboardEntry>,
+    rank: Option<Rank>,
+) -> Result<(), MuniBotError> {
+    if entries.is_empty() {
+        ctx.say("nobody's on this leaderboard yet! go chat a bit ^w^")
+            .await?;
+        return Ok(());
+    }
+
+    let your_rank = match rank {
+        Some(Rank { place, amount }) => format!(
+            "you're **#{place}** with **{}** coins",
+            amount.to_formatted_string(&Locale::en)
+        ),
+        None => "you're not on this leaderboard yet!".to_string(),
+    };
+
+    let pages = entries
+        .chunks(LEADERBOARD_PER_PAGE)
+        .enumerate()
+        .map(|(page, chunk)| {
+            let mut msg = MessageBuilder::new();
+            for (i, entry) in chunk.iter().enumerate() {
+                msg.push_bold(format!("#{} ", page * LEADERBOARD_PER_PAGE + i + 1))
+                    .mention(&entry.user_id)
+                    .push_line(format!(
+                        " with {} coins",
+                        entry.amount.to_formatted_string(&Locale::en)
+                    ));
+            }
+
+            CreateEmbed::new()
+                .title(title)
+                .description(msg.build())
+                .field("your rank", &your_rank, false)
+        })
+        .collect();
+
+    paginate_embeds(ctx, pages).await?;
+
+    Ok(())
+}
+
+/// see what's for sale in this server.
+#[poise::command(slash_command, guild_only)]
+async fn shop(ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let items = ShopItem::get_all(ctx.data().access().db(), guild_id).await?;
+    if items.is_empty() {
+        ctx.say("the shop is empty right now! ask an admin to stock it ^w^")
+            .await?;
+        return Ok(());
+    }
+
+    let pages = items
+        .chunks(ITEMS_PER_PAGE)
+        .map(|chunk| {
+            let mut msg = MessageBuilder::new();
+            for item in chunk {
+                msg.push_bold_safe(&item.name)
+                    .push(format!(
+                        " for {} coins: {}",
+                        item.price.to_formatted_string(&Locale::en),
+                        item.kind
+                    ))
+                    .push_line("");
+                if !item.description.is_empty() {
+                    msg.push_italic_line_safe(&item.description);
+                }
+            }
+
+            CreateEmbed::new()
+                .title("the shop")
+                .description(msg.build())
+        })
+        .collect();
+
+    paginate_embeds(ctx, pages).await?;
+
+    Ok(())
+}
+
+async fn autocomplete_shop_item(ctx: DiscordContext<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Vec::new();
+    };
+
+    let partial = partial.to_lowercase();
+    ShopItem::get_all(ctx.data().access().db(), guild_id)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|item| item.name)
+        .filter(|name| name.to_lowercase().contains(&partial))
+        .take(25)
+        .collect()
+}
+
+/// buy something from the shop.
+#[poise::command(slash_command, guild_only)]
+async fn buy(
+    ctx: DiscordContext<'_>,
+    #[description = "what you want to buy"]
+    #[autocomplete = "autocomplete_shop_item"]
+    item: String,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let db = ctx.data().access().db();
+    let Some(item) = ShopItem::get_by_name(db, guild_id, &item).await? else {
+        ctx.say(
+            MessageBuilder::new()
+                .push("there's nothing called ")
+                .push_bold_safe(&item)
+                .push(" in the shop. check /shop to see what's for sale!")
+                .build(),
+        )
+        .await?;
+        return Ok(());
+    };
+
+    let author_id = ctx.author().id;
+    let mut wallet = Wallet::get_from_db(db, guild_id, author_id).await?;
+    if wallet.balance() < item.price {
+        ctx.say(format!(
+            "that costs **{}** coins, but you only have **{}** coins :<",
+            item.price.to_formatted_string(&Locale::en),
+            wallet.balance().to_formatted_string(&Locale::en)
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    // give the role first, so nobody pays for a role i can't give them
+    let role_id = item.kind.role_id();
+    if let Some(role_id) = role_id {
+        if let Some(member) = ctx.author_member().await
+            && member.roles.contains(&role_id)
+        {
+            ctx.say("you already have that role, silly!").await?;
+            return Ok(());
+        }
+
+        if let Err(e) = ctx
+            .http()
+            .add_member_role(guild_id, author_id, role_id, Some("bought from the shop"))
+            .await
+        {
+            log::warn!("couldn't give shop role {role_id} to {author_id}: {e}");
+            ctx.say("i couldn't give you that role :< an admin might need to check my permissions. you haven't been charged.")
+                .await?;
+            return Ok(());
+        }
+    }
+
+    match item.buy(db, &mut wallet, author_id).await {
+        Ok(()) => {}
+        Err(e) => {
+            // take the role back, since it wasn't paid for
+            if let Some(role_id) = role_id {
+                ctx.http()
+                    .remove_member_role(guild_id, author_id, role_id, Some("shop purchase failed"))
+                    .await?;
+            }
+
+            if let ShopError::Wallet(WalletError::InsufficientFunds) = e {
+                ctx.say("you don't have enough coins for that anymore :<")
+                    .await?;
+                return Ok(());
+            }
+            return Err(e.into());
+        }
+    }
+
+    let mut msg = MessageBuilder::new();
+    msg.push("you bought ")
+        .push_bold_safe(&item.name)
+        .push(format!(
+            " for **{}** coins! ",
+            item.price.to_formatted_string(&Locale::en)
+        ));
+    match &item.kind {
+        ShopItemKind::Role { .. } => msg.push("enjoy your new role ^w^"),
+        ShopItemKind::TemporaryRole { duration, .. } => msg.push(format!(
+            "enjoy your new role for the next {} ^w^",
+            humantime::format_duration(*duration)
+        )),
+        ShopItemKind::Title { .. } => msg.push("check out your new title with /wallet ^w^"),
+        ShopItemKind::Consumable => {
+            msg.push("it's in your /inventory now. /use it whenever you like ^w^")
+        }
+    };
+    msg.push(format!(
+        " you have **{}** coins left.",
+        wallet.balance().to_formatted_string(&Locale::en)
+    ));
+    ctx.say(msg.build()).await?;
+
+    Ok(())
+}
+
+/// see everything you've bought in this server.
+#[poise::command(slash_command, guild_only)]
+async fn inventory(ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let items =
+        InventoryItem::get_for_user(ctx.data().access().db(), guild_id, ctx.author().id).await?;
+    if items.is_empty() {
+        ctx.say("your inventory is empty! check out the /shop ^w^")
+            .await?;
+        return Ok(());
+    }
+
+    let author_name = display_name_from_command_context(ctx).await;
+    let pages = items
+        .chunks(ITEMS_PER_PAGE)
+        .map(|chunk| {
+            let mut msg = MessageBuilder::new();
+            for item in chunk {
+                msg.push(format!("<t:{}:d> ", item.data.purchased_at.timestamp()))
+                    .push_bold_safe(&item.data.name)
+                    .push(format!(": {}", item.data.kind));
+                if item.data.expired {
+                    msg.push(" (expired)");
+                } else if item.data.used {
+                    msg.push(" (used)");
+                } else if let Some(expires_at) = item.data.expires_at {
+                    msg.push(format!(" (until <t:{}:R>)", expires_at.timestamp()));
+                }
+                msg.push_line("");
+            }
+
+            CreateEmbed::new()
+                .title(format!("{author_name}'s inventory"))
+                .description(msg.build())
+        })
+        .collect();
+
+    paginate_embeds(ctx, pages).await?;
+
+    Ok(())
+}
+
+async fn autocomplete_consumable(ctx: DiscordContext<'_>, partial: &str) -> Vec<String> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Vec::new();
+    };
+
+    let partial = partial.to_lowercase();
+    InventoryItem::unused_consumable_names(ctx.data().access().db(), guild_id, ctx.author().id)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|name| name.to_lowercase().contains(&partial))
+        .take(25)
+        .collect()
+}
+
+/// use up something you bought from the shop.
+#[poise::command(slash_command, guild_only, rename = "use")]
+async fn use_item(
+    ctx: DiscordContext<'_>,
+    #[description = "what you want to use"]
+    #[autocomplete = "autocomplete_consumable"]
+    item: String,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let used =
+        InventoryItem::use_consumable(ctx.data().access().db(), guild_id, ctx.author().id, &item)
+            .await?;
+    let msg = match used {
+        Some(used) => MessageBuilder::new()
+            .push("you used ")
+            .push_bold_safe(&used.data.name)
+            .push("! ^w^")
+            .build(),
+        None => MessageBuilder::new()
+            .push("you don't have any ")
+            .push_bold_safe(&item)
+            .push(" left to use. check your /inventory!")
+            .build(),
+    };
+    ctx.say(msg).await?;
+
+    Ok(())
+}
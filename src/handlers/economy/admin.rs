@@ -0,0 +1,723 @@
+use std::time::Duration;
+
+use num_format::{Locale, ToFormattedString};
+use poise::{
+    serenity_prelude::{ChannelId, Mentionable, MessageBuilder, RoleId, UserId},
+    CreateReply,
+};
+
+use super::{
+    ledger::LedgerEntryKind,
+    settings::EconomySettings,
+    shop::{ShopItem, ShopItemKind},
+    wallet::{Wallet, WalletError},
+};
+use crate::{discord::DiscordContext, MuniBotError};
+
+/// The shortest payout interval a guild can set.
+const MINIMUM_PAYOUT_INTERVAL: Duration = Duration::from_mins(1);
+
+/// The longest payout interval a guild can set.
+const MAXIMUM_PAYOUT_INTERVAL: Duration = Duration::from_days(7);
+
+/// The biggest salary multiplier a guild can set.
+const MAXIMUM_SALARY_MULTIPLIER: f64 = 100.0;
+
+/// The biggest house edge a guild can set, as a percentage.
+const MAXIMUM_HOUSE_EDGE_PERCENT: f64 = 50.0;
+
+/// manage this server's economy.
+#[poise::command(
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommand_required,
+    subcommands(
+        "grant",
+        "remove",
+        "reset_user",
+        "reset_server",
+        "set_salary_multiplier",
+        "set_payout_interval",
+        "exclude_channel",
+        "include_channel",
+        "set_betting",
+        "set_max_bet",
+        "set_house_edge",
+        "settings"
+    ),
+    ephemeral
+)]
+pub async fn economy(_ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    Ok(())
+}
+
+/// give coins to someone.
+#[poise::command(
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn grant(
+    ctx: DiscordContext<'_>,
+    #[description = "who to give coins to"] user: UserId,
+    #[description = "how many coins to give"] amount: u64,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let db = ctx.data().access().db();
+    let mut wallet = Wallet::get_from_db(db, guild_id, user).await?;
+    wallet
+        .deposit(
+            db,
+            amount,
+            LedgerEntryKind::AdminGrant,
+            Some(ctx.author().id),
+        )
+        .await?;
+
+    say_ephemeral(
+        ctx,
+        format!(
+            "done! gave **{}** coins to {}. they now have **{}** coins.",
+            amount.to_formatted_string(&Locale::en),
+            user.mention(),
+            wallet.balance().to_formatted_string(&Locale::en)
+        ),
+    )
+    .await
+}
+
+/// take coins away from someone.
+#[poise::command(
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn remove(
+    ctx: DiscordContext<'_>,
+    #[description = "who to take coins from"] user: UserId,
+    #[description = "how many coins to take. if they don't have this many, they'll be left with none."]
+    amount: u64,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let db = ctx.data().access().db();
+    let mut wallet = Wallet::get_from_db(db, guild_id, user).await?;
+    let amount = amount.min(wallet.balance());
+    match wallet
+        .spend(
+            db,
+            amount,
+            LedgerEntryKind::AdminRemove,
+            Some(ctx.author().id),
+        )
+        .await
+    {
+        Ok(()) => {
+            say_ephemeral(
+                ctx,
+                format!(
+                    "done! took **{}** coins from {}. they now have **{}** coins.",
+                    amount.to_formatted_string(&Locale::en),
+                    user.mention(),
+                    wallet.balance().to_formatted_string(&Locale::en)
+                ),
+            )
+            .await
+        }
+        // they spent some coins while we were working
+        Err(WalletError::InsufficientFunds) => {
+            say_ephemeral(
+                ctx,
+                format!(
+                    "{} just spent some of their coins, so i didn't take any. try again?",
+                    user.mention()
+                ),
+            )
+            .await
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// empty someone's wallet.
+#[poise::command(
+    rename = "reset-user",
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn reset_user(
+    ctx: DiscordContext<'_>,
+    #[description = "whose wallet to empty"] user: UserId,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let db = ctx.data().access().db();
+    Wallet::get_from_db(db, guild_id, user)
+        .await?
+        .reset(db, ctx.author().id)
+        .await?;
+
+    say_ephemeral(ctx, format!("done! {} has no coins now.", user.mention())).await
+}
+
+/// empty every wallet in this server. this can't be undone!
+#[poise::command(
+    rename = "reset-server",
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn reset_server(
+    ctx: DiscordContext<'_>,
+    #[description = "set this to true if you're really sure. everyone will lose all of their coins!"]
+    confirm: bool,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    if !confirm {
+        return say_ephemeral(
+            ctx,
+            "okay, i won't touch anything. set `confirm` to true if you really want to reset everyone's wallet.",
+        )
+        .await;
+    }
+
+    let reset_count =
+        Wallet::reset_guild(ctx.data().access().db(), guild_id, ctx.author().id).await?;
+
+    say_ephemeral(
+        ctx,
+        format!("done! i emptied **{reset_count}** wallets. everyone's starting fresh now."),
+    )
+    .await
+}
+
+/// change how much everyone earns by chatting.
+#[poise::command(
+    rename = "set-salary-multiplier",
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn set_salary_multiplier(
+    ctx: DiscordContext<'_>,
+    #[description = "what to multiply salaries by, e.g. 2 for double salaries or 0.5 for half"]
+    multiplier: f64,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    if !(0.0..=MAXIMUM_SALARY_MULTIPLIER).contains(&multiplier) {
+        return say_ephemeral(
+            ctx,
+            format!("the multiplier has to be between 0 and {MAXIMUM_SALARY_MULTIPLIER}!"),
+        )
+        .await;
+    }
+
+    let db = ctx.data().access().db();
+    let mut settings = EconomySettings::get_or_default(db, guild_id).await?;
+    settings.salary_multiplier = multiplier;
+    settings.save(db).await?;
+
+    say_ephemeral(
+        ctx,
+        format!("done! salaries are now multiplied by **{multiplier}**."),
+    )
+    .await
+}
+
+/// change how often people can claim their payouts.
+#[poise::command(
+    rename = "set-payout-interval",
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn set_payout_interval(
+    ctx: DiscordContext<'_>,
+    #[description = "how long people have to wait between claims, e.g. '5m', '1 hour', '1 day'"]
+    interval: String,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let interval = humantime::parse_duration(&interval)?;
+    if !(MINIMUM_PAYOUT_INTERVAL..=MAXIMUM_PAYOUT_INTERVAL).contains(&interval) {
+        return say_ephemeral(
+            ctx,
+            format!(
+                "the interval has to be between {} and {}!",
+                humantime::format_duration(MINIMUM_PAYOUT_INTERVAL),
+                humantime::format_duration(MAXIMUM_PAYOUT_INTERVAL)
+            ),
+        )
+        .await;
+    }
+
+    let db = ctx.data().access().db();
+    let mut settings = EconomySettings::get_or_default(db, guild_id).await?;
+    settings.payout_interval = interval;
+    settings.save(db).await?;
+
+    say_ephemeral(
+        ctx,
+        MessageBuilder::new()
+            .push("done! payouts can now be claimed every ")
+            .push_bold(humantime::format_duration(interval).to_string())
+            .push('.')
+            .build(),
+    )
+    .await
+}
+
+/// stop a channel from earning anyone coins.
+#[poise::command(
+    rename = "exclude-channel",
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn exclude_channel(
+    ctx: DiscordContext<'_>,
+    #[description = "the channel to exclude. if omitted, use the current channel instead."]
+    channel: Option<ChannelId>,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let channel_id = channel.unwrap_or_else(|| ctx.channel_id());
+
+    let db = ctx.data().access().db();
+    let mut settings = EconomySettings::get_or_default(db, guild_id).await?;
+    if settings.is_channel_excluded(channel_id) {
+        return say_ephemeral(
+            ctx,
+            format!("{} is already excluded :3", channel_id.mention()),
+        )
+        .await;
+    }
+    settings.excluded_channels.push(channel_id);
+    settings.save(db).await?;
+
+    say_ephemeral(
+        ctx,
+        format!(
+            "done! chatting in {} won't earn anyone coins anymore.",
+            channel_id.mention()
+        ),
+    )
+    .await
+}
+
+/// let a channel earn coins again.
+#[poise::command(
+    rename = "include-channel",
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn include_channel(
+    ctx: DiscordContext<'_>,
+    #[description = "the channel to include. if omitted, use the current channel instead."]
+    channel: Option<ChannelId>,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+    let channel_id = channel.unwrap_or_else(|| ctx.channel_id());
+
+    let db = ctx.data().access().db();
+    let mut settings = EconomySettings::get_or_default(db, guild_id).await?;
+    if !settings.is_channel_excluded(channel_id) {
+        return say_ephemeral(ctx, format!("{} isn't excluded :3", channel_id.mention())).await;
+    }
+    settings.excluded_channels.retain(|c| *c != channel_id);
+    settings.save(db).await?;
+
+    say_ephemeral(
+        ctx,
+        format!(
+            "done! chatting in {} earns coins again.",
+            channel_id.mention()
+        ),
+    )
+    .await
+}
+
+/// let people bet their coins, or stop them from betting.
+#[poise::command(
+    rename = "set-betting",
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn set_betting(
+    ctx: DiscordContext<'_>,
+    #[description = "whether people can use /bet and /slots"] enabled: bool,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let db = ctx.data().access().db();
+    let mut settings = EconomySettings::get_or_default(db, guild_id).await?;
+    settings.betting_enabled = enabled;
+    settings.save(db).await?;
+
+    say_ephemeral(
+        ctx,
+        if enabled {
+            "done! betting is open. good luck, everyone >:3"
+        } else {
+            "done! no more betting here."
+        },
+    )
+    .await
+}
+
+/// change the most coins anyone can bet at once.
+#[poise::command(
+    rename = "set-max-bet",
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn set_max_bet(
+    ctx: DiscordContext<'_>,
+    #[description = "the biggest bet allowed"]
+    #[min = 1]
+    amount: u64,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    if amount == 0 {
+        return say_ephemeral(ctx, "the max bet has to be at least one coin!").await;
+    }
+
+    let db = ctx.data().access().db();
+    let mut settings = EconomySettings::get_or_default(db, guild_id).await?;
+    settings.max_bet = amount;
+    settings.save(db).await?;
+
+    say_ephemeral(
+        ctx,
+        format!(
+            "done! people can bet up to **{}** coins at once.",
+            amount.to_formatted_string(&Locale::en)
+        ),
+    )
+    .await
+}
+
+/// change how much of every bet's winnings the house keeps.
+#[poise::command(
+    rename = "set-house-edge",
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn set_house_edge(
+    ctx: DiscordContext<'_>,
+    #[description = "the percentage of winnings the house keeps, e.g. 5 for 5%"] percent: f64,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    if !(0.0..=MAXIMUM_HOUSE_EDGE_PERCENT).contains(&percent) {
+        return say_ephemeral(
+            ctx,
+            format!("the house edge has to be between 0% and {MAXIMUM_HOUSE_EDGE_PERCENT}%!"),
+        )
+        .await;
+    }
+
+    let db = ctx.data().access().db();
+    let mut settings = EconomySettings::get_or_default(db, guild_id).await?;
+    settings.house_edge = percent / 100.0;
+    settings.save(db).await?;
+
+    say_ephemeral(
+        ctx,
+        format!("done! the house now keeps **{percent}%** of winnings."),
+    )
+    .await
+}
+
+/// see how this server's economy is set up.
+#[poise::command(
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn settings(ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let settings = EconomySettings::get_or_default(ctx.data().access().db(), guild_id).await?;
+
+    let mut msg = MessageBuilder::new();
+    msg.push("salary multiplier: ")
+        .push_bold_line(settings.salary_multiplier.to_string())
+        .push("payout interval: ")
+        .push_bold_line(humantime::format_duration(settings.payout_interval).to_string())
+        .push("excluded channels: ");
+    if settings.excluded_channels.is_empty() {
+        msg.push_bold("none");
+    } else {
+        let channels: Vec<String> = settings
+            .excluded_channels
+            .iter()
+            .map(|c| c.mention().to_string())
+            .collect();
+        msg.push(channels.join(", "));
+    }
+    msg.push_line("")
+        .push("betting: ")
+        .push_bold_line(if settings.betting_enabled {
+            "enabled"
+        } else {
+            "disabled"
+        })
+        .push("max bet: ")
+        .push_bold_line(settings.max_bet.to_formatted_string(&Locale::en))
+        .push("house edge: ")
+        .push_bold(format!("{}%", settings.house_edge * 100.0));
+
+    say_ephemeral(ctx, msg.build()).await
+}
+
+/// manage this server's shop.
+#[poise::command(
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    subcommand_required,
+    subcommands(
+        "add_role",
+        "add_temporary_role",
+        "add_title",
+        "add_consumable",
+        "remove_item"
+    ),
+    ephemeral
+)]
+pub async fn shop(_ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    Ok(())
+}
+
+/// sell a role in the shop.
+#[poise::command(
+    rename = "add-role",
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn add_role(
+    ctx: DiscordContext<'_>,
+    #[description = "what the item is called in the shop"] name: String,
+    #[description = "how many coins it costs"] price: u64,
+    #[description = "the role to give to whoever buys it"] role: RoleId,
+    #[description = "a description to show in the shop"] description: Option<String>,
+) -> Result<(), MuniBotError> {
+    add_item(
+        ctx,
+        name,
+        price,
+        description,
+        ShopItemKind::Role { role_id: role },
+    )
+    .await
+}
+
+/// sell a role in the shop that's taken away after a while.
+#[poise::command(
+    rename = "add-temporary-role",
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn add_temporary_role(
+    ctx: DiscordContext<'_>,
+    #[description = "what the item is called in the shop"] name: String,
+    #[description = "how many coins it costs"] price: u64,
+    #[description = "the role to give to whoever buys it"] role: RoleId,
+    #[description = "how long the role lasts, e.g. '1h', '1 day', '1 week'"] duration: String,
+    #[description = "a description to show in the shop"] description: Option<String>,
+) -> Result<(), MuniBotError> {
+    let duration = humantime::parse_duration(&duration)?;
+    add_item(
+        ctx,
+        name,
+        price,
+        description,
+        ShopItemKind::TemporaryRole {
+            role_id: role,
+            duration,
+        },
+    )
+    .await
+}
+
+/// sell a custom title in the shop.
+#[poise::command(
+    rename = "add-title",
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn add_title(
+    ctx: DiscordContext<'_>,
+    #[description = "what the item is called in the shop"] name: String,
+    #[description = "how many coins it costs"] price: u64,
+    #[description = "the title whoever buys it gets"] title: String,
+    #[description = "a description to show in the shop"] description: Option<String>,
+) -> Result<(), MuniBotError> {
+    add_item(ctx, name, price, description, ShopItemKind::Title { title }).await
+}
+
+/// sell something people can /use in the shop.
+#[poise::command(
+    rename = "add-consumable",
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn add_consumable(
+    ctx: DiscordContext<'_>,
+    #[description = "what the item is called in the shop"] name: String,
+    #[description = "how many coins it costs"] price: u64,
+    #[description = "a description to show in the shop"] description: Option<String>,
+) -> Result<(), MuniBotError> {
+    add_item(ctx, name, price, description, ShopItemKind::Consumable).await
+}
+
+/// stop selling something in the shop. people who bought it keep it.
+#[poise::command(
+    rename = "remove",
+    slash_command,
+    hide_in_help,
+    guild_only,
+    required_permissions = "MANAGE_GUILD",
+    ephemeral
+)]
+async fn remove_item(
+    ctx: DiscordContext<'_>,
+    #[description = "the name of the item to remove"] name: String,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let content = match ShopItem::remove(ctx.data().access().db(), guild_id, &name).await? {
+        Some(item) => MessageBuilder::new()
+            .push("done! ")
+            .push_bold_safe(&item.name)
+            .push(" isn't in the shop anymore.")
+            .build(),
+        None => MessageBuilder::new()
+            .push("there's nothing called ")
+            .push_bold_safe(&name)
+            .push(" in the shop :<")
+            .build(),
+    };
+    say_ephemeral(ctx, content).await
+}
+
+async fn add_item(
+    ctx: DiscordContext<'_>,
+    name: String,
+    price: u64,
+    description: Option<String>,
+    kind: ShopItemKind,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let item = ShopItem {
+        guild_id,
+        name: name.trim().to_string(),
+        description: description.unwrap_or_default(),
+        price,
+        kind,
+    };
+
+    let content = if item.add(ctx.data().access().db()).await? {
+        MessageBuilder::new()
+            .push("done! ")
+            .push_bold_safe(&item.name)
+            .push(format!(
+                " is in the shop now for **{}** coins.",
+                price.to_formatted_string(&Locale::en)
+            ))
+            .build()
+    } else {
+        MessageBuilder::new()
+            .push("there's already something called ")
+            .push_bold_safe(&item.name)
+            .push(" in the shop! remove it first if you want to replace it.")
+            .build()
+    };
+    say_ephemeral(ctx, content).await
+}
+
+async fn say_ephemeral(
+    ctx: DiscordContext<'_>,
+    content: impl Into<String>,
+) -> Result<(), MuniBotError> {
+    ctx.send(CreateReply::default().ephemeral(true).content(content))
+        .await?;
+    Ok(())
+}
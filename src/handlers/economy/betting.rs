@@ -0,0 +1,301 @@
+use num_format::{Locale, ToFormattedString};
+use poise::{serenity_prelude::MessageBuilder, ChoiceParameter};
+use rand::seq::SliceRandom;
+
+use super::{
+    settings::EconomySettings,
+    wallet::{Wallet, WalletError},
+};
+use crate::{
+    discord::DiscordContext,
+    handlers::{dice::DiceHandler, eight_ball::EightBallProvider},
+    MuniBotError,
+};
+
+/// The symbols on each reel of the slot machine.
+const SLOT_SYMBOLS: [&str; 6] = ["🍒", "🍋", "🍇", "🔔", "⭐", "💎"];
+
+/// What three matching symbols pay, as a multiple of the wager. With six
+/// symbols, this and [`SLOT_PAIR_MULTIPLIER`] make slots a fair game before
+/// the house edge is taken.
+const SLOT_TRIPLE_MULTIPLIER: f64 = 12.0;
+
+/// What two matching symbols pay, as a multiple of the wager.
+const SLOT_PAIR_MULTIPLIER: f64 = 1.6;
+
+/// The biggest die that can be bet on.
+const MAX_DICE_SIDES: u8 = 100;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, poise::ChoiceParameter)]
+enum CoinSide {
+    #[name = "heads"]
+    Heads,
+
+    #[name = "tails"]
+    Tails,
+}
+
+/// How a game turned out.
+struct Outcome {
+    /// What happened, to show to the player.
+    message: String,
+
+    /// What the game pays, as a multiple of the wager, before the house edge
+    /// is taken. Zero if the player lost.
+    multiplier: f64,
+}
+
+/// bet your coins on a game of chance.
+#[poise::command(
+    slash_command,
+    guild_only,
+    subcommand_required,
+    subcommands("coinflip", "dice")
+)]
+pub async fn bet(_ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    Ok(())
+}
+
+/// bet on a coin flip. doubles your coins if you call it right!
+#[poise::command(slash_command, guild_only)]
+async fn coinflip(
+    ctx: DiscordContext<'_>,
+    #[description = "how many coins to bet"] wager: u64,
+    #[description = "which side you think it'll land on"] side: CoinSide,
+) -> Result<(), MuniBotError> {
+    place_bet(ctx, wager, || {
+        let landed = if DiceHandler::roll(2) == 1 {
+            CoinSide::Heads
+        } else {
+            CoinSide::Tails
+        };
+        Outcome {
+            message: format!(
+                "you flip the coin {}... it's **{}**!",
+                EightBallProvider::get_adverb(),
+                landed.name()
+            ),
+            multiplier: if landed == side { 2.0 } else { 0.0 },
+        }
+    })
+    .await
+}
+
+/// bet on a die roll. the more sides, the bigger the prize!
+#[poise::command(slash_command, guild_only)]
+async fn dice(
+    ctx: DiscordContext<'_>,
+    #[description = "how many coins to bet"] wager: u64,
+    #[description = "how many sides the die has"]
+    #[min = 2]
+    #[max = 100]
+    sides: u8,
+    #[description = "the number you think it'll land on"]
+    #[min = 1]
+    #[max = 100]
+    guess: u8,
+) -> Result<(), MuniBotError> {
+    if !(2..=MAX_DICE_SIDES).contains(&sides) {
+        ctx.say(format!(
+            "the die has to have between 2 and {MAX_DICE_SIDES} sides!"
+        ))
+        .await?;
+        return Ok(());
+    }
+    if !(1..=sides).contains(&guess) {
+        ctx.say(format!(
+            "you have to guess a number from 1 to {sides}, silly!"
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    place_bet(ctx, wager, || {
+        let result = DiceHandler::roll(sides);
+        let mut msg = MessageBuilder::new();
+        DiceHandler::message_for_roll(result, sides).add_to_message_builder(&mut msg);
+        Outcome {
+            message: msg.build(),
+            multiplier: if result == guess { sides as f64 } else { 0.0 },
+        }
+    })
+    .await
+}
+
+/// bet on the slot machine. match symbols to win!
+#[poise::command(slash_command, guild_only)]
+pub async fn slots(
+    ctx: DiscordContext<'_>,
+    #[description = "how many coins to bet"] wager: u64,
+) -> Result<(), MuniBotError> {
+    place_bet(ctx, wager, || {
+        let reels = spin_slots();
+        Outcome {
+            message: format!(
+                "you pull the lever {}... {}",
+                EightBallProvider::get_adverb(),
+                reels.join(" ")
+            ),
+            multiplier: slots_multiplier(&reels),
+        }
+    })
+    .await
+}
+
+/// Checks that a bet is allowed, plays the game, and settles the bet with the
+/// player's wallet.
+async fn place_bet(
+    ctx: DiscordContext<'_>,
+    wager: u64,
+    play: impl FnOnce() -> Outcome,
+) -> Result<(), MuniBotError> {
+    let Some(guild_id) = ctx.guild_id() else {
+        return Ok(());
+    };
+
+    let db = ctx.data().access().db();
+    let settings = EconomySettings::get_or_default(db, guild_id).await?;
+    if !settings.betting_enabled {
+        ctx.say("betting isn't enabled in this server :3").await?;
+        return Ok(());
+    }
+    if wager == 0 {
+        ctx.say("you have to bet at least one coin, silly!").await?;
+        return Ok(());
+    }
+    if wager > settings.max_bet {
+        ctx.say(format!(
+            "you can only bet up to **{}** coins at once here!",
+            settings.max_bet.to_formatted_string(&Locale::en)
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let mut wallet = Wallet::get_from_db(db, guild_id, ctx.author().id).await?;
+    if wallet.balance() < wager {
+        ctx.say(format!(
+            "you only have **{}** coins to bet with :<",
+            wallet.balance().to_formatted_string(&Locale::en)
+        ))
+        .await?;
+        return Ok(());
+    }
+
+    let outcome = play();
+    let winnings = winnings(wager, outcome.multiplier, settings.house_edge);
+    match wallet.settle_bet(db, wager, winnings).await {
+        Ok(()) => {}
+        Err(WalletError::InsufficientFunds) => {
+            ctx.say("you don't have enough coins for that bet anymore :<")
+                .await?;
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    let result = match winnings {
+        0 => format!(
+            "you lost **{}** coins :<",
+            wager.to_formatted_string(&Locale::en)
+        ),
+        w if w <= wager => format!(
+            "you won, but only got **{}** coins back. the house always wins >:3",
+            w.to_formatted_string(&Locale::en)
+        ),
+        w => format!(
+            "you won **{}** coins!! 🎉",
+            (w - wager).to_formatted_string(&Locale::en)
+        ),
+    };
+    ctx.say(format!(
+        "{}\n{result} you have **{}** coins now.",
+        outcome.message,
+        wallet.balance().to_formatted_string(&Locale::en)
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// How many coins a bet pays back, wager included. The house's cut is taken
+/// out of the whole payout.
+fn winnings(wager: u64, multiplier: f64, house_edge: f64) -> u64 {
+    (wager as f64 * multiplier * (1.0 - house_edge)).floor() as u64
+}
+
+/// Spins the slot machine's three reels.
+fn spin_slots() -> [&'static str; 3] {
+    let mut rng = rand::thread_rng();
+    [(); 3].map(|_| *SLOT_SYMBOLS.choose(&mut rng).unwrap())
+}
+
+/// What a spin of the slot machine pays, as a multiple of the wager.
+fn slots_multiplier(reels: &[&str; 3]) -> f64 {
+    let [a, b, c] = reels;
+    if a == b && b == c {
+        SLOT_TRIPLE_MULTIPLIER
+    } else if a == b || b == c || a == c {
+        SLOT_PAIR_MULTIPLIER
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::{GuildId, UserId};
+
+    use super::{slots_multiplier, winnings};
+    use crate::{
+        db::test_db,
+        handlers::economy::{
+            ledger::{LedgerEntry, LedgerEntryKind},
+            wallet::{Wallet, WalletError},
+        },
+    };
+
+    #[test]
+    fn test_payouts() {
+        assert_eq!(winnings(100, 2.0, 0.05), 190);
+        assert_eq!(winnings(100, 0.0, 0.05), 0);
+        assert_eq!(winnings(1, 2.0, 0.5), 1);
+
+        assert_eq!(slots_multiplier(&["⭐", "⭐", "⭐"]), 12.0);
+        assert_eq!(slots_multiplier(&["⭐", "🍋", "⭐"]), 1.6);
+        assert_eq!(slots_multiplier(&["⭐", "🍋", "🍒"]), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_settle_bet() {
+        let db = test_db().await;
+        let guild_id = GuildId::new(1);
+        let user_id = UserId::new(2);
+
+        let mut wallet = Wallet::create_in_db(&db, guild_id, user_id, 100)
+            .await
+            .unwrap();
+        wallet.settle_bet(&db, 50, 95).await.unwrap();
+        assert_eq!(wallet.balance(), 145);
+        wallet.settle_bet(&db, 100, 0).await.unwrap();
+        assert_eq!(wallet.balance(), 45);
+
+        // a bet can't be covered by its own winnings
+        assert!(matches!(
+            wallet.settle_bet(&db, 50, 500).await,
+            Err(WalletError::InsufficientFunds)
+        ));
+        assert_eq!(wallet.balance(), 45);
+
+        let entries = LedgerEntry::get_recent(&db, guild_id, user_id, 10)
+            .await
+            .unwrap();
+        let amounts: Vec<_> = entries
+            .iter()
+            .filter(|e| e.kind == LedgerEntryKind::Bet)
+            .map(|e| e.amount)
+            .collect();
+        assert_eq!(amounts.len(), 2);
+        assert!(amounts.contains(&45) && amounts.contains(&-100));
+    }
+}
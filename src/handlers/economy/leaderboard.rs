@@ -0,0 +1,184 @@
+use poise::serenity_prelude::{GuildId, UserId};
+use serde::Deserialize;
+use surrealdb::{Connection, Surreal};
+
+use super::{ledger::LEDGER_TABLE, wallet::GUILD_WALLET_TABLE};
+
+/// Matches wallets and ledger entries owned by Discord users. Leaderboards
+/// are shown on Discord, so Twitch chatters without a linked Discord account
+/// are left out.
+const DISCORD_OWNER: &str = "!string::starts_with(user_id, 'twitch:')";
+
+/// A single place on a leaderboard.
+#[derive(Clone, Debug, Deserialize)]
+pub struct LeaderboardEntry {
+    pub user_id: UserId,
+    pub amount: u64,
+}
+
+/// Where a user places on a leaderboard.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Rank {
+    /// The user's place, starting at 1. Users tied with each other share the
+    /// same place.
+    pub place: u64,
+    pub amount: u64,
+}
+
+/// Returns the wallets with the most coins in a guild, richest first.
+pub async fn top_balances<C: Connection>(
+    db: &Surreal<C>,
+    guild_id: GuildId,
+    limit: usize,
+) -> Result<Vec<LeaderboardEntry>, surrealdb::Error> {
+    db.query(format!(
+        "SELECT user_id, balance AS amount FROM {GUILD_WALLET_TABLE}
+         WHERE guild_id = $guild AND balance > 0 AND {DISCORD_OWNER}
+         ORDER BY amount DESC
+         LIMIT $limit;"
+    ))
+    .bind(("guild", guild_id))
+    .bind(("limit", limit))
+    .await?
+    .take(0)
+}
+
+/// Returns where a user places on the balance leaderboard, or `None` if they
+/// don't have any coins.
+pub async fn balance_rank<C: Connection>(
+    db: &Surreal<C>,
+    guild_id: GuildId,
+    user_id: UserId,
+) -> Result<Option<Rank>, surrealdb::Error> {
+    let rank: Option<Rank> = db
+        .query(format!(
+            "LET $amount = (
+                 SELECT VALUE balance FROM {GUILD_WALLET_TABLE}
+                 WHERE guild_id = $guild AND user_id = $user
+             )[0] ?? 0;
+             RETURN {{
+                 place: count(
+                     SELECT id FROM {GUILD_WALLET_TABLE}
+                     WHERE guild_id = $guild AND balance > $amount AND {DISCORD_OWNER}
+                 ) + 1,
+                 amount: $amount,
+             }};"
+        ))
+        .bind(("guild", guild_id))
+        .bind(("user", user_id))
+        .await?
+        .take(1)?;
+
+    Ok(rank.filter(|r| r.amount > 0))
+}
+
+/// Returns who has earned the most salary in a guild over the past week, top
+/// earner first.
+pub async fn top_weekly_earners<C: Connection>(
+    db: &Surreal<C>,
+    guild_id: GuildId,
+    limit: usize,
+) -> Result<Vec<LeaderboardEntry>, surrealdb::Error> {
+    db.query(format!(
+        "SELECT * FROM (
+             SELECT user_id, math::sum(amount) AS amount FROM {LEDGER_TABLE}
+             WHERE guild_id = $guild AND kind = 'salary' AND created_at > time::now() - 1w
+                 AND {DISCORD_OWNER}
+             GROUP BY user_id
+         )
+         ORDER BY amount DESC
+         LIMIT $limit;"
+    ))
+    .bind(("guild", guild_id))
+    .bind(("limit", limit))
+    .await?
+    .take(0)
+}
+
+/// Returns where a user places among the past week's earners, or `None` if
+/// they haven't earned anything.
+pub async fn weekly_earner_rank<C: Connection>(
+    db: &Surreal<C>,
+    guild_id: GuildId,
+    user_id: UserId,
+) -> Result<Option<Rank>, surrealdb::Error> {
+    let rank: Option<Rank> = db
+        .query(format!(
+            "LET $earners = (
+                 SELECT user_id, math::sum(amount) AS amount FROM {LEDGER_TABLE}
+                 WHERE guild_id = $guild AND kind = 'salary' AND created_at > time::now() - 1w
+                 AND {DISCORD_OWNER}
+                 GROUP BY user_id
+             );
+             LET $amount = $earners[WHERE user_id = $user][0].amount ?? 0;
+             RETURN {{
+                 place: count($earners[WHERE amount > $amount]) + 1,
+                 amount: $amount,
+             }};"
+        ))
+        .bind(("guild", guild_id))
+        .bind(("user", user_id))
+        .await?
+        .take(2)?;
+
+    Ok(rank.filter(|r| r.amount > 0))
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::{GuildId, UserId};
+
+    use super::{balance_rank, top_balances, top_weekly_earners, weekly_earner_rank};
+    use crate::{
+        db::test_db,
+        handlers::economy::{owner::WalletOwner, payout::Payout, wallet::Wallet},
+    };
+
+    #[tokio::test]
+    async fn test_leaderboards() {
+        let db = test_db().await;
+        let guild_id = GuildId::new(1);
+
+        for (user, balance, salary) in [(2, 50, 10), (3, 200, 5), (4, 0, 30)] {
+            let user_id = UserId::new(user);
+            Wallet::create_in_db(&db, guild_id, user_id, balance)
+                .await
+                .unwrap();
+            Payout::get_from_db(&db, guild_id, user_id)
+                .await
+                .unwrap()
+                .deposit(&db, salary)
+                .await
+                .unwrap();
+        }
+
+        // twitch chatters without a discord account aren't shown
+        Wallet::create_in_db(&db, guild_id, WalletOwner::Twitch("5".to_string()), 1000)
+            .await
+            .unwrap();
+
+        let top = top_balances(&db, guild_id, 10).await.unwrap();
+        let top: Vec<_> = top.iter().map(|e| (e.user_id.get(), e.amount)).collect();
+        assert_eq!(top, vec![(3, 200), (2, 50)]);
+
+        let rank = balance_rank(&db, guild_id, UserId::new(2))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((rank.place, rank.amount), (2, 50));
+        assert!(balance_rank(&db, guild_id, UserId::new(4))
+            .await
+            .unwrap()
+            .is_none());
+
+        let top = top_weekly_earners(&db, guild_id, 10).await.unwrap();
+        let top: Vec<_> = top.iter().map(|e| (e.user_id.get(), e.amount)).collect();
+        assert_eq!(top, vec![(4, 30), (2, 10), (3, 5)]);
+
+        let rank = weekly_earner_rank(&db, guild_id, UserId::new(3))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((rank.place, rank.amount), (3, 5));
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Utc};
+use poise::serenity_prelude::GuildId;
+use serde::{Deserialize, Serialize};
+use surrealdb::{Connection, Surreal};
+
+use super::owner::WalletOwner;
+
+pub const LEDGER_TABLE: &str = "ledger_entry";
+
+/// What caused a change in balance.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerEntryKind {
+    /// Salary earned by chatting, paid into a payout.
+    Salary,
+
+    /// A payout claimed into a wallet.
+    Claim,
+
+    /// Coins sent to or received from someone else.
+    Transfer,
+
+    /// Coins given by an admin.
+    AdminGrant,
+
+    /// Coins taken away by an admin.
+    AdminRemove,
+
+    /// A wallet emptied by an admin.
+    AdminReset,
+
+    /// Something bought from the shop.
+    Purchase,
+
+    /// Coins won or lost betting.
+    Bet,
+}
+
+impl Display for LedgerEntryKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LedgerEntryKind::Salary => write!(f, "salary"),
+            LedgerEntryKind::Claim => write!(f, "claim"),
+            LedgerEntryKind::Transfer => write!(f, "transfer"),
+            LedgerEntryKind::AdminGrant => write!(f, "admin grant"),
+            LedgerEntryKind::AdminRemove => write!(f, "admin removal"),
+            LedgerEntryKind::AdminReset => write!(f, "admin reset"),
+            LedgerEntryKind::Purchase => write!(f, "purchase"),
+            LedgerEntryKind::Bet => write!(f, "bet"),
+        }
+    }
+}
+
+/// A record of a single change in someone's balance. Entries are only ever
+/// created alongside the change they describe, in the same transaction.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LedgerEntry {
+    pub guild_id: GuildId,
+    pub user_id: WalletOwner,
+
+    /// How much the balance changed by. Negative for coins leaving.
+    pub amount: i64,
+    pub kind: LedgerEntryKind,
+
+    /// The other person involved in the change, if any (e.g. the other end of
+    /// a transfer).
+    #[serde(default)]
+    pub counterparty: Option<WalletOwner>,
+
+    /// When the change happened. This is set by the database.
+    #[serde(skip_serializing)]
+    pub created_at: DateTime<Utc>,
+}
+
+impl LedgerEntry {
+    pub fn new(
+        guild_id: GuildId,
+        user_id: impl Into<WalletOwner>,
+        amount: i64,
+        kind: LedgerEntryKind,
+    ) -> Self {
+        Self {
+            guild_id,
+            user_id: user_id.into(),
+            amount,
+            kind,
+            counterparty: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    pub fn with_counterparty(self, counterparty: impl Into<WalletOwner>) -> Self {
+        Self {
+            counterparty: Some(counterparty.into()),
+            ..self
+        }
+    }
+
+    /// Returns the most recent entries for a user's wallet, newest first.
+    /// Salary isn't included, since it goes into payouts rather than wallets.
+    pub async fn get_recent<C: Connection>(
+        db: &Surreal<C>,
+        guild_id: GuildId,
+        user_id: impl Into<WalletOwner>,
+        limit: usize,
+    ) -> Result<Vec<Self>, surrealdb::Error> {
+        db.query(format!(
+            "SELECT * FROM {LEDGER_TABLE}
+             WHERE guild_id = $guild AND user_id = $user AND kind != 'salary'
+             ORDER BY created_at DESC
+             LIMIT $limit;"
+        ))
+        .bind(("guild", guild_id))
+        .bind(("user", user_id.into()))
+        .bind(("limit", limit))
+        .await?
+        .take(0)
+    }
+}
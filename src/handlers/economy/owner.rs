@@ -0,0 +1,151 @@
+use std::{fmt::Display, str::FromStr};
+
+use poise::serenity_prelude::UserId;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use surrealdb::{Connection, Surreal};
+
+use crate::handlers::account_link::AccountLink;
+
+/// The prefix used to store Twitch chatters' ids, so they can't be confused
+/// with Discord users' ids.
+const TWITCH_PREFIX: &str = "twitch:";
+
+/// Who a wallet, payout, or ledger entry belongs to. Twitch chatters who have
+/// linked their Discord account use their Discord user instead, so both
+/// platforms feed the same wallet.
+///
+/// In the database, Discord users are stored by their id (just like a
+/// [`UserId`]) and Twitch chatters are stored as `twitch:<id>`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum WalletOwner {
+    Discord(UserId),
+    Twitch(String),
+}
+
+impl WalletOwner {
+    /// Returns the owner for a Twitch chatter: their Discord account if
+    /// they've linked one, or their Twitch account otherwise.
+    pub async fn for_twitch_user<C: Connection>(
+        db: &Surreal<C>,
+        twitch_user_id: &str,
+    ) -> Result<Self, surrealdb::Error> {
+        Ok(
+            match AccountLink::get_by_twitch_id(db, twitch_user_id).await? {
+                Some(link) => WalletOwner::Discord(link.discord_user_id),
+                None => WalletOwner::Twitch(twitch_user_id.to_string()),
+            },
+        )
+    }
+
+    /// The owner's Discord user, if they're a Discord user.
+    pub fn discord_id(&self) -> Option<UserId> {
+        match self {
+            WalletOwner::Discord(user_id) => Some(*user_id),
+            WalletOwner::Twitch(_) => None,
+        }
+    }
+}
+
+impl From<UserId> for WalletOwner {
+    fn from(user_id: UserId) -> Self {
+        WalletOwner::Discord(user_id)
+    }
+}
+
+impl Display for WalletOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WalletOwner::Discord(user_id) => write!(f, "{user_id}"),
+            WalletOwner::Twitch(twitch_id) => write!(f, "{TWITCH_PREFIX}{twitch_id}"),
+        }
+    }
+}
+
+impl FromStr for WalletOwner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix(TWITCH_PREFIX) {
+            Some(twitch_id) => Ok(WalletOwner::Twitch(twitch_id.to_string())),
+            None => s
+                .parse::<u64>()
+                .ok()
+                .filter(|id| *id != 0)
+                .map(|id| WalletOwner::Discord(UserId::new(id)))
+                .ok_or_else(|| format!("{s} isn't a wallet owner")),
+        }
+    }
+}
+
+impl Serialize for WalletOwner {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for WalletOwner {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::{GuildId, UserId};
+
+    use super::WalletOwner;
+    use crate::{
+        db::test_db,
+        handlers::{
+            account_link::{AccountLink, ACCOUNT_LINK_TABLE},
+            economy::wallet::Wallet,
+        },
+    };
+
+    #[test]
+    fn test_owner_keys() {
+        let discord = WalletOwner::Discord(UserId::new(123));
+        let twitch = WalletOwner::Twitch("456".to_string());
+        assert_eq!(discord.to_string(), "123");
+        assert_eq!(twitch.to_string(), "twitch:456");
+        assert_eq!("123".parse(), Ok(discord));
+        assert_eq!("twitch:456".parse(), Ok(twitch));
+        assert!("nobody".parse::<WalletOwner>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_twitch_owners() {
+        let db = test_db().await;
+        let guild_id = GuildId::new(1);
+
+        let _: Option<AccountLink> = db
+            .create(ACCOUNT_LINK_TABLE)
+            .content(AccountLink {
+                discord_user_id: UserId::new(2),
+                twitch_user_id: "30".to_string(),
+                twitch_login: "linked".to_string(),
+            })
+            .await
+            .unwrap();
+
+        // linked chatters share their discord wallet
+        let linked = WalletOwner::for_twitch_user(&db, "30").await.unwrap();
+        assert_eq!(linked, WalletOwner::Discord(UserId::new(2)));
+        Wallet::create_in_db(&db, guild_id, UserId::new(2), 10)
+            .await
+            .unwrap();
+        let wallet = Wallet::get_from_db(&db, guild_id, linked).await.unwrap();
+        assert_eq!(wallet.balance(), 10);
+
+        // everyone else gets a wallet of their own
+        let unlinked = WalletOwner::for_twitch_user(&db, "31").await.unwrap();
+        assert_eq!(unlinked, WalletOwner::Twitch("31".to_string()));
+        Wallet::create_in_db(&db, guild_id, unlinked.clone(), 20)
+            .await
+            .unwrap();
+        let wallet = Wallet::get_from_db(&db, guild_id, unlinked).await.unwrap();
+        assert_eq!(wallet.balance(), 20);
+    }
+}
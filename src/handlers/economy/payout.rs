@@ -1,20 +1,24 @@
-use chrono::Local;
-use poise::serenity_prelude::{GuildId, UserId};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use poise::serenity_prelude::GuildId;
 use serde::{Deserialize, Serialize};
 use surrealdb::{Connection, RecordId, Surreal};
 use thiserror::Error;
 
-use super::wallet::{Wallet, WalletError};
+use super::{
+    ledger::{LedgerEntry, LedgerEntryKind, LEDGER_TABLE},
+    owner::WalletOwner,
+    wallet::{signed_amount, Wallet, WalletError},
+};
 use crate::MuniBotError;
 
-const GUILD_PAYOUT_TABLE: &str = "guild_payout";
-
-const PAYOUT_INTERVAL: chrono::Duration = chrono::Duration::milliseconds(1000 * 60 * 5);
+pub const GUILD_PAYOUT_TABLE: &str = "guild_payout";
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct PayoutData {
     guild_id: GuildId,
-    user_id: UserId,
+    user_id: WalletOwner,
     balance: u64,
     last_payout: chrono::DateTime<Local>,
 }
@@ -38,15 +42,16 @@ impl Payout {
     pub async fn get_from_db<C: Connection>(
         db: &Surreal<C>,
         guild_id: GuildId,
-        user_id: UserId,
+        user_id: impl Into<WalletOwner>,
     ) -> Result<Self, PayoutError> {
+        let user_id = user_id.into();
         match db
             .query(format!(
                 "SELECT * FROM {GUILD_PAYOUT_TABLE}
                  WHERE guild_id = $guild AND user_id = $user;"
             ))
             .bind(("guild", guild_id))
-            .bind(("user", user_id))
+            .bind(("user", user_id.clone()))
             .await?
             .take::<Option<Self>>(0)?
         {
@@ -59,75 +64,133 @@ impl Payout {
     async fn create_in_db<C: Connection>(
         db: &Surreal<C>,
         guild_id: GuildId,
-        user_id: UserId,
+        user_id: WalletOwner,
     ) -> Result<Self, PayoutError> {
         // insert the payout into the payout table
         db.create::<Option<Self>>(GUILD_PAYOUT_TABLE)
             .content(PayoutData {
                 guild_id,
-                user_id,
+                user_id: user_id.clone(),
                 balance: 0,
-                last_payout: chrono::Local::now() - PAYOUT_INTERVAL,
+                // new payouts can be claimed right away
+                last_payout: DateTime::UNIX_EPOCH.with_timezone(&Local),
             })
             .await
             .map_err(PayoutError::Database)
             .and_then(|opt| opt.ok_or_else(|| PayoutError::NotCreated(user_id, guild_id)))
     }
 
-    /// Updates this payout in the database.
-    async fn update_in_db<C: Connection>(&self, db: &Surreal<C>) -> Result<(), PayoutError> {
-        let data = self.data.clone();
-        db.update::<Option<Self>>((GUILD_PAYOUT_TABLE, self.id.key().clone()))
-            .content(data)
-            .await?;
-        Ok(())
-    }
-
-    /// Drains the payout into the corresponding user's guild wallet. Returns
-    /// the amount claimed as a receipt.
+    /// Drains the payout into the corresponding user's guild wallet, as long
+    /// as at least `interval` has passed since the last claim. Returns the
+    /// amount claimed as a receipt.
     pub async fn claim_to_wallet<C: Connection>(
         &mut self,
         db: &Surreal<C>,
+        interval: Duration,
     ) -> Result<ClaimResult, PayoutError> {
-        if chrono::Local::now() < self.next_payout_time() {
-            Err(PayoutError::TooSoon)
+        if chrono::Local::now() < self.next_payout_time(interval) {
+            return Err(PayoutError::TooSoon);
         } else if self.data.balance == 0 {
-            Err(PayoutError::NothingToClaim)
-        } else {
-            let mut wallet = Wallet::get_from_db(db, self.data.guild_id, self.data.user_id).await?;
+            return Err(PayoutError::NothingToClaim);
+        }
 
-            // deposit the payout into the user's wallet
-            let amount_claimed = self.data.balance;
-            wallet.deposit(db, self.data.balance).await?;
+        let wallet = Wallet::get_from_db(db, self.data.guild_id, self.data.user_id.clone()).await?;
+        let amount_claimed = self.data.balance;
+        let entry = LedgerEntry::new(
+            self.data.guild_id,
+            self.data.user_id.clone(),
+            signed_amount(amount_claimed)?,
+            LedgerEntryKind::Claim,
+        );
+        let now = chrono::Local::now();
 
-            // clear this payout
-            self.data.balance = 0;
-            self.data.last_payout = chrono::Local::now();
-            self.update_in_db(db).await?;
+        // the payout is only drained if nobody else has claimed it since we
+        // read it, so the same payout can't be claimed twice
+        let result: Option<ClaimChange> = db
+            .query(format!(
+                "BEGIN TRANSACTION;
+                 LET $claimed = (
+                     UPDATE $payout SET balance -= $amount, last_payout = $now
+                     WHERE balance >= $amount AND last_payout = $last_payout
+                 );
+                 IF !array::is_empty($claimed) {{
+                     UPDATE $wallet SET balance += $amount;
+                     CREATE {LEDGER_TABLE} CONTENT $entry;
+                 }};
+                 RETURN {{
+                     applied: !array::is_empty($claimed),
+                     payout_balance: $payout.balance,
+                     wallet_balance: $wallet.balance,
+                 }};
+                 COMMIT TRANSACTION;"
+            ))
+            .bind(("payout", self.id.clone()))
+            .bind(("wallet", wallet.id().clone()))
+            .bind(("amount", entry.amount))
+            .bind(("now", now))
+            .bind(("last_payout", self.data.last_payout))
+            .bind(("entry", entry))
+            .await?
+            .take(0)?;
 
-            Ok(ClaimResult {
-                amount_claimed,
-                new_balance: wallet.balance(),
-            })
+        let result = result.ok_or(PayoutError::NothingToClaim)?;
+        self.data.balance = result.payout_balance;
+        if !result.applied {
+            return Err(PayoutError::NothingToClaim);
         }
+        self.data.last_payout = now;
+
+        Ok(ClaimResult {
+            amount_claimed,
+            new_balance: result.wallet_balance,
+        })
     }
 
     /// Returns the time at which a user can claim their payout.
-    pub fn next_payout_time(&self) -> chrono::DateTime<Local> {
-        self.data.last_payout + PAYOUT_INTERVAL
+    pub fn next_payout_time(&self, interval: Duration) -> chrono::DateTime<Local> {
+        self.data.last_payout + interval
     }
 
-    /// Adds the given amount to the pending payout.
+    /// Adds the given amount of salary to the pending payout, recording it in
+    /// the ledger.
     pub async fn deposit<C: Connection>(
         &mut self,
         db: &Surreal<C>,
         amount: u64,
     ) -> Result<(), PayoutError> {
-        self.data.balance += amount;
-        self.update_in_db(db).await
+        let entry = LedgerEntry::new(
+            self.data.guild_id,
+            self.data.user_id.clone(),
+            signed_amount(amount)?,
+            LedgerEntryKind::Salary,
+        );
+
+        let balance: Option<u64> = db
+            .query(format!(
+                "BEGIN TRANSACTION;
+                 UPDATE $payout SET balance += $entry.amount;
+                 CREATE {LEDGER_TABLE} CONTENT $entry;
+                 RETURN $payout.balance;
+                 COMMIT TRANSACTION;"
+            ))
+            .bind(("payout", self.id.clone()))
+            .bind(("entry", entry))
+            .await?
+            .take(0)?;
+
+        self.data.balance = balance.unwrap_or(self.data.balance + amount);
+        Ok(())
     }
 }
 
+/// The outcome of claiming a payout in the database.
+#[derive(Deserialize)]
+struct ClaimChange {
+    applied: bool,
+    payout_balance: u64,
+    wallet_balance: u64,
+}
+
 #[derive(Error, Debug)]
 pub enum PayoutError {
     #[error("error in payout database: {0}")]
@@ -137,7 +200,7 @@ pub enum PayoutError {
     Wallet(#[from] WalletError),
 
     #[error("payout for user {0} in guild {1} not created :<")]
-    NotCreated(UserId, GuildId),
+    NotCreated(WalletOwner, GuildId),
 
     #[error("too soon to claim! wait some time before claiming again")]
     TooSoon,
@@ -151,3 +214,56 @@ impl From<PayoutError> for MuniBotError {
         MuniBotError::Other(format!("{e}"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::{GuildId, UserId};
+
+    use super::{Payout, PayoutError};
+    use crate::{
+        db::test_db,
+        handlers::economy::{
+            ledger::LedgerEntry, settings::DEFAULT_PAYOUT_INTERVAL, wallet::Wallet,
+        },
+    };
+
+    #[tokio::test]
+    async fn test_payout_claim() {
+        let db = test_db().await;
+        let (guild_id, user_id) = (GuildId::new(1), UserId::new(2));
+
+        let mut payout = Payout::get_from_db(&db, guild_id, user_id).await.unwrap();
+        assert!(matches!(
+            payout.claim_to_wallet(&db, DEFAULT_PAYOUT_INTERVAL).await,
+            Err(PayoutError::NothingToClaim)
+        ));
+
+        payout.deposit(&db, 20).await.unwrap();
+        payout.deposit(&db, 30).await.unwrap();
+        let result = payout
+            .claim_to_wallet(&db, DEFAULT_PAYOUT_INTERVAL)
+            .await
+            .unwrap();
+        assert_eq!(result.amount_claimed, 50);
+        assert_eq!(result.new_balance, 50);
+
+        // a stale copy of the payout can't be claimed again
+        let mut stale = Payout::get_from_db(&db, guild_id, user_id).await.unwrap();
+        stale.data.balance = 50;
+        stale.data.last_payout = payout.data.last_payout - DEFAULT_PAYOUT_INTERVAL;
+        assert!(matches!(
+            stale.claim_to_wallet(&db, DEFAULT_PAYOUT_INTERVAL).await,
+            Err(PayoutError::NothingToClaim)
+        ));
+
+        let wallet = Wallet::get_from_db(&db, guild_id, user_id).await.unwrap();
+        assert_eq!(wallet.balance(), 50);
+
+        // salary isn't shown in the history, but the claim is
+        let entries = LedgerEntry::get_recent(&db, guild_id, user_id, 10)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].amount, 50);
+    }
+}
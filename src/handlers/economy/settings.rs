@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use poise::serenity_prelude::{ChannelId, GuildId};
+use serde::{Deserialize, Serialize};
+use surrealdb::{Connection, RecordId, Surreal};
+
+use crate::db::DbItem;
+
+pub const ECONOMY_SETTINGS_TABLE: &str = "guild_economy_settings";
+
+/// How often payouts can be claimed, unless a guild says otherwise.
+pub const DEFAULT_PAYOUT_INTERVAL: Duration = Duration::from_mins(5);
+
+/// How a guild's economy is set up. Guilds without settings in the database
+/// use the defaults.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct EconomySettings {
+    /// The guild these settings are for.
+    #[serde(skip)]
+    guild_id: GuildId,
+
+    /// Salaries are multiplied by this before they're paid.
+    #[serde(default = "default_salary_multiplier")]
+    pub salary_multiplier: f64,
+
+    /// How long users have to wait between claiming payouts.
+    #[serde(default = "default_payout_interval")]
+    pub payout_interval: Duration,
+
+    /// Channels in which chatting doesn't earn any salary.
+    #[serde(default)]
+    pub excluded_channels: Vec<ChannelId>,
+
+    /// Whether people can bet their coins on games.
+    #[serde(default)]
+    pub betting_enabled: bool,
+
+    /// The most coins anyone can bet at once.
+    #[serde(default = "default_max_bet")]
+    pub max_bet: u64,
+
+    /// The fraction of winnings kept by the house, from 0 to 1.
+    #[serde(default = "default_house_edge")]
+    pub house_edge: f64,
+}
+
+fn default_salary_multiplier() -> f64 {
+    1.0
+}
+
+fn default_payout_interval() -> Duration {
+    DEFAULT_PAYOUT_INTERVAL
+}
+
+fn default_max_bet() -> u64 {
+    1000
+}
+
+fn default_house_edge() -> f64 {
+    0.05
+}
+
+#[async_trait]
+impl<C: Connection> DbItem<C> for EconomySettings {
+    type GetQuery = GuildId;
+    type Id = i64;
+    type UpsertContent = Self;
+
+    const NAME: &'static str = ECONOMY_SETTINGS_TABLE;
+
+    fn get_id(&self) -> Self::Id {
+        self.guild_id.get() as i64
+    }
+
+    async fn get_from_db(
+        db: &Surreal<C>,
+        guild_id: Self::GetQuery,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        let mut result = db
+            .query("SELECT * FROM $thing;")
+            .bind((
+                "thing",
+                RecordId::from_table_key(ECONOMY_SETTINGS_TABLE, guild_id.get() as i64),
+            ))
+            .await?;
+
+        Ok(result.take::<Option<Self>>(0)?.map(|mut s| {
+            s.guild_id = guild_id;
+            s
+        }))
+    }
+}
+
+impl EconomySettings {
+    pub fn new(guild_id: GuildId) -> Self {
+        Self {
+            guild_id,
+            salary_multiplier: default_salary_multiplier(),
+            payout_interval: default_payout_interval(),
+            excluded_channels: Vec::new(),
+            betting_enabled: false,
+            max_bet: default_max_bet(),
+            house_edge: default_house_edge(),
+        }
+    }
+
+    /// Returns a guild's settings, or the defaults if it doesn't have any.
+    pub async fn get_or_default<C: Connection>(
+        db: &Surreal<C>,
+        guild_id: GuildId,
+    ) -> Result<Self, surrealdb::Error> {
+        Ok(<Self as DbItem<C>>::get_from_db(db, guild_id)
+            .await?
+            .unwrap_or_else(|| Self::new(guild_id)))
+    }
+
+    /// Saves these settings to the database.
+    pub async fn save<C: Connection>(&self, db: &Surreal<C>) -> Result<(), surrealdb::Error> {
+        self.upsert_in_db(db, self.clone()).await?;
+        Ok(())
+    }
+
+    /// Returns true if chatting in the given channel doesn't earn salary.
+    pub fn is_channel_excluded(&self, channel_id: ChannelId) -> bool {
+        self.excluded_channels.contains(&channel_id)
+    }
+}
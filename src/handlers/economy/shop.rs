@@ -0,0 +1,527 @@
+use std::{fmt::Display, time::Duration};
+
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use poise::serenity_prelude::{GuildId, Mentionable, RoleId, UserId};
+use serde::{Deserialize, Serialize};
+use surrealdb::{Connection, RecordId, Surreal};
+use thiserror::Error;
+use tokio::task::JoinHandle;
+
+use super::{
+    ledger::LedgerEntryKind,
+    wallet::{Wallet, WalletError},
+};
+use crate::{discord::state::GlobalAccess, MuniBotError};
+
+pub const SHOP_ITEM_TABLE: &str = "shop_item";
+pub const INVENTORY_ITEM_TABLE: &str = "inventory_item";
+
+/// How often to check for temporary roles that have run out.
+const ROLE_EXPIRY_INTERVAL: Duration = Duration::from_mins(1);
+
+/// What buying a shop item gets you.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShopItemKind {
+    /// A role, kept forever.
+    Role { role_id: RoleId },
+
+    /// A role that's taken away again after a while.
+    TemporaryRole { role_id: RoleId, duration: Duration },
+
+    /// A custom title, shown alongside your name in your wallet.
+    Title { title: String },
+
+    /// Something kept in your inventory until it's used up with `/use`.
+    Consumable,
+}
+
+impl ShopItemKind {
+    /// The role that buying this item grants, if any.
+    pub fn role_id(&self) -> Option<RoleId> {
+        match self {
+            ShopItemKind::Role { role_id } | ShopItemKind::TemporaryRole { role_id, .. } => {
+                Some(*role_id)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for ShopItemKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ShopItemKind::Role { role_id } => write!(f, "the {} role", role_id.mention()),
+            ShopItemKind::TemporaryRole { role_id, duration } => write!(
+                f,
+                "the {} role for {}",
+                role_id.mention(),
+                humantime::format_duration(*duration)
+            ),
+            ShopItemKind::Title { title } => write!(f, "the title \"{title}\""),
+            ShopItemKind::Consumable => write!(f, "something you can /use"),
+        }
+    }
+}
+
+/// Something that can be bought in a guild's shop.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ShopItem {
+    pub guild_id: GuildId,
+    pub name: String,
+    pub description: String,
+    pub price: u64,
+    pub kind: ShopItemKind,
+}
+
+impl ShopItem {
+    /// Returns every item in a guild's shop, cheapest first.
+    pub async fn get_all<C: Connection>(
+        db: &Surreal<C>,
+        guild_id: GuildId,
+    ) -> Result<Vec<Self>, surrealdb::Error> {
+        db.query(format!(
+            "SELECT * FROM {SHOP_ITEM_TABLE}
+             WHERE guild_id = $guild
+             ORDER BY price, name;"
+        ))
+        .bind(("guild", guild_id))
+        .await?
+        .take(0)
+    }
+
+    /// Finds an item in a guild's shop by its name, ignoring case.
+    pub async fn get_by_name<C: Connection>(
+        db: &Surreal<C>,
+        guild_id: GuildId,
+        name: &str,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        db.query(format!(
+            "SELECT * FROM {SHOP_ITEM_TABLE}
+             WHERE guild_id = $guild AND string::lowercase(name) = string::lowercase($name)
+             LIMIT 1;"
+        ))
+        .bind(("guild", guild_id))
+        .bind(("name", name.to_string()))
+        .await?
+        .take(0)
+    }
+
+    /// Adds this item to its guild's shop. Returns false if an item with the
+    /// same name is already in the shop.
+    pub async fn add<C: Connection>(&self, db: &Surreal<C>) -> Result<bool, surrealdb::Error> {
+        if Self::get_by_name(db, self.guild_id, &self.name)
+            .await?
+            .is_some()
+        {
+            return Ok(false);
+        }
+
+        db.query(format!("CREATE {SHOP_ITEM_TABLE} CONTENT $item;"))
+            .bind(("item", self.clone()))
+            .await?
+            .check()?;
+        Ok(true)
+    }
+
+    /// Removes an item from a guild's shop by its name, returning it if it
+    /// existed. Items already bought stay in people's inventories.
+    pub async fn remove<C: Connection>(
+        db: &Surreal<C>,
+        guild_id: GuildId,
+        name: &str,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        db.query(format!(
+            "DELETE {SHOP_ITEM_TABLE}
+             WHERE guild_id = $guild AND string::lowercase(name) = string::lowercase($name)
+             RETURN BEFORE;"
+        ))
+        .bind(("guild", guild_id))
+        .bind(("name", name.to_string()))
+        .await?
+        .take::<Vec<Self>>(0)
+        .map(|items| items.into_iter().next())
+    }
+
+    /// Buys this item with the given wallet, adding it to the wallet owner's
+    /// inventory. Granting any role is up to the caller.
+    pub async fn buy<C: Connection>(
+        &self,
+        db: &Surreal<C>,
+        wallet: &mut Wallet,
+        user_id: UserId,
+    ) -> Result<(), ShopError> {
+        let expires_at = match &self.kind {
+            ShopItemKind::TemporaryRole { duration, .. } => Some(Utc::now() + *duration),
+            _ => None,
+        };
+
+        wallet
+            .spend_on(
+                db,
+                self.price,
+                LedgerEntryKind::Purchase,
+                INVENTORY_ITEM_TABLE,
+                InventoryItemData {
+                    guild_id: self.guild_id,
+                    user_id,
+                    name: self.name.clone(),
+                    kind: self.kind.clone(),
+                    price: self.price,
+                    purchased_at: Utc::now(),
+                    expires_at,
+                    expired: false,
+                    used: false,
+                },
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct InventoryItemData {
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+
+    /// The name of the shop item at the time it was bought.
+    pub name: String,
+    pub kind: ShopItemKind,
+    pub price: u64,
+
+    /// When the item was bought. This is set by the database.
+    #[serde(skip_serializing)]
+    pub purchased_at: DateTime<Utc>,
+
+    /// When a temporary role runs out.
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// Whether a temporary role has run out and been taken away.
+    #[serde(default)]
+    pub expired: bool,
+
+    /// Whether a consumable has been used up.
+    #[serde(default)]
+    pub used: bool,
+}
+
+/// Something someone has bought.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct InventoryItem {
+    id: RecordId,
+
+    #[serde(flatten)]
+    pub data: InventoryItemData,
+}
+
+impl InventoryItem {
+    /// Returns everything a user has bought in a guild, newest first.
+    pub async fn get_for_user<C: Connection>(
+        db: &Surreal<C>,
+        guild_id: GuildId,
+        user_id: UserId,
+    ) -> Result<Vec<Self>, surrealdb::Error> {
+        db.query(format!(
+            "SELECT * FROM {INVENTORY_ITEM_TABLE}
+             WHERE guild_id = $guild AND user_id = $user
+             ORDER BY purchased_at DESC;"
+        ))
+        .bind(("guild", guild_id))
+        .bind(("user", user_id))
+        .await?
+        .take(0)
+    }
+
+    /// Returns the title a user bought most recently, if they've bought any.
+    pub async fn latest_title<C: Connection>(
+        db: &Surreal<C>,
+        guild_id: GuildId,
+        user_id: UserId,
+    ) -> Result<Option<String>, surrealdb::Error> {
+        Ok(Self::get_for_user(db, guild_id, user_id)
+            .await?
+            .into_iter()
+            .find_map(|item| match item.data.kind {
+                ShopItemKind::Title { title } => Some(title),
+                _ => None,
+            }))
+    }
+
+    /// Returns the names of the consumables a user has and hasn't used yet,
+    /// without duplicates.
+    pub async fn unused_consumable_names<C: Connection>(
+        db: &Surreal<C>,
+        guild_id: GuildId,
+        user_id: UserId,
+    ) -> Result<Vec<String>, surrealdb::Error> {
+        db.query(format!(
+            "SELECT name FROM {INVENTORY_ITEM_TABLE}
+             WHERE guild_id = $guild AND user_id = $user
+                AND kind.type = 'consumable' AND used != true
+             GROUP BY name;"
+        ))
+        .bind(("guild", guild_id))
+        .bind(("user", user_id))
+        .await?
+        .take((0, "name"))
+    }
+
+    /// Uses up one of a user's consumables by its name, ignoring case. The
+    /// oldest one is used first. Returns the item that was used, or `None` if
+    /// the user doesn't have one left.
+    pub async fn use_consumable<C: Connection>(
+        db: &Surreal<C>,
+        guild_id: GuildId,
+        user_id: UserId,
+        name: &str,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        let used: Vec<Self> = db
+            .query(format!(
+                "UPDATE (
+                    SELECT id, purchased_at FROM {INVENTORY_ITEM_TABLE}
+                    WHERE guild_id = $guild AND user_id = $user
+                        AND kind.type = 'consumable' AND used != true
+                        AND string::lowercase(name) = string::lowercase($name)
+                    ORDER BY purchased_at
+                    LIMIT 1
+                 ).id SET used = true RETURN AFTER;"
+            ))
+            .bind(("guild", guild_id))
+            .bind(("user", user_id))
+            .bind(("name", name.to_string()))
+            .await?
+            .take(0)?;
+        Ok(used.into_iter().next())
+    }
+
+    /// Returns every temporary role that has run out but hasn't been taken
+    /// away yet.
+    async fn get_expired_roles<C: Connection>(
+        db: &Surreal<C>,
+    ) -> Result<Vec<Self>, surrealdb::Error> {
+        let items: Vec<Self> = db
+            .query(format!(
+                "SELECT * FROM {INVENTORY_ITEM_TABLE}
+                 WHERE kind.type = 'temporary_role' AND expired = false;"
+            ))
+            .await?
+            .take(0)?;
+
+        let now = Utc::now();
+        Ok(items
+            .into_iter()
+            .filter(|item| item.data.expires_at.is_some_and(|t| t <= now))
+            .collect())
+    }
+
+    /// Marks this item's temporary role as taken away.
+    async fn mark_expired<C: Connection>(&self, db: &Surreal<C>) -> Result<(), surrealdb::Error> {
+        db.query("UPDATE $item SET expired = true;")
+            .bind(("item", self.id.clone()))
+            .await?
+            .check()?;
+        Ok(())
+    }
+}
+
+/// Starts a task that takes temporary roles away from people once they run
+/// out.
+pub fn start_role_expiry(access: GlobalAccess) -> JoinHandle<!> {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(ROLE_EXPIRY_INTERVAL).await;
+
+            let expired = match InventoryItem::get_expired_roles(access.db()).await {
+                Ok(expired) => expired,
+                Err(e) => {
+                    error!("couldn't check for expired shop roles :< {e}");
+                    continue;
+                }
+            };
+
+            for item in expired {
+                let InventoryItemData {
+                    guild_id,
+                    user_id,
+                    ref kind,
+                    ..
+                } = item.data;
+                if let Some(role_id) = kind.role_id() {
+                    match access
+                        .http()
+                        .remove_member_role(
+                            guild_id,
+                            user_id,
+                            role_id,
+                            Some("temporary role from the shop ran out"),
+                        )
+                        .await
+                    {
+                        Ok(()) => info!("took expired shop role {role_id} from {user_id}"),
+                        // don't keep trying forever if the role or member is
+                        // gone, or munibot can't manage the role anymore
+                        Err(e) => error!(
+                            "couldn't take expired shop role {role_id} from {user_id} :< {e}"
+                        ),
+                    }
+                }
+
+                if let Err(e) = item.mark_expired(access.db()).await {
+                    error!("couldn't mark shop role as expired :< {e}");
+                }
+            }
+        }
+    })
+}
+
+#[derive(Error, Debug)]
+pub enum ShopError {
+    #[error("error in shop database: {0}")]
+    Database(#[from] surrealdb::Error),
+
+    #[error("error with wallet: {0}")]
+    Wallet(#[from] WalletError),
+}
+
+impl From<ShopError> for MuniBotError {
+    fn from(e: ShopError) -> Self {
+        MuniBotError::Other(format!("{e}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use poise::serenity_prelude::{GuildId, RoleId, UserId};
+
+    use super::{InventoryItem, ShopError, ShopItem, ShopItemKind};
+    use crate::{
+        db::test_db,
+        handlers::economy::{
+            ledger::LedgerEntryKind,
+            wallet::{Wallet, WalletError},
+        },
+    };
+
+    #[tokio::test]
+    async fn test_shop_purchases() {
+        let db = test_db().await;
+        let guild_id = GuildId::new(1);
+        let user_id = UserId::new(2);
+
+        let title = ShopItem {
+            guild_id,
+            name: "Cool Title".to_string(),
+            description: String::new(),
+            price: 30,
+            kind: ShopItemKind::Title {
+                title: "the cool".to_string(),
+            },
+        };
+        let role = ShopItem {
+            guild_id,
+            name: "vip".to_string(),
+            description: String::new(),
+            price: 50,
+            kind: ShopItemKind::TemporaryRole {
+                role_id: RoleId::new(3),
+                duration: Duration::ZERO,
+            },
+        };
+        assert!(title.add(&db).await.unwrap());
+        assert!(role.add(&db).await.unwrap());
+        assert!(!title.add(&db).await.unwrap());
+
+        let title = ShopItem::get_by_name(&db, guild_id, "cool title")
+            .await
+            .unwrap()
+            .unwrap();
+
+        let mut wallet = Wallet::create_in_db(&db, guild_id, user_id, 60)
+            .await
+            .unwrap();
+        title.buy(&db, &mut wallet, user_id).await.unwrap();
+        assert_eq!(wallet.balance(), 30);
+        assert!(matches!(
+            role.buy(&db, &mut wallet, user_id).await,
+            Err(ShopError::Wallet(WalletError::InsufficientFunds))
+        ));
+        assert_eq!(wallet.balance(), 30);
+
+        let inventory = InventoryItem::get_for_user(&db, guild_id, user_id)
+            .await
+            .unwrap();
+        assert_eq!(inventory.len(), 1);
+        assert_eq!(
+            InventoryItem::latest_title(&db, guild_id, user_id)
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("the cool")
+        );
+
+        wallet
+            .deposit(&db, 20, LedgerEntryKind::AdminGrant, None)
+            .await
+            .unwrap();
+        role.buy(&db, &mut wallet, user_id).await.unwrap();
+        assert_eq!(wallet.balance(), 0);
+
+        let expired = InventoryItem::get_expired_roles(&db).await.unwrap();
+        assert_eq!(expired.len(), 1);
+        expired[0].mark_expired(&db).await.unwrap();
+        assert!(InventoryItem::get_expired_roles(&db)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_using_consumables() {
+        let db = test_db().await;
+        let guild_id = GuildId::new(1);
+        let user_id = UserId::new(2);
+
+        let cookie = ShopItem {
+            guild_id,
+            name: "Cookie".to_string(),
+            description: String::new(),
+            price: 5,
+            kind: ShopItemKind::Consumable,
+        };
+        let mut wallet = Wallet::create_in_db(&db, guild_id, user_id, 10)
+            .await
+            .unwrap();
+        cookie.buy(&db, &mut wallet, user_id).await.unwrap();
+        cookie.buy(&db, &mut wallet, user_id).await.unwrap();
+        assert_eq!(
+            InventoryItem::unused_consumable_names(&db, guild_id, user_id)
+                .await
+                .unwrap(),
+            vec!["Cookie".to_string()]
+        );
+
+        for _ in 0..2 {
+            let used = InventoryItem::use_consumable(&db, guild_id, user_id, "cookie")
+                .await
+                .unwrap();
+            assert!(used.is_some_and(|item| item.data.used));
+        }
+        assert!(
+            InventoryItem::use_consumable(&db, guild_id, user_id, "cookie")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            InventoryItem::unused_consumable_names(&db, guild_id, user_id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use poise::serenity_prelude::GuildId;
+
+use super::owner::WalletOwner;
+
+/// How far back the spam filter looks when deciding whether to pay for a
+/// message.
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// How many messages someone can be paid for within [`RATE_WINDOW`].
+const MAX_PAID_MESSAGES: usize = 6;
+
+/// How many of someone's recent messages are remembered, to catch them
+/// repeating themselves.
+const REMEMBERED_MESSAGES: usize = 5;
+
+/// What the spam filter remembers about someone's recent chatting.
+#[derive(Debug, Default)]
+struct RecentActivity {
+    /// When each of their recent paid messages was sent, oldest first.
+    paid_at: VecDeque<Instant>,
+
+    /// The (normalized) content of their most recent messages, oldest first.
+    messages: VecDeque<String>,
+
+    /// When they last sent a message at all.
+    last_seen: Option<Instant>,
+}
+
+/// Decides which messages are worth paying salary for, so repeating the same
+/// message or rapid-fire spam doesn't earn coins.
+#[derive(Debug, Default)]
+pub struct SpamFilter {
+    recent: HashMap<(GuildId, WalletOwner), RecentActivity>,
+    last_cleanup: Option<Instant>,
+}
+
+impl SpamFilter {
+    /// Records a message and returns true if it should be paid for. A message
+    /// isn't paid for if it repeats one of the author's recent messages, or
+    /// if they've already been paid for too many messages recently.
+    pub fn should_pay(
+        &mut self,
+        guild_id: GuildId,
+        user_id: impl Into<WalletOwner>,
+        content: &str,
+        now: Instant,
+    ) -> bool {
+        self.clean_up(now);
+
+        let activity = self.recent.entry((guild_id, user_id.into())).or_default();
+        activity.last_seen = Some(now);
+        while activity
+            .paid_at
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > RATE_WINDOW)
+        {
+            activity.paid_at.pop_front();
+        }
+
+        let content = normalize(content);
+        let repeated = activity.messages.contains(&content);
+        activity.messages.push_back(content);
+        if activity.messages.len() > REMEMBERED_MESSAGES {
+            activity.messages.pop_front();
+        }
+
+        if repeated || activity.paid_at.len() >= MAX_PAID_MESSAGES {
+            return false;
+        }
+
+        activity.paid_at.push_back(now);
+        true
+    }
+
+    /// Forgets about anyone who hasn't chatted in a while, at most once per
+    /// [`RATE_WINDOW`].
+    fn clean_up(&mut self, now: Instant) {
+        if self
+            .last_cleanup
+            .is_some_and(|t| now.duration_since(t) < RATE_WINDOW)
+        {
+            return;
+        }
+
+        self.recent.retain(|_, activity| {
+            activity
+                .last_seen
+                .is_some_and(|t| now.duration_since(t) <= RATE_WINDOW)
+        });
+        self.last_cleanup = Some(now);
+    }
+}
+
+/// Normalizes message content so trivial changes (like case or extra spaces)
+/// don't get around duplicate detection.
+fn normalize(content: &str) -> String {
+    content
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use poise::serenity_prelude::{GuildId, UserId};
+
+    use super::{SpamFilter, MAX_PAID_MESSAGES, RATE_WINDOW};
+
+    #[test]
+    fn test_spam_filter() {
+        let mut filter = SpamFilter::default();
+        let (guild_id, user_id) = (GuildId::new(1), UserId::new(2));
+        let start = Instant::now();
+
+        // repeats aren't paid, even with different spacing or case
+        assert!(filter.should_pay(guild_id, user_id, "hello there", start));
+        assert!(!filter.should_pay(guild_id, user_id, "Hello  there", start));
+
+        // only so many messages are paid within the window
+        for i in 1..MAX_PAID_MESSAGES {
+            assert!(filter.should_pay(guild_id, user_id, &format!("message {i}"), start));
+        }
+        assert!(!filter.should_pay(guild_id, user_id, "one too many", start));
+
+        // someone else isn't affected
+        assert!(filter.should_pay(guild_id, UserId::new(3), "hello there", start));
+
+        // once the window passes, messages are paid again
+        let later = start + RATE_WINDOW + Duration::from_secs(1);
+        assert!(filter.should_pay(guild_id, user_id, "i'm back", later));
+    }
+}
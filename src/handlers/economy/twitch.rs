@@ -0,0 +1,216 @@
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use num_format::{Locale, ToFormattedString};
+use poise::serenity_prelude::GuildId;
+use twitch_irc::message::{PrivmsgMessage, ServerMessage};
+
+use super::{
+    owner::WalletOwner,
+    payout::{ClaimResult, Payout, PayoutError},
+    settings::EconomySettings,
+    spam::SpamFilter,
+    wallet::Wallet,
+    EconomyProvider,
+};
+use crate::{
+    config::Config,
+    db::MuniBotDb,
+    twitch::{
+        agent::TwitchAgent,
+        bot::MuniBotTwitchIRCClient,
+        commands::TwitchCommand,
+        handler::{TwitchHandlerError, TwitchMessageHandler},
+    },
+};
+
+const COMMANDS: [TwitchCommand; 2] = [TwitchCommand::new("wallet"), TwitchCommand::new("claim")];
+
+/// Pays Twitch chatters for chatting and handles `!wallet` and `!claim`.
+/// Chatters earn coins in the economy of the Discord server their channel is
+/// set up with (see `discord_guild` in the channel config).
+pub struct TwitchEconomyHandler {
+    db: MuniBotDb,
+    spam_filter: SpamFilter,
+}
+
+impl TwitchEconomyHandler {
+    pub fn new(db: MuniBotDb) -> Self {
+        Self {
+            db,
+            spam_filter: SpamFilter::default(),
+        }
+    }
+
+    /// Replies with the chatter's wallet balance.
+    async fn show_wallet(
+        &mut self,
+        m: &PrivmsgMessage,
+        client: &MuniBotTwitchIRCClient,
+        wallet: Wallet,
+    ) -> Result<(), TwitchHandlerError> {
+        self.send_twitch_message(
+            client,
+            &m.channel_login,
+            &format!(
+                "@{} you have {} coins in your wallet.",
+                m.sender.name,
+                wallet.balance().to_formatted_string(&Locale::en)
+            ),
+        )
+        .await
+    }
+
+    /// Claims the chatter's payout into their wallet and replies with the
+    /// result.
+    async fn claim(
+        &mut self,
+        m: &PrivmsgMessage,
+        client: &MuniBotTwitchIRCClient,
+        mut payout: Payout,
+        settings: &EconomySettings,
+    ) -> Result<(), TwitchHandlerError> {
+        let reply = match payout
+            .claim_to_wallet(&self.db, settings.payout_interval)
+            .await
+        {
+            Ok(ClaimResult {
+                amount_claimed,
+                new_balance,
+            }) => format!(
+                "@{} here are {} coins! ^w^ you now have {} coins.",
+                m.sender.name,
+                amount_claimed.to_formatted_string(&Locale::en),
+                new_balance.to_formatted_string(&Locale::en)
+            ),
+            Err(PayoutError::TooSoon) => {
+                let wait = (payout.next_payout_time(settings.payout_interval)
+                    - chrono::Local::now())
+                .num_seconds()
+                .max(1) as u64;
+                format!(
+                    "@{} you can't claim your payout yet! try again in {}.",
+                    m.sender.name,
+                    humantime::format_duration(Duration::from_secs(wait))
+                )
+            }
+            Err(PayoutError::NothingToClaim) => format!(
+                "@{} your payout is empty at the moment. try again later!",
+                m.sender.name
+            ),
+            Err(e) => {
+                return Err(TwitchHandlerError::Other(format!(
+                    "error claiming payout: {e}"
+                )))
+            }
+        };
+
+        self.send_twitch_message(client, &m.channel_login, &reply)
+            .await
+    }
+
+    /// Pays salary for a message, unless it's spam.
+    async fn pay_salary(
+        &mut self,
+        m: &PrivmsgMessage,
+        guild_id: GuildId,
+        owner: WalletOwner,
+        settings: &EconomySettings,
+    ) -> Result<(), TwitchHandlerError> {
+        let salary = (EconomyProvider::calc_salary(&m.message_text) as f64
+            * settings.salary_multiplier)
+            .round() as u64;
+        if salary == 0
+            || !self.spam_filter.should_pay(
+                guild_id,
+                owner.clone(),
+                &m.message_text,
+                Instant::now(),
+            )
+        {
+            return Ok(());
+        }
+
+        Payout::get_from_db(&self.db, guild_id, owner)
+            .await
+            .map_err(|e| TwitchHandlerError::Other(format!("error getting payout from db: {e}")))?
+            .deposit(&self.db, salary)
+            .await
+            .map_err(|e| {
+                TwitchHandlerError::Other(format!("error depositing salary into payout: {e}"))
+            })
+    }
+}
+
+#[async_trait]
+impl TwitchMessageHandler for TwitchEconomyHandler {
+    fn twitch_handler_name(&self) -> &'static str {
+        "economy"
+    }
+
+    fn twitch_commands(&self) -> &'static [TwitchCommand] {
+        &COMMANDS
+    }
+
+    async fn handle_twitch_message(
+        &mut self,
+        message: &ServerMessage,
+        client: &MuniBotTwitchIRCClient,
+        _agent: &TwitchAgent,
+        config: &Config,
+    ) -> Result<bool, TwitchHandlerError> {
+        let ServerMessage::Privmsg(m) = message else {
+            return Ok(false);
+        };
+
+        // only channels set up with a discord server have an economy
+        let Some(guild_id) = config
+            .twitch
+            .channels
+            .get(&m.channel_login)
+            .and_then(|channel| channel.discord_guild)
+        else {
+            return Ok(false);
+        };
+
+        // munibot doesn't pay itself
+        if m.sender
+            .login
+            .eq_ignore_ascii_case(&config.twitch.twitch_user)
+        {
+            return Ok(false);
+        }
+
+        let settings = EconomySettings::get_or_default(&self.db, guild_id).await?;
+        let owner = WalletOwner::for_twitch_user(&self.db, &m.sender.id).await?;
+
+        let command = m.message_text.split_whitespace().next().unwrap_or_default();
+        match command {
+            "!wallet" => {
+                let wallet = Wallet::get_from_db(&self.db, guild_id, owner)
+                    .await
+                    .map_err(|e| {
+                        TwitchHandlerError::Other(format!("error getting wallet from db: {e}"))
+                    })?;
+                self.show_wallet(m, client, wallet).await?;
+                Ok(true)
+            }
+            "!claim" => {
+                let payout = Payout::get_from_db(&self.db, guild_id, owner)
+                    .await
+                    .map_err(|e| {
+                        TwitchHandlerError::Other(format!("error getting payout from db: {e}"))
+                    })?;
+                self.claim(m, client, payout, &settings).await?;
+                Ok(true)
+            }
+            // other commands don't earn anything, and are left for other
+            // handlers
+            c if c.starts_with('!') => Ok(false),
+            _ => {
+                self.pay_salary(m, guild_id, owner, &settings).await?;
+                Ok(false)
+            }
+        }
+    }
+}
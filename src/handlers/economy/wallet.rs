@@ -3,6 +3,11 @@ use serde::{Deserialize, Serialize};
 use surrealdb::{Connection, RecordId, Surreal};
 use thiserror::Error;
 
+use super::{
+    ledger::{LedgerEntry, LedgerEntryKind, LEDGER_TABLE},
+    owner::WalletOwner,
+    payout::GUILD_PAYOUT_TABLE,
+};
 use crate::MuniBotError;
 
 pub const GUILD_WALLET_TABLE: &str = "guild_wallet";
@@ -10,7 +15,7 @@ pub const GUILD_WALLET_TABLE: &str = "guild_wallet";
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct WalletData {
     guild_id: GuildId,
-    user_id: UserId,
+    user_id: WalletOwner,
     balance: u64,
 }
 
@@ -28,15 +33,16 @@ impl Wallet {
     pub async fn get_from_db<C: Connection>(
         db: &Surreal<C>,
         guild_id: GuildId,
-        user_id: UserId,
+        user_id: impl Into<WalletOwner>,
     ) -> Result<Self, WalletError> {
+        let user_id = user_id.into();
         match db
             .query(format!(
                 "SELECT * FROM {GUILD_WALLET_TABLE}
                  WHERE guild_id = $guild AND user_id = $user;"
             ))
             .bind(("guild", guild_id))
-            .bind(("user", user_id))
+            .bind(("user", user_id.clone()))
             .await?
             .take::<Option<Self>>(0)?
         {
@@ -49,14 +55,15 @@ impl Wallet {
     pub async fn create_in_db<C: Connection>(
         db: &Surreal<C>,
         guild_id: GuildId,
-        user_id: UserId,
+        user_id: impl Into<WalletOwner>,
         balance: u64,
     ) -> Result<Self, WalletError> {
+        let user_id = user_id.into();
         // insert the wallet into the wallet table
         db.create::<Option<Self>>(GUILD_WALLET_TABLE)
             .content(WalletData {
                 guild_id,
-                user_id,
+                user_id: user_id.clone(),
                 balance,
             })
             .await
@@ -64,38 +71,290 @@ impl Wallet {
             .and_then(|opt| opt.ok_or_else(|| WalletError::NotCreated(user_id, guild_id)))
     }
 
-    /// Updates this wallet in the database.
-    async fn update_in_db<C: Connection>(&self, db: &Surreal<C>) -> Result<(), WalletError> {
-        let data = self.data.clone();
-        db.update::<Option<Self>>((GUILD_WALLET_TABLE, self.id.key().clone()))
-            .content(data)
-            .await?;
-        Ok(())
-    }
-
-    /// Deposits the given amount into the wallet and updates it in the
-    /// database.
+    /// Deposits the given amount into the wallet, recording why (and who
+    /// else was involved, if anyone) in the ledger.
     pub async fn deposit<C: Connection>(
         &mut self,
         db: &Surreal<C>,
         amount: u64,
+        kind: LedgerEntryKind,
+        counterparty: Option<UserId>,
     ) -> Result<(), WalletError> {
-        self.data.balance += amount;
-        self.update_in_db(db).await
+        let amount = signed_amount(amount)?;
+        self.apply(db, self.ledger_entry(amount, kind, counterparty))
+            .await
     }
 
-    /// Spends the given amount from the wallet and updates it in the database.
+    /// Spends the given amount from the wallet, recording why (and who else
+    /// was involved, if anyone) in the ledger. Fails if the wallet doesn't
+    /// have enough coins.
     pub async fn spend<C: Connection>(
         &mut self,
         db: &Surreal<C>,
         amount: u64,
+        kind: LedgerEntryKind,
+        counterparty: Option<UserId>,
+    ) -> Result<(), WalletError> {
+        let amount = signed_amount(amount)?;
+        self.apply(db, self.ledger_entry(-amount, kind, counterparty))
+            .await
+    }
+
+    /// Spends the given amount from the wallet and creates a record of what
+    /// the coins were spent on, all in one transaction. The record isn't
+    /// created if the wallet doesn't have enough coins.
+    pub async fn spend_on<C: Connection, T: Serialize + Send + 'static>(
+        &mut self,
+        db: &Surreal<C>,
+        amount: u64,
+        kind: LedgerEntryKind,
+        table: &str,
+        record: T,
+    ) -> Result<(), WalletError> {
+        let entry = self.ledger_entry(-signed_amount(amount)?, kind, None);
+        let result: Option<BalanceChange> = db
+            .query(format!(
+                "BEGIN TRANSACTION;
+                 LET $updated = (
+                     UPDATE $wallet SET balance += $entry.amount
+                     WHERE balance + $entry.amount >= 0
+                 );
+                 IF !array::is_empty($updated) {{
+                     CREATE {LEDGER_TABLE} CONTENT $entry;
+                     CREATE type::table($table) CONTENT $record;
+                 }};
+                 RETURN {{ applied: !array::is_empty($updated), balance: $wallet.balance }};
+                 COMMIT TRANSACTION;"
+            ))
+            .bind(("wallet", self.id.clone()))
+            .bind(("entry", entry))
+            .bind(("table", table.to_string()))
+            .bind(("record", record))
+            .await?
+            .take(0)?;
+
+        let result = result.ok_or(WalletError::NotFound)?;
+        self.data.balance = result.balance;
+        if result.applied {
+            Ok(())
+        } else {
+            Err(WalletError::InsufficientFunds)
+        }
+    }
+
+    /// Empties the wallet, recording who did it in the ledger.
+    pub async fn reset<C: Connection>(
+        &mut self,
+        db: &Surreal<C>,
+        admin: UserId,
+    ) -> Result<(), WalletError> {
+        let entry = self.ledger_entry(0, LedgerEntryKind::AdminReset, Some(admin));
+        let balance: Option<u64> = db
+            .query(format!(
+                "BEGIN TRANSACTION;
+                 LET $before = (UPDATE $wallet SET balance = 0 RETURN BEFORE)[0].balance ?? 0;
+                 IF $before > 0 {{
+                     CREATE {LEDGER_TABLE} SET
+                         guild_id = $entry.guild_id,
+                         user_id = $entry.user_id,
+                         amount = -$before,
+                         kind = $entry.kind,
+                         counterparty = $entry.counterparty;
+                 }};
+                 RETURN $wallet.balance;
+                 COMMIT TRANSACTION;"
+            ))
+            .bind(("wallet", self.id.clone()))
+            .bind(("entry", entry))
+            .await?
+            .take(0)?;
+
+        self.data.balance = balance.ok_or(WalletError::NotFound)?;
+        Ok(())
+    }
+
+    /// Empties every wallet in a guild, recording who did it in the ledger.
+    /// Pending payouts are emptied too. Returns how many wallets had coins.
+    pub async fn reset_guild<C: Connection>(
+        db: &Surreal<C>,
+        guild_id: GuildId,
+        admin: UserId,
+    ) -> Result<usize, WalletError> {
+        let reset_count: Option<usize> = db
+            .query(format!(
+                "BEGIN TRANSACTION;
+                 LET $wallets = (
+                     SELECT guild_id, user_id, balance FROM {GUILD_WALLET_TABLE}
+                     WHERE guild_id = $guild AND balance > 0
+                 );
+                 FOR $wallet IN $wallets {{
+                     CREATE {LEDGER_TABLE} CONTENT {{
+                         guild_id: $wallet.guild_id,
+                         user_id: $wallet.user_id,
+                         amount: -$wallet.balance,
+                         kind: $kind,
+                         counterparty: $admin,
+                     }};
+                 }};
+                 UPDATE {GUILD_WALLET_TABLE} SET balance = 0 WHERE guild_id = $guild;
+                 UPDATE {GUILD_PAYOUT_TABLE} SET balance = 0 WHERE guild_id = $guild;
+                 RETURN array::len($wallets);
+                 COMMIT TRANSACTION;"
+            ))
+            .bind(("guild", guild_id))
+            .bind(("admin", admin))
+            .bind(("kind", LedgerEntryKind::AdminReset))
+            .await?
+            .take(0)?;
+
+        Ok(reset_count.unwrap_or(0))
+    }
+
+    /// Settles a bet: the wager is taken from the wallet and any winnings
+    /// are paid back into it, all as one change recorded in the ledger. Fails
+    /// if the wallet can't cover the wager.
+    pub async fn settle_bet<C: Connection>(
+        &mut self,
+        db: &Surreal<C>,
+        wager: u64,
+        winnings: u64,
+    ) -> Result<(), WalletError> {
+        let net = signed_amount(winnings)? - signed_amount(wager)?;
+        self.apply_requiring(
+            db,
+            self.ledger_entry(net, LedgerEntryKind::Bet, None),
+            signed_amount(wager)?,
+        )
+        .await
+    }
+
+    /// Builds a ledger entry for a change to this wallet.
+    fn ledger_entry(
+        &self,
+        amount: i64,
+        kind: LedgerEntryKind,
+        counterparty: Option<UserId>,
+    ) -> LedgerEntry {
+        let entry = LedgerEntry::new(self.data.guild_id, self.data.user_id.clone(), amount, kind);
+        match counterparty {
+            Some(counterparty) => entry.with_counterparty(counterparty),
+            None => entry,
+        }
+    }
+
+    /// Applies a ledger entry's change to this wallet. The balance is changed
+    /// in the database itself (rather than read, modified, and written back),
+    /// so concurrent changes can't overwrite each other or spend the same
+    /// coins twice.
+    async fn apply<C: Connection>(
+        &mut self,
+        db: &Surreal<C>,
+        entry: LedgerEntry,
+    ) -> Result<(), WalletError> {
+        self.apply_requiring(db, entry, 0).await
+    }
+
+    /// Like [`Wallet::apply`], but the change is only made if the wallet
+    /// holds at least `required` coins beforehand.
+    async fn apply_requiring<C: Connection>(
+        &mut self,
+        db: &Surreal<C>,
+        entry: LedgerEntry,
+        required: i64,
+    ) -> Result<(), WalletError> {
+        let result: Option<BalanceChange> = db
+            .query(format!(
+                "BEGIN TRANSACTION;
+                 LET $updated = (
+                     UPDATE $wallet SET balance += $entry.amount
+                     WHERE balance >= $required AND balance + $entry.amount >= 0
+                 );
+                 IF !array::is_empty($updated) {{
+                     CREATE {LEDGER_TABLE} CONTENT $entry;
+                 }};
+                 RETURN {{ applied: !array::is_empty($updated), balance: $wallet.balance }};
+                 COMMIT TRANSACTION;"
+            ))
+            .bind(("wallet", self.id.clone()))
+            .bind(("entry", entry))
+            .bind(("required", required))
+            .await?
+            .take(0)?;
+
+        let result = result.ok_or(WalletError::NotFound)?;
+        self.data.balance = result.balance;
+        if result.applied {
+            Ok(())
+        } else {
+            Err(WalletError::InsufficientFunds)
+        }
+    }
+
+    /// Moves coins from this wallet to another in a single transaction, so
+    /// coins can't be lost (or duplicated) partway through. Both wallets'
+    /// balances are updated afterwards.
+    pub async fn transfer_to<C: Connection>(
+        &mut self,
+        db: &Surreal<C>,
+        recipient: &mut Wallet,
+        amount: u64,
     ) -> Result<(), WalletError> {
-        self.data.balance = self
-            .data
-            .balance
-            .checked_sub(amount)
-            .ok_or(WalletError::InsufficientFunds)?;
-        self.update_in_db(db).await
+        let signed = signed_amount(amount)?;
+        let sender_entry = LedgerEntry::new(
+            self.data.guild_id,
+            self.data.user_id.clone(),
+            -signed,
+            LedgerEntryKind::Transfer,
+        )
+        .with_counterparty(recipient.data.user_id.clone());
+        let recipient_entry = LedgerEntry::new(
+            recipient.data.guild_id,
+            recipient.data.user_id.clone(),
+            signed,
+            LedgerEntryKind::Transfer,
+        )
+        .with_counterparty(self.data.user_id.clone());
+
+        let result: Option<TransferResult> = db
+            .query(format!(
+                "BEGIN TRANSACTION;
+                 LET $sent = (
+                     UPDATE $sender SET balance -= $amount
+                     WHERE balance >= $amount
+                 );
+                 IF !array::is_empty($sent) {{
+                     UPDATE $recipient SET balance += $amount;
+                     CREATE {LEDGER_TABLE} CONTENT $sender_entry;
+                     CREATE {LEDGER_TABLE} CONTENT $recipient_entry;
+                 }};
+                 RETURN {{
+                     applied: !array::is_empty($sent),
+                     sender_balance: $sender.balance,
+                     recipient_balance: $recipient.balance,
+                 }};
+                 COMMIT TRANSACTION;"
+            ))
+            .bind(("sender", self.id.clone()))
+            .bind(("recipient", recipient.id.clone()))
+            .bind(("amount", signed))
+            .bind(("sender_entry", sender_entry))
+            .bind(("recipient_entry", recipient_entry))
+            .await?
+            .take(0)?;
+
+        let result = result.ok_or(WalletError::NotFound)?;
+        self.data.balance = result.sender_balance;
+        recipient.data.balance = result.recipient_balance;
+        if result.applied {
+            Ok(())
+        } else {
+            Err(WalletError::InsufficientFunds)
+        }
+    }
+
+    /// The id of this wallet's record.
+    pub fn id(&self) -> &RecordId {
+        &self.id
     }
 
     /// The balance of the wallet.
@@ -104,16 +363,42 @@ impl Wallet {
     }
 }
 
+/// The outcome of changing a wallet's balance in the database.
+#[derive(Deserialize)]
+struct BalanceChange {
+    applied: bool,
+    balance: u64,
+}
+
+/// The outcome of a transfer between two wallets in the database.
+#[derive(Deserialize)]
+struct TransferResult {
+    applied: bool,
+    sender_balance: u64,
+    recipient_balance: u64,
+}
+
+/// Converts an amount of coins to the signed amount stored in the ledger.
+pub(super) fn signed_amount(amount: u64) -> Result<i64, WalletError> {
+    i64::try_from(amount).map_err(|_| WalletError::AmountTooLarge(amount))
+}
+
 #[derive(Error, Debug)]
 pub enum WalletError {
     #[error("error in wallet database: {0}")]
     Database(#[from] surrealdb::Error),
 
     #[error("wallet for user {0} in guild {1} not created :<")]
-    NotCreated(UserId, GuildId),
+    NotCreated(WalletOwner, GuildId),
+
+    #[error("wallet doesn't exist anymore :<")]
+    NotFound,
 
     #[error("insufficient funds in wallet")]
     InsufficientFunds,
+
+    #[error("{0} coins is way too many coins")]
+    AmountTooLarge(u64),
 }
 
 impl From<WalletError> for MuniBotError {
@@ -121,3 +406,105 @@ impl From<WalletError> for MuniBotError {
         MuniBotError::Other(format!("{e}"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use poise::serenity_prelude::{GuildId, UserId};
+
+    use super::{Wallet, WalletError};
+    use crate::{
+        db::test_db,
+        handlers::economy::ledger::{LedgerEntry, LedgerEntryKind},
+    };
+
+    #[tokio::test]
+    async fn test_wallet_transfer() {
+        let db = test_db().await;
+        let guild_id = GuildId::new(1);
+        let (sender_id, recipient_id) = (UserId::new(2), UserId::new(3));
+
+        let mut sender = Wallet::create_in_db(&db, guild_id, sender_id, 100)
+            .await
+            .unwrap();
+        let mut recipient = Wallet::get_from_db(&db, guild_id, recipient_id)
+            .await
+            .unwrap();
+
+        sender.transfer_to(&db, &mut recipient, 30).await.unwrap();
+        assert_eq!(sender.balance(), 70);
+        assert_eq!(recipient.balance(), 30);
+
+        // nothing moves if the sender can't afford it
+        assert!(matches!(
+            sender.transfer_to(&db, &mut recipient, 71).await,
+            Err(WalletError::InsufficientFunds)
+        ));
+        let sender = Wallet::get_from_db(&db, guild_id, sender_id).await.unwrap();
+        let recipient = Wallet::get_from_db(&db, guild_id, recipient_id)
+            .await
+            .unwrap();
+        assert_eq!(sender.balance(), 70);
+        assert_eq!(recipient.balance(), 30);
+
+        // both ends of the transfer are in the ledger
+        let entries = LedgerEntry::get_recent(&db, guild_id, sender_id, 10)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].amount, -30);
+        assert_eq!(entries[0].counterparty, Some(recipient_id.into()));
+        let entries = LedgerEntry::get_recent(&db, guild_id, recipient_id, 10)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].amount, 30);
+    }
+
+    #[tokio::test]
+    async fn test_wallet_resets() {
+        let db = test_db().await;
+        let guild_id = GuildId::new(1);
+        let admin_id = UserId::new(9);
+
+        let mut wallet = Wallet::get_from_db(&db, guild_id, UserId::new(2))
+            .await
+            .unwrap();
+        wallet
+            .deposit(&db, 40, LedgerEntryKind::AdminGrant, Some(admin_id))
+            .await
+            .unwrap();
+        wallet.reset(&db, admin_id).await.unwrap();
+        assert_eq!(wallet.balance(), 0);
+
+        let entries = LedgerEntry::get_recent(&db, guild_id, UserId::new(2), 10)
+            .await
+            .unwrap();
+        assert_eq!(entries[0].kind, LedgerEntryKind::AdminReset);
+        assert_eq!(entries[0].amount, -40);
+
+        for user in [3, 4] {
+            Wallet::create_in_db(&db, guild_id, UserId::new(user), 10)
+                .await
+                .unwrap();
+        }
+        Wallet::create_in_db(&db, GuildId::new(5), UserId::new(3), 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            Wallet::reset_guild(&db, guild_id, admin_id).await.unwrap(),
+            2
+        );
+        for user in [3, 4] {
+            let wallet = Wallet::get_from_db(&db, guild_id, UserId::new(user))
+                .await
+                .unwrap();
+            assert_eq!(wallet.balance(), 0);
+        }
+
+        // other guilds are left alone
+        let other_guild_wallet = Wallet::get_from_db(&db, GuildId::new(5), UserId::new(3))
+            .await
+            .unwrap();
+        assert_eq!(other_guild_wallet.balance(), 10);
+    }
+}
@@ -14,9 +14,14 @@ pub struct EightBallProvider;
 impl EightBallProvider {
     /// Returns a random shake message, "shakes eight ball <adverb>".
     fn get_shake_message() -> String {
+        format!("shakes eight ball {}...", Self::get_adverb())
+    }
+
+    /// Returns a random adverb for how something is shaken (or flipped, or
+    /// pulled...)
+    pub fn get_adverb() -> &'static str {
         let mut rng = rand::thread_rng();
-        let adverb = SHAKE_ADVERBS.choose(&mut rng).unwrap();
-        format!("shakes eight ball {}...", adverb)
+        SHAKE_ADVERBS.choose(&mut rng).unwrap()
     }
 
     /// Returns a random eight ball response.
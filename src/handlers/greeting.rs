@@ -57,6 +57,10 @@ impl GreetingHandler {
 
 #[async_trait]
 impl TwitchMessageHandler for GreetingHandler {
+    fn twitch_handler_name(&self) -> &'static str {
+        "greeting"
+    }
+
     async fn handle_twitch_message(
         &mut self,
         message: &ServerMessage,
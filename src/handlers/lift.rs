@@ -1,4 +1,4 @@
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use twitch_irc::message::{ReplyToMessage, ServerMessage};
 
@@ -7,30 +7,26 @@ use crate::{
     twitch::{
         agent::TwitchAgent,
         bot::MuniBotTwitchIRCClient,
+        commands::TwitchCommand,
         handler::{TwitchHandlerError, TwitchMessageHandler},
     },
 };
 
-pub struct LiftHandler {
-    last_call: Instant,
-}
+const LIFT: TwitchCommand =
+    TwitchCommand::new("liftmuni").global_cooldown(Duration::from_secs(300));
 
-impl LiftHandler {
-    pub fn new() -> Self {
-        Self {
-            last_call: Instant::now() - Duration::from_secs(300),
-        }
+pub struct LiftHandler;
+
+#[async_trait::async_trait]
+impl TwitchMessageHandler for LiftHandler {
+    fn twitch_handler_name(&self) -> &'static str {
+        "lift"
     }
-}
 
-impl Default for LiftHandler {
-    fn default() -> Self {
-        Self::new()
+    fn twitch_commands(&self) -> &'static [TwitchCommand] {
+        &[LIFT]
     }
-}
 
-#[async_trait::async_trait]
-impl TwitchMessageHandler for LiftHandler {
     async fn handle_twitch_message(
         &mut self,
         message: &ServerMessage,
@@ -39,11 +35,9 @@ impl TwitchMessageHandler for LiftHandler {
         _config: &Config,
     ) -> Result<bool, TwitchHandlerError> {
         if let ServerMessage::Privmsg(msg) = message
-            && msg.message_text.starts_with("!liftmuni")
-            && self.last_call.elapsed().as_secs() > 300
+            && LIFT.matches(&msg.message_text)
         {
             self.send_twitch_message(client, msg.channel_login(), "nuh uh. not here. muni is streaming right now. you can't do that while he's streaming.").await?;
-            self.last_call = Instant::now();
 
             Ok(true)
         } else {
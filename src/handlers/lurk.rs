@@ -6,14 +6,25 @@ use crate::{
     twitch::{
         agent::TwitchAgent,
         bot::MuniBotTwitchIRCClient,
+        commands::TwitchCommand,
         handler::{TwitchHandlerError, TwitchMessageHandler},
     },
 };
 
+const COMMANDS: [TwitchCommand; 2] = [TwitchCommand::new("lurk"), TwitchCommand::new("unlurk")];
+
 pub struct LurkHandler;
 
 #[async_trait]
 impl TwitchMessageHandler for LurkHandler {
+    fn twitch_handler_name(&self) -> &'static str {
+        "lurk"
+    }
+
+    fn twitch_commands(&self) -> &'static [TwitchCommand] {
+        &COMMANDS
+    }
+
     async fn handle_twitch_message(
         &mut self,
         message: &ServerMessage,
@@ -16,11 +16,14 @@ use crate::{
     twitch::{
         agent::TwitchAgent,
         bot::MuniBotTwitchIRCClient,
+        commands::TwitchCommand,
         handler::{TwitchHandlerError, TwitchMessageHandler},
     },
     MuniBotError,
 };
 
+const MAGICAL: TwitchCommand = TwitchCommand::new("magical");
+
 pub struct MagicalHandler;
 
 impl MagicalHandler {
@@ -58,6 +61,14 @@ impl MagicalHandler {
 
 #[async_trait]
 impl TwitchMessageHandler for MagicalHandler {
+    fn twitch_handler_name(&self) -> &'static str {
+        "magical"
+    }
+
+    fn twitch_commands(&self) -> &'static [TwitchCommand] {
+        &[MAGICAL]
+    }
+
     async fn handle_twitch_message(
         &mut self,
         message: &ServerMessage,
@@ -1,75 +1,250 @@
-use std::env;
-
 use async_trait::async_trait;
 use chrono::{DateTime, Local};
-use dotenvy::dotenv;
+use poise::serenity_prelude::{CreateEmbed, CreateEmbedFooter, MessageBuilder};
 use serde::{Deserialize, Serialize};
-use surrealdb::{
-    engine::remote::ws::{self, Ws},
-    opt::auth::Database,
-    Surreal,
-};
-use twitch_irc::message::ServerMessage;
+use surrealdb::{Connection, Surreal};
+use twitch_irc::message::{PrivmsgMessage, ServerMessage};
 
 use crate::{
-    config::{Config, DbConfig},
+    config::Config,
+    db::MuniBotDb,
+    discord::{
+        commands::DiscordCommandProvider, pagination::paginate_embeds, DiscordCommand,
+        DiscordContext,
+    },
     twitch::{
         agent::TwitchAgent,
         bot::MuniBotTwitchIRCClient,
+        commands::TwitchCommand,
         handler::{TwitchHandlerError, TwitchMessageHandler},
+        utils::is_moderator,
     },
     MuniBotError,
 };
 
 const QUOTE_TABLE: &str = "quote";
 
+/// The record counting how many quote numbers have been handed out.
+const QUOTE_COUNTER: &str = "counter:quote";
+
+/// How many quotes are shown on each page of `/quote list`.
+const QUOTES_PER_PAGE: usize = 10;
+
+/// The most characters Discord allows in an embed's description.
+const EMBED_DESCRIPTION_LIMIT: usize = 4096;
+
 /// A quote.
 #[derive(Deserialize, Serialize)]
 pub struct Quote {
+    /// The number of this quote. Numbers are assigned when quotes are added and
+    /// never change, even if earlier quotes are deleted.
+    pub number: u32,
     pub created_at: DateTime<Local>,
     pub quote: String,
     pub invoker: String,
+
+    /// The display name of the invoker at the time the quote was added. Older
+    /// quotes don't have this.
+    #[serde(default)]
+    pub invoker_name: Option<String>,
+
     pub stream_category: String,
     pub stream_title: String,
 }
 
-/// A handler for the `!quote` command.
-pub struct QuotesHandler {
-    db: Surreal<ws::Client>,
-}
+impl Quote {
+    /// Adds a new quote to the database, returning the new quote's number.
+    pub async fn add<C: Connection>(
+        db: &Surreal<C>,
+        created_at: DateTime<Local>,
+        quote: String,
+        invoker: String,
+        invoker_name: String,
+        stream_category: String,
+        stream_title: String,
+    ) -> Result<u32, surrealdb::Error> {
+        // numbers come from a counter bumped in the same transaction, so
+        // they're never reused and concurrent quotes can't get the same one
+        let number: Option<u32> = db
+            .query(format!(
+                "BEGIN TRANSACTION;
+                 LET $number = (UPSERT ONLY {QUOTE_COUNTER} SET value += 1 RETURN VALUE value);
+                 CREATE {QUOTE_TABLE} SET
+                     number = $number,
+                     created_at = $quote.created_at,
+                     quote = $quote.quote,
+                     invoker = $quote.invoker,
+                     invoker_name = $quote.invoker_name,
+                     stream_category = $quote.stream_category,
+                     stream_title = $quote.stream_title;
+                 RETURN $number;
+                 COMMIT TRANSACTION;"
+            ))
+            .bind((
+                "quote",
+                Quote {
+                    number: 0,
+                    created_at,
+                    quote,
+                    invoker,
+                    invoker_name: Some(invoker_name),
+                    stream_category,
+                    stream_title,
+                },
+            ))
+            .await?
+            .check()?
+            .take(0)?;
 
-impl QuotesHandler {
-    /// Create a new QuotesHandler, connecting to the database.
-    pub async fn new(db_config: &DbConfig) -> Result<Self, MuniBotError> {
-        dotenv().ok(); // TODO: map to MuniBotError::DotenvError
-
-        let db = Surreal::new::<Ws>(&db_config.url).await?;
-        db.signin(Database {
-            namespace: "muni_bot",
-            database: "muni_bot",
-            username: &db_config.user,
-            password: &env::var("DATABASE_PASS").expect("expected DATABASE_PASS to be set"),
-        })
-        .await?;
+        Ok(number.unwrap_or_default())
+    }
+
+    /// Gets the quote with the given number.
+    pub async fn get<C: Connection>(
+        db: &Surreal<C>,
+        number: u32,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        db.query(format!(
+            "SELECT * FROM {QUOTE_TABLE} WHERE number = $number;"
+        ))
+        .bind(("number", number))
+        .await?
+        .take(0)
+    }
 
-        Ok(Self { db })
+    /// Gets every quote, ordered by number.
+    pub async fn get_all<C: Connection>(db: &Surreal<C>) -> Result<Vec<Self>, surrealdb::Error> {
+        db.query(format!("SELECT * FROM {QUOTE_TABLE} ORDER BY number;"))
+            .await?
+            .take(0)
+    }
+
+    /// Gets a random quote.
+    pub async fn get_random<C: Connection>(
+        db: &Surreal<C>,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        db.query(format!(
+            "SELECT * FROM {QUOTE_TABLE}
+             ORDER BY rand()
+             LIMIT 1;",
+        ))
+        .await?
+        .take(0)
     }
 
-    /// Add a new quote to the database, returning the new count of quotes
-    pub async fn add_new_quote(&mut self, new_quote: Quote) -> Result<u32, TwitchHandlerError> {
-        self.db
-            .create::<Option<Quote>>(QUOTE_TABLE)
-            .content(new_quote)
+    /// Searches quotes by their text, falling back to the stream category and
+    /// title they were recorded under. The best matches come first.
+    pub async fn search<C: Connection>(
+        db: &Surreal<C>,
+        query: &str,
+        limit: u32,
+    ) -> Result<Vec<Self>, surrealdb::Error> {
+        let mut response = db
+            .query(format!(
+                "SELECT *, search::score(0) AS score FROM {QUOTE_TABLE}
+                 WHERE quote @0@ $query
+                 ORDER BY score DESC
+                 LIMIT $limit;
+                 SELECT * FROM {QUOTE_TABLE}
+                 WHERE stream_category @0@ $query OR stream_title @1@ $query
+                 ORDER BY number
+                 LIMIT $limit;"
+            ))
+            .bind(("query", query.to_string()))
+            .bind(("limit", limit))
             .await?;
 
-        let count = self
-            .db
-            .query(format!("SELECT count() FROM {QUOTE_TABLE} GROUP ALL;"))
-            .await?
-            .take::<Option<u32>>((0, "count"))?
-            .unwrap_or(0);
+        let text_matches: Vec<Self> = response.take(0)?;
+        if text_matches.is_empty() {
+            response.take(1)
+        } else {
+            Ok(text_matches)
+        }
+    }
+
+    /// Replaces the text of the quote with the given number. Returns the edited
+    /// quote, if it exists.
+    pub async fn edit<C: Connection>(
+        db: &Surreal<C>,
+        number: u32,
+        new_text: String,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        db.query(format!(
+            "UPDATE {QUOTE_TABLE} SET quote = $text WHERE number = $number RETURN AFTER;"
+        ))
+        .bind(("text", new_text))
+        .bind(("number", number))
+        .await?
+        .take(0)
+    }
 
-        Ok(count)
+    /// Deletes the quote with the given number. Returns the deleted quote, if it
+    /// existed.
+    pub async fn delete<C: Connection>(
+        db: &Surreal<C>,
+        number: u32,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        db.query(format!(
+            "DELETE {QUOTE_TABLE} WHERE number = $number RETURN BEFORE;"
+        ))
+        .bind(("number", number))
+        .await?
+        .take(0)
+    }
+
+    /// Builds a Discord embed showing this quote and where it came from.
+    pub fn to_embed(&self) -> CreateEmbed {
+        let invoker = self
+            .invoker_name
+            .clone()
+            .unwrap_or_else(|| format!("twitch user {}", self.invoker));
+
+        CreateEmbed::new()
+            .title(format!("quote #{}", self.number))
+            .description(format!("\"{}\"", self.quote))
+            .field(
+                "category",
+                non_empty_or(&self.stream_category, "*none*"),
+                true,
+            )
+            .field(
+                "recorded",
+                format!("<t:{}:D>", self.created_at.timestamp()),
+                true,
+            )
+            .field(
+                "stream title",
+                non_empty_or(&self.stream_title, "*none*"),
+                false,
+            )
+            .footer(CreateEmbedFooter::new(format!("added by {invoker}")))
+            .timestamp(self.created_at)
+    }
+}
+
+fn non_empty_or(s: &str, fallback: &str) -> String {
+    if s.trim().is_empty() {
+        fallback.to_string()
+    } else {
+        s.to_string()
+    }
+}
+
+const COMMANDS: [TwitchCommand; 4] = [
+    TwitchCommand::new("quote"),
+    TwitchCommand::new("addquote"),
+    TwitchCommand::new("editquote"),
+    TwitchCommand::new("delquote"),
+];
+
+/// A handler for the `!quote` command.
+pub struct QuotesHandler {
+    db: MuniBotDb,
+}
+
+impl QuotesHandler {
+    pub fn new(db: MuniBotDb) -> Self {
+        Self { db }
     }
 
     /// Recall a quote from the database and send it in chat.
@@ -77,22 +252,11 @@ impl QuotesHandler {
         &mut self,
         client: &MuniBotTwitchIRCClient,
         recipient_channel: &str,
-        n_requested: Option<i32>,
+        n_requested: Option<u32>,
     ) -> Result<(), TwitchHandlerError> {
         if let Some(n) = n_requested {
             // recall specific quote
-            let mut response = self
-                .db
-                .query(format!(
-                    "SELECT * FROM {QUOTE_TABLE}
-                     ORDER BY created_at
-                     LIMIT 1
-                     START $n;",
-                ))
-                .bind(("n", n - 1))
-                .await?;
-
-            if let Some(quote) = response.take::<Option<Quote>>(0)? {
+            if let Some(quote) = Quote::get(&self.db, n).await? {
                 self.send_twitch_message(
                     client,
                     recipient_channel,
@@ -109,20 +273,11 @@ impl QuotesHandler {
             }
         } else {
             // recall random quote
-            let mut response = self
-                .db
-                .query(format!(
-                    "SELECT * FROM {QUOTE_TABLE}
-                     ORDER BY rand()
-                     LIMIT 1;",
-                ))
-                .await?;
-
-            if let Some(quote) = response.take::<Option<Quote>>(0)? {
+            if let Some(quote) = Quote::get_random(&self.db).await? {
                 self.send_twitch_message(
                     client,
                     recipient_channel,
-                    &format!(r#"random quote: "{}""#, quote.quote),
+                    &format!(r#"random quote #{}: "{}""#, quote.number, quote.quote),
                 )
                 .await
             } else {
@@ -131,10 +286,110 @@ impl QuotesHandler {
             }
         }
     }
+
+    /// Find the quote best matching the search query and send it in chat.
+    pub async fn search_quote(
+        &mut self,
+        client: &MuniBotTwitchIRCClient,
+        recipient_channel: &str,
+        query: &str,
+    ) -> Result<(), TwitchHandlerError> {
+        if let Some(quote) = Quote::search(&self.db, query, 1).await?.into_iter().next() {
+            self.send_twitch_message(
+                client,
+                recipient_channel,
+                &format!(r#"here's quote #{}: "{}""#, quote.number, quote.quote),
+            )
+            .await
+        } else {
+            self.send_twitch_message(
+                client,
+                recipient_channel,
+                &format!("i couldn't find any quotes matching \"{query}\" :("),
+            )
+            .await
+        }
+    }
+
+    /// Handles `!editquote <n> <text>` and `!delquote <n>`. Only moderators can
+    /// use these.
+    async fn handle_moderator_command(
+        &mut self,
+        m: &PrivmsgMessage,
+        client: &MuniBotTwitchIRCClient,
+    ) -> Result<bool, TwitchHandlerError> {
+        let (command, content) = split_command(&m.message_text);
+
+        if command == "!editquote" {
+            if is_moderator(m) {
+                let (number, new_text) = content
+                    .split_once(char::is_whitespace)
+                    .map(|(n, text)| (parse_quote_number(n), text.trim()))
+                    .unwrap_or((None, ""));
+
+                let reply = match number {
+                    Some(n) if !new_text.is_empty() => {
+                        if Quote::edit(&self.db, n, new_text.to_string())
+                            .await?
+                            .is_some()
+                        {
+                            format!("quote #{n} has been rewritten in the muni history books!")
+                        } else {
+                            format!("quote #{n} not found :(")
+                        }
+                    }
+                    _ => "usage: !editquote <number> <new quote text>".to_string(),
+                };
+                self.send_twitch_message(client, &m.channel_login, &reply)
+                    .await?;
+            }
+
+            Ok(true)
+        } else if command == "!delquote" {
+            if is_moderator(m) {
+                let reply = match parse_quote_number(content) {
+                    Some(n) => {
+                        if Quote::delete(&self.db, n).await?.is_some() {
+                            format!("quote #{n} has been erased from the muni history books.")
+                        } else {
+                            format!("quote #{n} not found :(")
+                        }
+                    }
+                    None => "usage: !delquote <number>".to_string(),
+                };
+                self.send_twitch_message(client, &m.channel_login, &reply)
+                    .await?;
+            }
+
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+/// Splits a chat message into its first word and the rest, like
+/// `("!quote", "12")`, so commands only match as whole words.
+fn split_command(text: &str) -> (&str, &str) {
+    let (command, content) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    (command, content.trim())
+}
+
+/// Parses a quote number like `12` or `#12`.
+fn parse_quote_number(s: &str) -> Option<u32> {
+    s.trim().trim_start_matches('#').parse().ok()
 }
 
 #[async_trait]
 impl TwitchMessageHandler for QuotesHandler {
+    fn twitch_handler_name(&self) -> &'static str {
+        "quotes"
+    }
+
+    fn twitch_commands(&self) -> &'static [TwitchCommand] {
+        &COMMANDS
+    }
+
     async fn handle_twitch_message(
         &mut self,
         message: &ServerMessage,
@@ -143,7 +398,9 @@ impl TwitchMessageHandler for QuotesHandler {
         _config: &Config,
     ) -> Result<bool, TwitchHandlerError> {
         let handled = if let ServerMessage::Privmsg(m) = message {
-            if let Some(content) = m.message_text.strip_prefix("!addquote").map(str::trim) {
+            let (command, content) = split_command(&m.message_text);
+
+            if command == "!addquote" {
                 if content.is_empty() {
                     self.send_twitch_message(
                         client,
@@ -152,34 +409,37 @@ impl TwitchMessageHandler for QuotesHandler {
                     )
                     .await?;
                 } else if let Some(channel_info) = agent.get_channel_info(&m.channel_id).await? {
-                    let new_quote = Quote {
-                        created_at: Local::now(),
-                        quote: content.to_string(),
-                        invoker: m.sender.id.to_string(),
-                        stream_category: channel_info.game_name.take(),
-                        stream_title: channel_info.title,
-                    };
-
-                    let quote_count = self.add_new_quote(new_quote).await?;
+                    let quote_number = Quote::add(
+                        &self.db,
+                        Local::now(),
+                        content.to_string(),
+                        m.sender.id.to_string(),
+                        m.sender.name.to_string(),
+                        channel_info.game_name.take(),
+                        channel_info.title,
+                    )
+                    .await?;
                     self.send_twitch_message(
                         client,
                         &m.channel_login,
                         &format!(
-                        "quote #{quote_count} is in! recorded in the muni history books forever"
+                        "quote #{quote_number} is in! recorded in the muni history books forever"
                     ),
                     )
                     .await?;
                 }
 
                 true
-            } else if let Some(content) = m.message_text.strip_prefix("!quote").map(str::trim) {
+            } else if self.handle_moderator_command(m, client).await? {
+                true
+            } else if command == "!quote" {
                 if content.is_empty() {
                     // recall a random quote
                     self.recall_quote(client, &m.channel_login, None).await?;
-                } else if let Ok(n) = content.parse::<i32>() {
+                } else if let Some(n) = parse_quote_number(content) {
                     self.recall_quote(client, &m.channel_login, Some(n)).await?;
                 } else if content.len() >= 3 {
-                    // TODO: recall a quote that matches the content
+                    self.search_quote(client, &m.channel_login, content).await?;
                 }
 
                 true
@@ -193,3 +453,247 @@ impl TwitchMessageHandler for QuotesHandler {
         Ok(handled)
     }
 }
+
+/// Provides `/quote` commands for browsing quotes on Discord.
+pub struct QuotesProvider;
+
+impl DiscordCommandProvider for QuotesProvider {
+    fn commands(&self) -> Vec<DiscordCommand> {
+        vec![quote()]
+    }
+}
+
+/// recall quotes from muni's streams.
+#[poise::command(
+    slash_command,
+    subcommand_required,
+    subcommands("random", "get", "search", "list")
+)]
+async fn quote(_ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    Ok(())
+}
+
+/// show a random quote.
+#[poise::command(slash_command)]
+async fn random(ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    if let Some(quote) = Quote::get_random(ctx.data().access().db()).await? {
+        ctx.send(poise::CreateReply::default().embed(quote.to_embed()))
+            .await?;
+    } else {
+        ctx.say("there aren't any quotes yet :(").await?;
+    }
+
+    Ok(())
+}
+
+/// show a specific quote.
+#[poise::command(slash_command)]
+async fn get(
+    ctx: DiscordContext<'_>,
+    #[description = "the number of the quote"] number: u32,
+) -> Result<(), MuniBotError> {
+    if let Some(quote) = Quote::get(ctx.data().access().db(), number).await? {
+        ctx.send(poise::CreateReply::default().embed(quote.to_embed()))
+            .await?;
+    } else {
+        ctx.say(format!("quote #{number} not found :(")).await?;
+    }
+
+    Ok(())
+}
+
+/// search for quotes by what was said, or by the stream they were said on.
+#[poise::command(slash_command)]
+async fn search(
+    ctx: DiscordContext<'_>,
+    #[description = "what to search for"] query: String,
+) -> Result<(), MuniBotError> {
+    let results = Quote::search(ctx.data().access().db(), &query, 50).await?;
+
+    if results.is_empty() {
+        ctx.say(
+            MessageBuilder::new()
+                .push("i couldn't find any quotes matching ")
+                .push_bold_safe(&query)
+                .push(" :(")
+                .build(),
+        )
+        .await?;
+    } else if let [quote] = results.as_slice() {
+        ctx.send(poise::CreateReply::default().embed(quote.to_embed()))
+            .await?;
+    } else {
+        let pages = results.iter().map(Quote::to_embed).collect();
+        paginate_embeds(ctx, pages).await?;
+    }
+
+    Ok(())
+}
+
+/// Lays quotes out into pages for `/quote list`. Pages hold up to
+/// [`QUOTES_PER_PAGE`] quotes, but fewer if long quotes wouldn't fit in an
+/// embed.
+fn list_pages(quotes: &[Quote]) -> Vec<String> {
+    let mut pages = Vec::new();
+    let mut page = String::new();
+    let mut quotes_on_page = 0;
+    for quote in quotes {
+        let line = MessageBuilder::new()
+            .push_bold(format!("#{}", quote.number))
+            .push(format!(" <t:{}:d> ", quote.created_at.timestamp()))
+            .push_line_safe(format!("\"{}\"", quote.quote))
+            .build();
+
+        if quotes_on_page > 0
+            && (quotes_on_page == QUOTES_PER_PAGE
+                || page.chars().count() + line.chars().count() > EMBED_DESCRIPTION_LIMIT)
+        {
+            pages.push(std::mem::take(&mut page));
+            quotes_on_page = 0;
+        }
+        page.push_str(&line);
+        quotes_on_page += 1;
+    }
+    if !page.is_empty() {
+        pages.push(page);
+    }
+    pages
+}
+
+/// browse every quote.
+#[poise::command(slash_command)]
+async fn list(ctx: DiscordContext<'_>) -> Result<(), MuniBotError> {
+    let quotes = Quote::get_all(ctx.data().access().db()).await?;
+
+    if quotes.is_empty() {
+        ctx.say("there aren't any quotes yet :(").await?;
+        return Ok(());
+    }
+
+    let pages = list_pages(&quotes)
+        .into_iter()
+        .map(|page| {
+            CreateEmbed::new()
+                .title("the muni history books")
+                .description(page)
+        })
+        .collect();
+
+    paginate_embeds(ctx, pages).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Local;
+
+    use super::{list_pages, split_command, Quote, EMBED_DESCRIPTION_LIMIT, QUOTES_PER_PAGE};
+    use crate::db::test_db;
+
+    async fn add_quote(db: &crate::db::MuniBotDb, text: &str, category: &str) -> u32 {
+        Quote::add(
+            db,
+            Local::now(),
+            text.to_string(),
+            "1234".to_string(),
+            "muni".to_string(),
+            category.to_string(),
+            "a stream".to_string(),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_quote_numbers_are_stable() {
+        let db = test_db().await;
+
+        assert_eq!(add_quote(&db, "first", "Minecraft").await, 1);
+        assert_eq!(add_quote(&db, "second", "Minecraft").await, 2);
+        assert_eq!(add_quote(&db, "third", "Minecraft").await, 3);
+
+        // deleting a quote doesn't renumber the others
+        assert!(Quote::delete(&db, 2).await.unwrap().is_some());
+        assert!(Quote::get(&db, 2).await.unwrap().is_none());
+        assert_eq!(Quote::get(&db, 3).await.unwrap().unwrap().quote, "third");
+        assert_eq!(add_quote(&db, "fourth", "Minecraft").await, 4);
+
+        // not even when the newest one is deleted
+        assert!(Quote::delete(&db, 4).await.unwrap().is_some());
+        assert_eq!(add_quote(&db, "fifth", "Minecraft").await, 5);
+
+        let edited = Quote::edit(&db, 1, "first, but better".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(edited.quote, "first, but better");
+    }
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(split_command("!quote"), ("!quote", ""));
+        assert_eq!(split_command("!quote  #12 "), ("!quote", "#12"));
+        assert_eq!(
+            split_command("!addquote hi there"),
+            ("!addquote", "hi there")
+        );
+        assert_eq!(split_command("!quotebook hi"), ("!quotebook", "hi"));
+        assert_eq!(split_command("!addquoteX"), ("!addquoteX", ""));
+    }
+
+    #[tokio::test]
+    async fn test_list_pages() {
+        let db = test_db().await;
+
+        for i in 0..12 {
+            add_quote(&db, &format!("quote {i}"), "Minecraft").await;
+        }
+        let pages = list_pages(&Quote::get_all(&db).await.unwrap());
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].lines().count(), QUOTES_PER_PAGE);
+        assert_eq!(pages[1].lines().count(), 2);
+
+        // long quotes (twitch allows about 500 characters) get fewer to a page
+        for _ in 0..10 {
+            add_quote(&db, &"a".repeat(490), "Minecraft").await;
+        }
+        let pages = list_pages(&Quote::get_all(&db).await.unwrap());
+        assert_eq!(pages.len(), 3);
+        assert!(pages[1].lines().count() < QUOTES_PER_PAGE);
+        assert!(pages
+            .iter()
+            .all(|page| page.chars().count() <= EMBED_DESCRIPTION_LIMIT));
+        assert_eq!(
+            pages.iter().map(|page| page.lines().count()).sum::<usize>(),
+            22
+        );
+    }
+
+    #[tokio::test]
+    async fn test_quote_search() {
+        let db = test_db().await;
+
+        add_quote(&db, "i love running through the fields", "Minecraft").await;
+        add_quote(&db, "never gonna give you up", "Just Chatting").await;
+
+        let results = Quote::search(&db, "run", 10).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].number, 1);
+
+        // falls back to the stream category
+        let results = Quote::search(&db, "chatting", 10).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].number, 2);
+
+        assert!(Quote::search(&db, "zebra", 10).await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_quote_number() {
+        assert_eq!(super::parse_quote_number("12"), Some(12));
+        assert_eq!(super::parse_quote_number(" #7 "), Some(7));
+        assert_eq!(super::parse_quote_number("seven"), None);
+        assert_eq!(super::parse_quote_number("-1"), None);
+    }
+}
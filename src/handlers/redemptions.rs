@@ -0,0 +1,205 @@
+use async_trait::async_trait;
+use log::{error, warn};
+use tokio::sync::mpsc::UnboundedSender;
+use twitch_api::helix::points::CustomRewardRedemptionStatus;
+
+use super::{content_warning::ContentWarningHandler, TwitchEventHandlerCollection};
+use crate::{
+    config::{Config, RedemptionAction},
+    discord::relay::DiscordRelayMessage,
+    twitch::{
+        agent::TwitchAgent,
+        bot::MuniBotTwitchIRCClient,
+        eventsub::TwitchEvent,
+        handler::{TwitchEventHandler, TwitchHandlerError},
+    },
+};
+
+/// Runs the actions configured for channel points rewards when they're
+/// redeemed, then marks the redemptions fulfilled or refunds them.
+pub struct RedemptionHandler {
+    content_warning: ContentWarningHandler,
+
+    /// Handlers that redemptions can run by name.
+    built_in_handlers: TwitchEventHandlerCollection,
+
+    discord_messages: UnboundedSender<DiscordRelayMessage>,
+}
+
+impl RedemptionHandler {
+    pub fn new(
+        content_warning: ContentWarningHandler,
+        built_in_handlers: TwitchEventHandlerCollection,
+        discord_messages: UnboundedSender<DiscordRelayMessage>,
+    ) -> Self {
+        Self {
+            content_warning,
+            built_in_handlers,
+            discord_messages,
+        }
+    }
+
+    /// Runs a single action for a redemption. Returns `true` if the action
+    /// did what it was supposed to.
+    async fn run_action(
+        &mut self,
+        action: &RedemptionAction,
+        event: &TwitchEvent,
+        client: &MuniBotTwitchIRCClient,
+        agent: &TwitchAgent<'_>,
+        config: &Config,
+    ) -> Result<bool, TwitchHandlerError> {
+        let TwitchEvent::Redemption {
+            channel_login,
+            reward_title,
+            user_name,
+            user_input,
+            ..
+        } = event
+        else {
+            return Ok(false);
+        };
+        let fill = |template: &str| fill_template(template, user_name, user_input, reward_title);
+
+        match action {
+            RedemptionAction::Chat { message } => {
+                client
+                    .say(channel_login.clone(), fill(message))
+                    .await
+                    .map_err(TwitchHandlerError::SendMessage)?;
+                Ok(true)
+            }
+            RedemptionAction::Discord { channel, message } => Ok(self
+                .discord_messages
+                .send(DiscordRelayMessage {
+                    channel_id: *channel,
+                    content: fill(message),
+                })
+                .is_ok()),
+            RedemptionAction::ContentWarning { warning } => {
+                let warning = warning
+                    .as_deref()
+                    .map(fill)
+                    .unwrap_or_else(|| user_input.clone());
+                self.content_warning
+                    .toggle_warning(client, config, channel_login, &warning)
+                    .await
+            }
+            RedemptionAction::Handler { name } => {
+                let handler = self
+                    .built_in_handlers
+                    .iter_mut()
+                    .find(|handler| handler.twitch_event_handler_name() == name)
+                    .ok_or_else(|| {
+                        TwitchHandlerError::Other(format!(
+                            "there's no built-in handler named {name}"
+                        ))
+                    })?;
+                handler
+                    .handle_twitch_event(event, client, agent, config)
+                    .await
+            }
+        }
+    }
+}
+
+/// Fills in the `{user}`, `{input}` and `{reward}` placeholders of a
+/// redemption message.
+fn fill_template(template: &str, user_name: &str, user_input: &str, reward_title: &str) -> String {
+    template
+        .replace("{user}", user_name)
+        .replace("{input}", user_input)
+        .replace("{reward}", reward_title)
+}
+
+#[async_trait]
+impl TwitchEventHandler for RedemptionHandler {
+    fn twitch_event_handler_name(&self) -> &'static str {
+        "redemptions"
+    }
+
+    async fn handle_twitch_event(
+        &mut self,
+        event: &TwitchEvent,
+        client: &MuniBotTwitchIRCClient,
+        agent: &TwitchAgent,
+        config: &Config,
+    ) -> Result<bool, TwitchHandlerError> {
+        let TwitchEvent::Redemption {
+            channel_login,
+            redemption_id,
+            reward_id,
+            reward_title,
+            ..
+        } = event
+        else {
+            return Ok(false);
+        };
+        let Some(redemption) = config
+            .twitch
+            .redemption_for(channel_login, reward_id, reward_title)
+        else {
+            return Ok(false);
+        };
+
+        // stop at the first action that fails, since later ones might depend
+        // on it
+        let mut succeeded = true;
+        for action in &redemption.actions {
+            match self.run_action(action, event, client, agent, config).await {
+                Ok(true) => continue,
+                Ok(false) => {
+                    warn!("redemption action {action:?} for {reward_title} didn't go through")
+                }
+                Err(e) => error!("redemption action {action:?} for {reward_title} failed: {e}"),
+            }
+            succeeded = false;
+            break;
+        }
+
+        let status = if succeeded && redemption.fulfill {
+            Some(CustomRewardRedemptionStatus::Fulfilled)
+        } else if !succeeded && redemption.refund_on_failure {
+            Some(CustomRewardRedemptionStatus::Canceled)
+        } else {
+            None
+        };
+        if let Some(status) = status {
+            let broadcaster = agent
+                .get_user_from_login(channel_login)
+                .await?
+                .ok_or_else(|| {
+                    TwitchHandlerError::Other(format!(
+                        "twitch channel {channel_login} doesn't exist"
+                    ))
+                })?;
+            agent
+                .update_redemption_status(&broadcaster.id, reward_id, redemption_id, status)
+                .await?;
+        }
+
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fill_template;
+
+    #[test]
+    fn test_fill_template() {
+        assert_eq!(
+            fill_template(
+                "{user} redeemed {reward}: {input}",
+                "muni_corn",
+                "change the subject pls",
+                "subject change /srs"
+            ),
+            "muni_corn redeemed subject change /srs: change the subject pls"
+        );
+        assert_eq!(
+            fill_template("no placeholders", "a", "b", "c"),
+            "no placeholders"
+        );
+    }
+}
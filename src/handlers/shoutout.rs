@@ -1,74 +1,325 @@
+use std::{sync::Arc, time::Duration};
+
 use async_trait::async_trait;
-use twitch_irc::message::ServerMessage;
+use log::warn;
+use twitch_api::{helix::channels::ChannelInformation, types::UserId};
+use twitch_irc::message::{ServerMessage, UserNoticeEvent};
 
 use crate::{
     config::Config,
     twitch::{
         agent::TwitchAgent,
         bot::MuniBotTwitchIRCClient,
-        handler::{TwitchHandlerError, TwitchMessageHandler},
+        cache::TtlCache,
+        commands::TwitchCommand,
+        eventsub::TwitchEvent,
+        handler::{TwitchEventHandler, TwitchHandlerError, TwitchMessageHandler},
+        utils::ChatRole,
     },
 };
 
-pub struct ShoutoutHandler;
+/// How long before the same raider can be shouted out automatically again in
+/// the same channel. This also keeps a raid from being shouted out twice when
+/// it shows up in both chat and EventSub.
+const RAID_SHOUTOUT_COOLDOWN: Duration = Duration::from_mins(30);
+
+/// Shoutouts are for moderators, so viewers can't spam them. Twitch's own
+/// shoutouts have a two minute cooldown per channel anyway.
+const COMMANDS: [TwitchCommand; 2] = [
+    TwitchCommand::new("so")
+        .aliases(&["shoutout"])
+        .role(ChatRole::Moderator)
+        .global_cooldown(Duration::from_secs(10)),
+    TwitchCommand::new("mso")
+        .role(ChatRole::Moderator)
+        .global_cooldown(Duration::from_secs(30)),
+];
+
+/// Shouts out other streamers, either when asked with `!so` or automatically
+/// when they raid. The same handler is registered for both chat messages and
+/// EventSub events, so clones share their raid cooldowns.
+#[derive(Clone)]
+pub struct ShoutoutHandler {
+    /// Raiders recently shouted out, by channel login and raider ID.
+    recent_raids: Arc<TtlCache<(String, UserId), ()>>,
+}
+
+impl ShoutoutHandler {
+    pub fn new() -> Self {
+        Self {
+            recent_raids: Arc::new(TtlCache::new(RAID_SHOUTOUT_COOLDOWN)),
+        }
+    }
+
+    /// Looks up the target on Twitch and builds a shoutout message for them.
+    /// Returns `None` if no one with that login exists.
+    async fn shoutout_message(
+        agent: &TwitchAgent<'_>,
+        target_login: &str,
+    ) -> Result<Option<(UserId, String)>, TwitchHandlerError> {
+        let Some(user) = agent.get_user_from_login(target_login).await? else {
+            return Ok(None);
+        };
+        let channel = agent.get_channel_info(user.id.as_str()).await?;
+
+        let message = format_shoutout(user.display_name.as_str(), user.login.as_str(), channel);
+        Ok(Some((user.id, message)))
+    }
+
+    /// Sends a native Twitch shoutout too. Twitch only allows these every so
+    /// often (and only if munibot is a moderator), so failures are just
+    /// logged.
+    async fn send_native_shoutout(agent: &TwitchAgent<'_>, from: &UserId, to: &UserId) {
+        if let Err(e) = agent.send_shoutout(from, to).await {
+            warn!("couldn't send a native shoutout to {to} from {from}: {e}");
+        }
+    }
+
+    /// Shouts out a streamer who raided the channel, unless they were already
+    /// shouted out for a raid recently. Returns `true` if a shoutout was sent.
+    async fn shoutout_raider(
+        &self,
+        client: &MuniBotTwitchIRCClient,
+        agent: &TwitchAgent<'_>,
+        channel_login: &str,
+        channel_id: &UserId,
+        raider_id: &UserId,
+        raider_login: &str,
+    ) -> Result<bool, TwitchHandlerError> {
+        let key = (channel_login.to_string(), raider_id.clone());
+        if self.recent_raids.get(&key).is_some() {
+            return Ok(false);
+        }
+
+        let Some((raider_id, shoutout)) = Self::shoutout_message(agent, raider_login).await? else {
+            return Ok(false);
+        };
+        client
+            .say(
+                channel_login.to_string(),
+                format!("thank you so much for the raid!! <3 {shoutout}"),
+            )
+            .await
+            .map_err(TwitchHandlerError::SendMessage)?;
+
+        // only start the cooldown once the shoutout is out, so the raid can
+        // still be shouted out when it shows up from the other source
+        self.recent_raids.insert(key, ());
+        Self::send_native_shoutout(agent, channel_id, &raider_id).await;
+
+        Ok(true)
+    }
+}
+
+impl Default for ShoutoutHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Builds the shoutout message for a streamer, mentioning what they last
+/// streamed if we know.
+fn format_shoutout(display_name: &str, login: &str, channel: Option<ChannelInformation>) -> String {
+    let last_stream = channel
+        .map(|c| (c.game_name.to_string(), c.title))
+        .map(|(game, title)| match (game.is_empty(), title.is_empty()) {
+            (false, false) => format!(" they were last seen streaming {game}: \"{title}\"."),
+            (false, true) => format!(" they were last seen streaming {game}."),
+            (true, false) => format!(" their last stream was \"{title}\"."),
+            (true, true) => String::new(),
+        })
+        .unwrap_or_default();
+
+    format!("this is a PSA that you NEED to go check out {display_name} at https://twitch.tv/{login} ! :3{last_stream} clearly they deserve the shoutout, so go follow them now >:c")
+}
 
 #[async_trait]
 impl TwitchMessageHandler for ShoutoutHandler {
+    fn twitch_handler_name(&self) -> &'static str {
+        "shoutout"
+    }
+
+    fn twitch_commands(&self) -> &'static [TwitchCommand] {
+        &COMMANDS
+    }
+
     async fn handle_twitch_message(
         &mut self,
         message: &ServerMessage,
         client: &MuniBotTwitchIRCClient,
-        _agent: &TwitchAgent,
+        agent: &TwitchAgent,
         _config: &Config,
     ) -> Result<bool, TwitchHandlerError> {
-        if let ServerMessage::Privmsg(msg) = message {
-            // accept either !so or !shoutout
-            if let Some(target) = msg
-                .message_text
-                .strip_prefix("!so ")
-                .or_else(|| msg.message_text.strip_prefix("!shoutout "))
-                // strip @ from the front of the username if it's there
-                .map(|s| s.trim_start_matches('@'))
-            {
-                let message = format!("this is a PSA that you NEED to go check out {target} at https://twitch.tv/{target} ! :3 clearly they deserve the shoutout, so go follow them now >:c");
-
-                // send the message
-                self.send_twitch_message(client, &msg.channel_login, &message)
-                    .await?;
-
-                Ok(true)
-            } else if let Some(targets_raw) = msg
-                // multi-shoutouts
-                .message_text
-                .strip_prefix("!mso ")
-            {
-                let mut message = String::from("go check out these cuties! :3");
-
-                for mut target in targets_raw.split_whitespace() {
-                    target = target.trim_start_matches('@');
-                    let link = format!(" https://twitch.tv/{}", target);
-
-                    // if the message after adding this link would exceed twitch's character limit
-                    // of 500, send the message first and reset it
-                    if message.len() + link.len() >= 500 {
-                        self.send_twitch_message(client, &msg.channel_login, &message)
+        match message {
+            ServerMessage::Privmsg(msg) => {
+                // accept either !so or !shoutout
+                if let Some(target) = msg
+                    .message_text
+                    .strip_prefix("!so ")
+                    .or_else(|| msg.message_text.strip_prefix("!shoutout "))
+                    .and_then(|s| s.split_whitespace().next())
+                    // strip @ from the front of the username if it's there
+                    .map(|s| s.trim_start_matches('@'))
+                {
+                    match Self::shoutout_message(agent, target).await? {
+                        Some((target_id, message)) => {
+                            self.send_twitch_message(client, &msg.channel_login, &message)
+                                .await?;
+                            Self::send_native_shoutout(
+                                agent,
+                                &UserId::from(msg.channel_id.clone()),
+                                &target_id,
+                            )
+                            .await;
+                        }
+                        None => {
+                            self.send_twitch_message(
+                                client,
+                                &msg.channel_login,
+                                &format!("i couldn't find anyone named {target} on twitch :<"),
+                            )
                             .await?;
-                        message = link.trim().to_string();
-                    } else {
-                        // otherwise, add the link to the message
-                        message.push_str(&link);
+                        }
+                    }
+
+                    Ok(true)
+                } else if let Some(targets_raw) = msg
+                    // multi-shoutouts
+                    .message_text
+                    .strip_prefix("!mso ")
+                {
+                    let mut message = String::from("go check out these cuties! :3");
+
+                    for mut target in targets_raw.split_whitespace() {
+                        target = target.trim_start_matches('@');
+                        let link = format!(" https://twitch.tv/{}", target);
+
+                        // if the message after adding this link would exceed twitch's character limit
+                        // of 500, send the message first and reset it
+                        if message.len() + link.len() >= 500 {
+                            self.send_twitch_message(client, &msg.channel_login, &message)
+                                .await?;
+                            message = link.trim().to_string();
+                        } else {
+                            // otherwise, add the link to the message
+                            message.push_str(&link);
+                        }
                     }
+
+                    self.send_twitch_message(client, &msg.channel_login, &message)
+                        .await?;
+
+                    Ok(true)
+                } else {
+                    Ok(false)
                 }
+            }
+            ServerMessage::UserNotice(notice) => {
+                if let UserNoticeEvent::Raid { .. } = notice.event {
+                    self.shoutout_raider(
+                        client,
+                        agent,
+                        &notice.channel_login,
+                        &UserId::from(notice.channel_id.clone()),
+                        &UserId::from(notice.sender.id.clone()),
+                        &notice.sender.login,
+                    )
+                    .await
+                } else {
+                    Ok(false)
+                }
+            }
+            _ => Ok(false),
+        }
+    }
+}
 
-                self.send_twitch_message(client, &msg.channel_login, &message)
-                    .await?;
+#[async_trait]
+impl TwitchEventHandler for ShoutoutHandler {
+    fn twitch_event_handler_name(&self) -> &'static str {
+        "shoutout"
+    }
+
+    async fn handle_twitch_event(
+        &mut self,
+        event: &TwitchEvent,
+        client: &MuniBotTwitchIRCClient,
+        agent: &TwitchAgent,
+        _config: &Config,
+    ) -> Result<bool, TwitchHandlerError> {
+        match event {
+            TwitchEvent::Raid {
+                channel_login,
+                from_id,
+                from_login,
+                ..
+            } => {
+                let Some(channel) = agent.get_user_from_login(channel_login).await? else {
+                    return Ok(false);
+                };
+                self.shoutout_raider(
+                    client,
+                    agent,
+                    channel_login,
+                    &channel.id,
+                    from_id,
+                    from_login,
+                )
+                .await
+            }
+            // redemptions only get here when a reward is set up to run this
+            // handler, so the viewer gets to pick who to shout out
+            TwitchEvent::Redemption {
+                channel_login,
+                user_input,
+                ..
+            } => {
+                let Some(target) = user_input
+                    .split_whitespace()
+                    .next()
+                    .map(|s| s.trim_start_matches('@'))
+                else {
+                    return Ok(false);
+                };
+                let Some(channel) = agent.get_user_from_login(channel_login).await? else {
+                    return Ok(false);
+                };
 
-                Ok(true)
-            } else {
-                Ok(false)
+                match Self::shoutout_message(agent, target).await? {
+                    Some((target_id, message)) => {
+                        client
+                            .say(channel_login.clone(), message)
+                            .await
+                            .map_err(TwitchHandlerError::SendMessage)?;
+                        Self::send_native_shoutout(agent, &channel.id, &target_id).await;
+                        Ok(true)
+                    }
+                    None => {
+                        client
+                            .say(
+                                channel_login.clone(),
+                                format!("i couldn't find anyone named {target} on twitch :<"),
+                            )
+                            .await
+                            .map_err(TwitchHandlerError::SendMessage)?;
+                        Ok(false)
+                    }
+                }
             }
-        } else {
-            Ok(false)
+            _ => Ok(false),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::format_shoutout;
+
+    #[test]
+    fn test_format_shoutout_without_channel() {
+        assert_eq!(
+            format_shoutout("Muni_Corn", "muni_corn", None),
+            "this is a PSA that you NEED to go check out Muni_Corn at https://twitch.tv/muni_corn ! :3 clearly they deserve the shoutout, so go follow them now >:c"
+        );
+    }
+}
@@ -6,14 +6,25 @@ use crate::{
     twitch::{
         agent::TwitchAgent,
         bot::MuniBotTwitchIRCClient,
+        commands::TwitchCommand,
         handler::{TwitchHandlerError, TwitchMessageHandler},
     },
 };
 
+const DISCORD: TwitchCommand = TwitchCommand::new("discord");
+
 pub struct SocialsHandler;
 
 #[async_trait]
 impl TwitchMessageHandler for SocialsHandler {
+    fn twitch_handler_name(&self) -> &'static str {
+        "socials"
+    }
+
+    fn twitch_commands(&self) -> &'static [TwitchCommand] {
+        &[DISCORD]
+    }
+
     async fn handle_twitch_message(
         &mut self,
         message: &ServerMessage,
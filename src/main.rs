@@ -2,22 +2,29 @@ use std::sync::Arc;
 
 use clap::Parser;
 use env_logger::Env;
-use log::{error, info, warn};
+use log::{error, warn};
 use munibot::{
     config::Config,
+    db::{self, MuniBotDb},
     discord::{
-        simple::SimpleCommandProvider, start_discord_integration, vc_greeter::VoiceChannelGreeter,
+        relay::DiscordRelayMessage, simple::SimpleCommandProvider, start_discord_integration,
+        vc_greeter::VoiceChannelGreeter,
     },
     handlers::{
-        bot_affection::BotAffectionProvider, dice::DiceHandler, economy::EconomyProvider,
-        greeting::GreetingHandler, magical::MagicalHandler,
+        account_link::AccountLinkProvider, autoban::audit::ModerationNotice,
+        bot_affection::BotAffectionProvider, custom_commands::CustomCommandHandler,
+        dice::DiceHandler, economy::EconomyProvider, greeting::GreetingHandler,
+        magical::MagicalHandler, quotes::QuotesProvider,
         temperature::TemperatureConversionProvider, ventriloquize::VentriloquizeProvider,
         DiscordCommandProviderCollection, DiscordMessageHandlerCollection,
     },
-    twitch::{bot::TwitchBot, get_basic_auth_url},
+    twitch::{bot::TwitchBot, channels::ChannelMembershipRequest, tokens::TwitchAppCredentials},
     MuniBotError,
 };
-use tokio::sync::Mutex;
+use tokio::sync::{
+    mpsc::{self, UnboundedReceiver, UnboundedSender},
+    Mutex,
+};
 
 #[derive(Parser, Debug)]
 struct Args {
@@ -34,15 +41,35 @@ async fn main() -> Result<(), MuniBotError> {
     let args = Args::parse();
     let config = Config::read_or_write_default_from(&args.config_file)?;
 
-    let discord_handle = start_discord(config.clone());
+    // one database connection is shared by every integration
+    let db = db::connect(&config.db).await?;
+    db::start_health_monitor(db.clone());
+
+    db::migrations::run(&db).await?;
+
+    // lets discord commands ask the twitch bot to join or leave channels
+    let (membership_tx, membership_rx) = mpsc::unbounded_channel();
+
+    // lets the twitch bot mirror its moderation actions to discord
+    let (moderation_tx, moderation_rx) = mpsc::unbounded_channel();
+
+    // lets the twitch bot post messages in discord channels
+    let (relay_tx, relay_rx) = mpsc::unbounded_channel();
+
+    let discord_handle = start_discord(
+        config.clone(),
+        db.clone(),
+        membership_tx,
+        moderation_rx,
+        relay_rx,
+    );
 
     // ensure credentials exist
-    let twitch_handle = match std::env::var("TWITCH_TOKEN") {
-        Ok(twitch_token) => {
+    let twitch_handle = match TwitchAppCredentials::from_env() {
+        Ok(twitch_app) => {
             // start twitch
-            match TwitchBot::new(config.clone())
-                .await
-                .launch(twitch_token, &config)
+            match TwitchBot::new(db, moderation_tx, relay_tx)
+                .launch(twitch_app, &config, membership_rx)
                 .await
             {
                 // wait for the twitch bot to stop, if ever
@@ -54,12 +81,7 @@ async fn main() -> Result<(), MuniBotError> {
             }
         }
         Err(e) => {
-            if let Ok(auth_page_url) = get_basic_auth_url() {
-                error!("no TWITCH_TOKEN found ({e})");
-                info!("visit {auth_page_url} to get a token");
-            } else {
-                error!("no TWITCH_TOKEN found and no TWITCH_CLIENT_ID set. the TWITCH_CLIENT_ID environment variable is required to generate an auth url link.");
-            }
+            error!("TWITCH_CLIENT_ID and TWITCH_CLIENT_SECRET are both required for twitch ({e})");
             warn!("since twitch integration is misconfigured, i won't be running my twitch integration at this time. >.>");
             None
         }
@@ -82,26 +104,39 @@ async fn main() -> Result<(), MuniBotError> {
     Ok(())
 }
 
-fn start_discord(config: Config) -> tokio::task::JoinHandle<()> {
+fn start_discord(
+    config: Config,
+    db: MuniBotDb,
+    twitch_membership: UnboundedSender<ChannelMembershipRequest>,
+    moderation_notices: UnboundedReceiver<ModerationNotice>,
+    relay_messages: UnboundedReceiver<DiscordRelayMessage>,
+) -> tokio::task::JoinHandle<()> {
     // start discord
     let discord_handlers: DiscordMessageHandlerCollection = vec![
         Arc::new(Mutex::new(GreetingHandler)),
-        Arc::new(Mutex::new(EconomyProvider)),
+        Arc::new(Mutex::new(EconomyProvider::default())),
         Arc::new(Mutex::new(VoiceChannelGreeter)),
+        Arc::new(Mutex::new(CustomCommandHandler::new(db.clone()))),
     ];
     let discord_command_providers: DiscordCommandProviderCollection = vec![
         Box::new(DiceHandler),
         Box::new(BotAffectionProvider),
         Box::new(MagicalHandler),
         Box::new(VentriloquizeProvider),
-        Box::new(EconomyProvider),
+        Box::new(EconomyProvider::default()),
         Box::new(TemperatureConversionProvider),
         Box::new(SimpleCommandProvider),
+        Box::new(QuotesProvider),
+        Box::new(AccountLinkProvider),
     ];
 
     tokio::spawn(start_discord_integration(
         discord_handlers,
         discord_command_providers,
         config,
+        db,
+        twitch_membership,
+        moderation_notices,
+        relay_messages,
     ))
 }
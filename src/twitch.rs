@@ -1,41 +1,52 @@
-use std::{borrow::Cow, env::VarError, str::FromStr};
+use std::{borrow::Cow, str::FromStr};
 
 use twitch_oauth2::Scope;
 use url::Url;
 
 pub mod agent;
 pub mod bot;
+pub(crate) mod cache;
+pub mod channels;
+pub mod commands;
+pub mod eventsub;
 pub mod handler;
 pub mod tokens;
+pub mod utils;
 
 pub(crate) const REDIRECT_URI: &str = "http://localhost:6864/twitch";
 
-const SCOPE: [Scope; 8] = [
+const SCOPE: [Scope; 13] = [
+    Scope::BitsRead,
+    Scope::ChannelManageRedemptions,
     Scope::ChannelReadRedemptions,
     Scope::ChannelReadSubscriptions,
     Scope::ModeratorManageAnnouncements,
     Scope::ModeratorManageBannedUsers,
     Scope::Other(Cow::Borrowed("moderator:read:chatters")),
     Scope::ModeratorManageChatMessages,
+    Scope::ModeratorManageShoutouts,
+    Scope::ModeratorManageWarnings,
+    Scope::ModeratorReadFollowers,
     Scope::ChatEdit,
     Scope::ChatRead,
 ];
 
-/// Returns the authorization URL to authorize munibot's Twitch account. Will
-/// only return an error if the `TWITCH_CLIENT_ID` variable is not set.
-pub fn get_basic_auth_url() -> Result<Url, VarError> {
+/// Returns the URL munibot's owner visits to authorize munibot's Twitch
+/// account. Twitch sends them back to [`REDIRECT_URI`] with an authorization
+/// code, along with the given `state` so the redirect can be checked.
+pub fn get_auth_code_url(client_id: &str, state: &str) -> Url {
     let mut url = Url::from_str("https://id.twitch.tv/oauth2/authorize").unwrap();
-    let client_id = std::env::var("TWITCH_CLIENT_ID")?;
 
     let auth = vec![
-        ("response_type", "token"),
-        ("client_id", client_id.as_str()),
+        ("response_type", "code"),
+        ("client_id", client_id),
         ("redirect_uri", REDIRECT_URI),
+        ("state", state),
     ];
 
     url.query_pairs_mut().extend_pairs(auth);
     url.query_pairs_mut()
         .append_pair("scope", &SCOPE.as_slice().join(" "));
 
-    Ok(url)
+    url
 }
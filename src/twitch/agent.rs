@@ -1,27 +1,57 @@
-use std::{error::Error, fmt::Display};
+use std::{error::Error, fmt::Display, time::Duration};
 
 use log::{debug, info};
 use twitch_api::{
-    helix::{channels::ChannelInformation, users::User, ClientRequestError},
-    types::UserId,
+    eventsub::{EventSubscription, Transport},
+    helix::{
+        channels::ChannelInformation,
+        chat::SendAShoutoutRequest,
+        points::{
+            CustomRewardRedemptionStatus, UpdateRedemptionStatusBody, UpdateRedemptionStatusRequest,
+        },
+        users::User,
+        ClientRequestError, EmptyBody, HelixRequestDeleteError,
+    },
+    types::{EventSubId, UserId},
     HelixClient,
 };
 
-use super::tokens::TwitchAuth;
+use super::{cache::TtlCache, tokens::TwitchAuth};
+
+/// The longest timeout Twitch allows, in seconds (two weeks).
+const MAX_TIMEOUT_SECS: u32 = 1_209_600;
+
+/// How long looked-up users are remembered. Logins rarely change, so this can
+/// be a while.
+const USER_CACHE_TTL: Duration = Duration::from_mins(10);
+
+/// How long looked-up channel info is remembered. Streamers change their
+/// titles and categories often, so this is kept short.
+const CHANNEL_CACHE_TTL: Duration = Duration::from_mins(1);
 
 pub struct TwitchAgent<'a> {
     helix_client: HelixClient<'a, reqwest::Client>,
     auth: TwitchAuth,
+
+    users_by_login: TtlCache<String, User>,
+    users_by_id: TtlCache<UserId, User>,
+    channels: TtlCache<String, ChannelInformation>,
 }
 
 impl<'a> TwitchAgent<'a> {
     pub fn new(auth: TwitchAuth) -> Self {
         let helix_client = HelixClient::default();
-        Self { helix_client, auth }
+        Self {
+            helix_client,
+            auth,
+            users_by_login: TtlCache::new(USER_CACHE_TTL),
+            users_by_id: TtlCache::new(USER_CACHE_TTL),
+            channels: TtlCache::new(CHANNEL_CACHE_TTL),
+        }
     }
 
     pub fn get_bot_id(&self) -> &UserId {
-        &self.auth.get_user_token().user_id
+        self.auth.get_bot_id()
     }
 
     pub fn get_helix_client(&self) -> &HelixClient<'a, reqwest::Client> {
@@ -32,22 +62,64 @@ impl<'a> TwitchAgent<'a> {
         &self.auth
     }
 
-    /// Get the channel info for the given broadcaster ID
+    /// Get the channel info for the given broadcaster ID. Channel info is
+    /// cached for a short while.
     pub async fn get_channel_info(
         &self,
         broadcaster_id: &str,
     ) -> Result<Option<ChannelInformation>, TwitchAgentError> {
-        Ok(self
+        if let Some(channel) = self.channels.get(&broadcaster_id.to_string()) {
+            return Ok(Some(channel));
+        }
+
+        let channel = self
             .helix_client
-            .get_channel_from_id(broadcaster_id, self.auth.get_user_token())
-            .await?)
+            .get_channel_from_id(broadcaster_id, &self.auth.get_user_token().await?)
+            .await?;
+        if let Some(channel) = &channel {
+            self.channels
+                .insert(broadcaster_id.to_string(), channel.clone());
+        }
+        Ok(channel)
     }
 
+    /// Get the user with the given login. Users are cached for a while.
     pub async fn get_user_from_login(&self, login: &str) -> Result<Option<User>, TwitchAgentError> {
-        Ok(self
+        let login = login.to_lowercase();
+        if let Some(user) = self.users_by_login.get(&login) {
+            return Ok(Some(user));
+        }
+
+        let user = self
             .helix_client
-            .get_user_from_login(login, self.auth.get_user_token())
-            .await?)
+            .get_user_from_login(&login, &self.auth.get_user_token().await?)
+            .await?;
+        if let Some(user) = &user {
+            self.remember_user(user);
+        }
+        Ok(user)
+    }
+
+    /// Get the user with the given ID. Users are cached for a while.
+    pub async fn get_user_from_id(&self, id: &UserId) -> Result<Option<User>, TwitchAgentError> {
+        if let Some(user) = self.users_by_id.get(id) {
+            return Ok(Some(user));
+        }
+
+        let user = self
+            .helix_client
+            .get_user_from_id(id, &self.auth.get_user_token().await?)
+            .await?;
+        if let Some(user) = &user {
+            self.remember_user(user);
+        }
+        Ok(user)
+    }
+
+    fn remember_user(&self, user: &User) {
+        self.users_by_login
+            .insert(user.login.to_string(), user.clone());
+        self.users_by_id.insert(user.id.clone(), user.clone());
     }
 
     pub async fn ban_user(
@@ -65,12 +137,175 @@ impl<'a> TwitchAgent<'a> {
                 None,
                 broadcaster_id,
                 moderator_id,
-                self.auth.get_user_token(),
+                &self.auth.get_user_token().await?,
             )
             .await?;
         info!("munibot banned user {ban_user_id} from broadcaster {broadcaster_id}");
         Ok(())
     }
+
+    /// Lifts a ban or timeout.
+    pub async fn unban_user(
+        &self,
+        unban_user_id: &UserId,
+        broadcaster_id: &UserId,
+    ) -> Result<(), TwitchAgentError> {
+        debug!("attempting to unban user {}", unban_user_id);
+        let moderator_id = self.get_bot_id();
+        self.helix_client
+            .unban_user(
+                unban_user_id,
+                broadcaster_id,
+                moderator_id,
+                &self.auth.get_user_token().await?,
+            )
+            .await?;
+        info!("munibot unbanned user {unban_user_id} from broadcaster {broadcaster_id}");
+        Ok(())
+    }
+
+    /// Times out a user for the given duration. Twitch only allows timeouts of
+    /// up to two weeks, so longer durations are shortened.
+    pub async fn timeout_user(
+        &self,
+        timeout_user_id: &UserId,
+        duration: Duration,
+        reason: &str,
+        broadcaster_id: &UserId,
+    ) -> Result<(), TwitchAgentError> {
+        debug!("attempting to time out user {}", timeout_user_id);
+        let moderator_id = self.get_bot_id();
+        let seconds = duration.as_secs().clamp(1, MAX_TIMEOUT_SECS as u64) as u32;
+        self.helix_client
+            .ban_user(
+                timeout_user_id,
+                reason,
+                seconds,
+                broadcaster_id,
+                moderator_id,
+                &self.auth.get_user_token().await?,
+            )
+            .await?;
+        info!(
+            "munibot timed out user {timeout_user_id} from broadcaster {broadcaster_id} for {seconds}s"
+        );
+        Ok(())
+    }
+
+    /// Sends a user a warning that they have to acknowledge before they can
+    /// chat again.
+    pub async fn warn_user(
+        &self,
+        warn_user_id: &UserId,
+        reason: &str,
+        broadcaster_id: &UserId,
+    ) -> Result<(), TwitchAgentError> {
+        debug!("attempting to warn user {}", warn_user_id);
+        let moderator_id = self.get_bot_id();
+        self.helix_client
+            .warn_chat_user(
+                warn_user_id,
+                reason,
+                broadcaster_id,
+                moderator_id,
+                &self.auth.get_user_token().await?,
+            )
+            .await?;
+        info!("munibot warned user {warn_user_id} in broadcaster {broadcaster_id}'s chat");
+        Ok(())
+    }
+
+    /// Deletes a single chat message.
+    pub async fn delete_message(
+        &self,
+        message_id: &str,
+        broadcaster_id: &UserId,
+    ) -> Result<(), TwitchAgentError> {
+        debug!("attempting to delete message {}", message_id);
+        let moderator_id = self.get_bot_id();
+        self.helix_client
+            .delete_chat_message(
+                broadcaster_id,
+                moderator_id,
+                message_id,
+                &self.auth.get_user_token().await?,
+            )
+            .await?;
+        info!("munibot deleted message {message_id} from broadcaster {broadcaster_id}");
+        Ok(())
+    }
+
+    /// Sends a native Twitch shoutout from one broadcaster to another. This
+    /// only works if munibot is a moderator in the sending broadcaster's
+    /// channel, and Twitch limits how often it can be done.
+    pub async fn send_shoutout(
+        &self,
+        from_broadcaster_id: &UserId,
+        to_broadcaster_id: &UserId,
+    ) -> Result<(), TwitchAgentError> {
+        debug!("attempting to shout out {to_broadcaster_id} from {from_broadcaster_id}");
+        let request =
+            SendAShoutoutRequest::new(from_broadcaster_id, to_broadcaster_id, self.get_bot_id());
+        self.helix_client
+            .req_post(request, EmptyBody, &self.auth.get_user_token().await?)
+            .await?;
+        info!("munibot shouted out {to_broadcaster_id} from broadcaster {from_broadcaster_id}");
+        Ok(())
+    }
+
+    /// Marks a channel points redemption as fulfilled, or cancels it to refund
+    /// the viewer. Twitch only allows this for rewards created by munibot's
+    /// application, in munibot's own channel.
+    pub async fn update_redemption_status(
+        &self,
+        broadcaster_id: &UserId,
+        reward_id: &str,
+        redemption_id: &str,
+        status: CustomRewardRedemptionStatus,
+    ) -> Result<(), TwitchAgentError> {
+        debug!("attempting to set redemption {redemption_id} to {status:?}");
+        let request = UpdateRedemptionStatusRequest::new(broadcaster_id, reward_id, redemption_id);
+        self.helix_client
+            .req_patch(
+                request,
+                UpdateRedemptionStatusBody::status(status),
+                &self.auth.get_user_token().await?,
+            )
+            .await?;
+        info!(
+            "munibot set redemption {redemption_id} in broadcaster {broadcaster_id} to {status:?}"
+        );
+        Ok(())
+    }
+
+    /// Subscribes an EventSub websocket session to an event, returning the
+    /// subscription's ID.
+    pub async fn subscribe_to_event<E: EventSubscription + Send>(
+        &self,
+        subscription: E,
+        session_id: &str,
+    ) -> Result<EventSubId, TwitchAgentError> {
+        let subscription = self
+            .helix_client
+            .create_eventsub_subscription(
+                subscription,
+                Transport::websocket(session_id),
+                &self.auth.get_user_token().await?,
+            )
+            .await?;
+        Ok(subscription.id)
+    }
+
+    /// Deletes an EventSub subscription.
+    pub async fn unsubscribe_from_event(
+        &self,
+        subscription_id: &EventSubId,
+    ) -> Result<(), TwitchAgentError> {
+        self.helix_client
+            .delete_eventsub_subscription(subscription_id, &self.auth.get_user_token().await?)
+            .await?;
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -82,6 +317,19 @@ pub enum TwitchAgentError {
     Other(String),
 }
 
+impl TwitchAgentError {
+    /// Returns true if Twitch refused to unban a user because they weren't
+    /// banned, like when their timeout already ran out.
+    pub fn is_not_banned(&self) -> bool {
+        matches!(
+            self,
+            TwitchAgentError::HelixRequestError(ClientRequestError::HelixRequestDeleteError(
+                HelixRequestDeleteError::Error { status, message, .. }
+            )) if status.as_u16() == 400 && message.contains("not banned")
+        )
+    }
+}
+
 impl From<reqwest::Error> for TwitchAgentError {
     fn from(e: reqwest::Error) -> Self {
         Self::ReqwestError(e)
@@ -1,60 +1,147 @@
+use std::sync::Arc;
+
 use anyhow::Result;
 use async_trait::async_trait;
-use log::{error, info, warn};
-use tokio::task::JoinHandle;
-use twitch_irc::{
-    irc, login::StaticLoginCredentials, message::ServerMessage, ClientConfig, SecureTCPTransport,
-    TwitchIRCClient,
+use log::{error, warn};
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
 };
+use twitch_irc::{irc, message::ServerMessage, ClientConfig, SecureTCPTransport, TwitchIRCClient};
 
 use super::{
     agent::TwitchAgent,
-    handler::{TwitchHandlerError, TwitchMessageHandler},
+    channels::{self, ChannelMembershipHandler, ChannelMembershipRequest},
+    commands::{CommandCooldowns, TwitchCommand},
+    eventsub::{self, TwitchEvent},
+    handler::{TwitchEventHandler, TwitchHandlerError, TwitchMessageHandler},
+    tokens::{
+        self, MuniBotTwitchCredentials, TwitchAppCredentials, TwitchAuth, TwitchTokenStorage,
+    },
 };
 use crate::{
     config::Config,
+    db::MuniBotDb,
+    discord::relay::DiscordRelayMessage,
     handlers::{
-        affection::AffectionHandler, autoban::AutoBanHandler, bonk::BonkHandler,
-        greeting::GreetingHandler, lift::LiftHandler, lurk::LurkHandler, magical::MagicalHandler,
-        quotes::QuotesHandler, shoutout::ShoutoutHandler, socials::SocialsHandler,
-        TwitchHandlerCollection,
+        account_link::AccountLinkHandler,
+        affection::AffectionHandler,
+        autoban::{audit::ModerationNotice, AutoBanHandler},
+        bonk::BonkHandler,
+        content_warning::ContentWarningHandler,
+        custom_commands::CustomCommandHandler,
+        economy::TwitchEconomyHandler,
+        greeting::GreetingHandler,
+        lift::LiftHandler,
+        lurk::LurkHandler,
+        magical::MagicalHandler,
+        quotes::QuotesHandler,
+        redemptions::RedemptionHandler,
+        shoutout::ShoutoutHandler,
+        socials::SocialsHandler,
+        TwitchEventHandlerCollection, TwitchHandlerCollection,
     },
-    twitch::tokens::TwitchAuth,
 };
 
-pub type MuniBotTwitchIRCClient = TwitchIRCClient<SecureTCPTransport, StaticLoginCredentials>;
-pub type MuniBotTwitchIRCError = twitch_irc::Error<SecureTCPTransport, StaticLoginCredentials>;
+pub type MuniBotTwitchIRCClient = TwitchIRCClient<SecureTCPTransport, MuniBotTwitchCredentials>;
+pub type MuniBotTwitchIRCError = twitch_irc::Error<SecureTCPTransport, MuniBotTwitchCredentials>;
 
 pub struct TwitchBot {
+    db: MuniBotDb,
     auto_ban_handler: AutoBanHandler,
+    membership_handler: ChannelMembershipHandler,
+    account_link_handler: AccountLinkHandler,
+    redemption_handler: RedemptionHandler,
     message_handlers: TwitchHandlerCollection,
+    event_handlers: TwitchEventHandlerCollection,
+    command_cooldowns: CommandCooldowns,
 }
 
 impl TwitchBot {
-    pub async fn new(config: Config) -> Self {
+    pub fn new(
+        db: MuniBotDb,
+        moderation_notices: UnboundedSender<ModerationNotice>,
+        discord_messages: UnboundedSender<DiscordRelayMessage>,
+    ) -> Self {
+        // shoutouts are handled in chat, for eventsub raids and for
+        // redemptions, sharing one raid cooldown
+        let shoutout_handler = ShoutoutHandler::new();
+
+        // content warnings can be issued in chat or by redemptions
+        let content_warning_handler =
+            ContentWarningHandler::new(db.clone(), discord_messages.clone());
+
+        let auto_ban_handler = AutoBanHandler::new(db.clone(), moderation_notices);
+        let membership_handler = ChannelMembershipHandler::new(db.clone());
+        let account_link_handler = AccountLinkHandler::new(db.clone());
+        let mut message_handlers: TwitchHandlerCollection = vec![
+            Box::new(TwitchEconomyHandler::new(db.clone())),
+            Box::new(content_warning_handler.clone()),
+            Box::new(QuotesHandler::new(db.clone())),
+            Box::new(BonkHandler),
+            Box::new(SocialsHandler),
+            Box::new(LurkHandler),
+            Box::new(GreetingHandler),
+            Box::new(LiftHandler),
+            Box::new(shoutout_handler.clone()),
+            Box::new(AffectionHandler),
+            Box::new(MagicalHandler),
+        ];
+
+        // custom commands can't be named after any of munibot's own
+        let builtin_commands: Vec<TwitchCommand> = message_handlers
+            .iter()
+            .map(|handler| handler.twitch_commands())
+            .chain([
+                auto_ban_handler.twitch_commands(),
+                membership_handler.twitch_commands(),
+                account_link_handler.twitch_commands(),
+            ])
+            .flatten()
+            .copied()
+            .collect();
+        message_handlers.push(Box::new(
+            CustomCommandHandler::new(db.clone()).with_builtin_commands(&builtin_commands),
+        ));
+
         Self {
-            auto_ban_handler: AutoBanHandler,
-            message_handlers: vec![
-                Box::new(QuotesHandler::new(&config.db).await.unwrap()),
-                Box::new(BonkHandler),
-                Box::new(SocialsHandler),
-                Box::new(LurkHandler),
-                Box::new(GreetingHandler),
-                Box::new(LiftHandler::new()),
-                Box::new(ShoutoutHandler),
-                Box::new(AffectionHandler),
-                Box::new(MagicalHandler),
-            ],
+            db,
+            auto_ban_handler,
+            membership_handler,
+            account_link_handler,
+            redemption_handler: RedemptionHandler::new(
+                content_warning_handler,
+                vec![Box::new(shoutout_handler.clone())],
+                discord_messages,
+            ),
+            message_handlers,
+            event_handlers: vec![Box::new(shoutout_handler)],
+            command_cooldowns: CommandCooldowns::default(),
         }
     }
 
-    pub async fn launch(mut self, token: String, bot_config: &Config) -> Result<JoinHandle<()>> {
-        let credentials =
-            StaticLoginCredentials::new(bot_config.twitch.twitch_user.clone(), Some(token.clone()));
-        let cred_config = ClientConfig::new_simple(credentials.clone());
-        let twitch_auth = TwitchAuth::new(&bot_config.twitch.twitch_user, &token).await?;
-        let agent = TwitchAgent::new(twitch_auth);
+    pub async fn launch(
+        mut self,
+        app: TwitchAppCredentials,
+        bot_config: &Config,
+        membership_requests: UnboundedReceiver<ChannelMembershipRequest>,
+    ) -> Result<JoinHandle<()>> {
+        let twitch_user = bot_config.twitch.twitch_user.clone();
+
+        // the first time munibot runs, its owner has to authorize it. after
+        // that, tokens are refreshed and saved on their own.
+        let storage = TwitchTokenStorage::new(self.db.clone(), &twitch_user);
+        tokens::ensure_token(&storage, &app).await?;
+        let credentials = MuniBotTwitchCredentials::init_with_username(
+            Some(twitch_user.clone()),
+            app.client_id,
+            app.client_secret,
+            storage,
+        );
+        let twitch_auth = TwitchAuth::new(credentials.clone()).await?;
+        let agent = Arc::new(TwitchAgent::new(twitch_auth));
 
+        let cred_config = ClientConfig::new_simple(credentials);
         let (mut incoming_messages, irc_client) = MuniBotTwitchIRCClient::new(cred_config);
         irc_client
             .send_message(irc![
@@ -65,49 +152,113 @@ impl TwitchBot {
             .await?;
 
         // join all the initial channels
-        for channel in &bot_config.twitch.initial_channels {
-            self.join_channel(channel, &irc_client).await;
+        let mut channels = bot_config.twitch.initial_channels.clone();
+
+        // join channels we were invited to at runtime
+        match self.membership_handler.saved_channels().await {
+            Ok(saved_channels) => channels.extend(saved_channels),
+            Err(e) => error!("couldn't load saved twitch channels from the database :( {e}"),
         }
 
         // join our own channel too
-        self.join_channel(&bot_config.twitch.twitch_user, &irc_client)
-            .await;
+        channels.push(twitch_user);
+
+        for channel in &channels {
+            self.join_channel(channel, &irc_client);
+        }
+
+        // channels joined or left from here on are passed to eventsub as well
+        let (eventsub_membership_tx, eventsub_membership_rx) = mpsc::unbounded_channel();
+        self.membership_handler
+            .forward_membership_to(eventsub_membership_tx.clone());
+
+        // listen for join/part requests from other integrations
+        channels::start_membership_listener(
+            irc_client.clone(),
+            membership_requests,
+            eventsub_membership_tx,
+        );
+
+        // listen for eventsub events in the channels we joined
+        let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+        let broadcaster_ids = Self::broadcaster_ids(&agent, &channels).await;
+        eventsub::start_eventsub_client(
+            agent.clone(),
+            broadcaster_ids,
+            eventsub_membership_rx,
+            event_tx,
+        );
 
         let bot_config_clone = bot_config.clone();
         let handle = tokio::spawn(async move {
-            while let Some(message) = incoming_messages.recv().await {
-                if let ServerMessage::Notice(notice_msg) = message {
-                    if let Some(channel) = notice_msg.channel_login {
-                        warn!(
-                            "notice received from {}: {}",
-                            channel, notice_msg.message_text
-                        );
-                    } else {
-                        warn!("notice received from twitch: {}", notice_msg.message_text);
+            loop {
+                tokio::select! {
+                    message = incoming_messages.recv() => {
+                        let Some(message) = message else {
+                            break;
+                        };
+                        if let ServerMessage::Notice(notice_msg) = message {
+                            if let Some(channel) = notice_msg.channel_login {
+                                warn!(
+                                    "notice received from {}: {}",
+                                    channel, notice_msg.message_text
+                                );
+                            } else {
+                                warn!("notice received from twitch: {}", notice_msg.message_text);
+                            }
+                        } else if let Err(e) = self
+                            .handle_twitch_message(&message, &irc_client, &agent, &bot_config_clone)
+                            .await
+                        {
+                            error!("error in twitch message handler! {e}");
+                        }
+                    }
+                    Some(event) = event_rx.recv() => {
+                        if let Err(e) = self
+                            .handle_twitch_event(&event, &irc_client, &agent, &bot_config_clone)
+                            .await
+                        {
+                            error!("error in twitch event handler! {e}");
+                        }
                     }
-                } else if let Err(e) = self
-                    .handle_twitch_message(&message, &irc_client, &agent, &bot_config_clone)
-                    .await
-                {
-                    error!("error in twitch message handler! {e}");
                 }
             }
         });
         Ok(handle)
     }
 
-    async fn join_channel(&self, channel: &str, client: &MuniBotTwitchIRCClient) {
-        // join a channel. this will error if the passed channel login name is
-        // malformed.
-        if let Err(e) = client.join(channel.to_string()) {
+    /// Looks up the user IDs of the given channels, skipping any that can't be
+    /// found.
+    async fn broadcaster_ids(
+        agent: &TwitchAgent<'_>,
+        channels: &[String],
+    ) -> Vec<twitch_api::types::UserId> {
+        let mut ids = Vec::new();
+        for channel in channels {
+            match agent.get_user_from_login(channel).await {
+                Ok(Some(user)) => ids.push(user.id),
+                Ok(None) => warn!("twitch channel {channel} doesn't seem to exist"),
+                Err(e) => error!("couldn't look up twitch channel {channel} :( {e}"),
+            }
+        }
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    fn join_channel(&self, channel: &str, client: &MuniBotTwitchIRCClient) {
+        if let Err(e) = channels::join_channel(client, channel) {
             error!("error joining {}'s twitch channel :( {}", channel, e);
         }
-        info!("twitch: joined channel {}", channel);
     }
 }
 
 #[async_trait]
 impl TwitchMessageHandler for TwitchBot {
+    fn twitch_handler_name(&self) -> &'static str {
+        "bot"
+    }
+
     async fn handle_twitch_message(
         &mut self,
         message: &ServerMessage,
@@ -123,17 +274,84 @@ impl TwitchMessageHandler for TwitchBot {
             error!("error in autoban handler at root: {}", e);
         }
 
-        if let ServerMessage::Privmsg(privmsg) = message
-            && privmsg.channel_login == "muni_corn"
+        // handle membership commands before anything else. these only respond in
+        // our own channel.
+        match self
+            .membership_handler
+            .handle_twitch_message(message, client, agent, config)
+            .await
         {
+            Ok(true) => return Ok(true),
+            Err(e) => error!("error in membership handler at root: {}", e),
+            _ => (),
+        }
+
+        // account linking works in every channel, since people can link from
+        // wherever they chat
+        match self
+            .account_link_handler
+            .handle_twitch_message(message, client, agent, config)
+            .await
+        {
+            Ok(true) => return Ok(true),
+            Err(e) => error!("error in account link handler at root: {}", e),
+            _ => (),
+        }
+
+        // chat messages and notices (like raids) go to the channel's handlers
+        let channel_login = match message {
+            ServerMessage::Privmsg(privmsg) => Some(&privmsg.channel_login),
+            ServerMessage::UserNotice(notice) => Some(&notice.channel_login),
+            _ => None,
+        };
+        if let Some(channel_login) = channel_login {
             for message_handler in self.message_handlers.iter_mut() {
+                // skip handlers that aren't enabled in this channel
+                if !config
+                    .twitch
+                    .is_handler_enabled(channel_login, message_handler.twitch_handler_name())
+                {
+                    continue;
+                }
+
+                // commands used by someone without the right role, or while
+                // on cooldown, are ignored
+                let command = match message {
+                    ServerMessage::Privmsg(m) => message_handler
+                        .twitch_commands()
+                        .iter()
+                        .find(|command| command.matches(&m.message_text))
+                        .map(|command| (m, command)),
+                    _ => None,
+                };
+                if let Some((m, command)) = command
+                    && (!command.allows(m)
+                        || !self.command_cooldowns.is_ready(
+                            channel_login,
+                            command.name,
+                            &m.sender.login,
+                        ))
+                {
+                    return Ok(true);
+                }
+
                 // try to handle the message. if the handler determines the message was handled,
                 // we'll stop
                 match message_handler
                     .handle_twitch_message(message, client, agent, config)
                     .await
                 {
-                    Ok(true) => return Ok(true),
+                    Ok(true) => {
+                        if let Some((m, command)) = command {
+                            self.command_cooldowns.start(
+                                channel_login,
+                                command.name,
+                                &m.sender.login,
+                                command.cooldown,
+                            );
+                        }
+                        return Ok(true);
+                    }
                     Err(e) => error!("error twitch in message handler: {}", e),
                     _ => continue,
                 }
@@ -143,3 +361,47 @@ impl TwitchMessageHandler for TwitchBot {
         return Ok(false);
     }
 }
+
+#[async_trait]
+impl TwitchEventHandler for TwitchBot {
+    fn twitch_event_handler_name(&self) -> &'static str {
+        "bot"
+    }
+
+    async fn handle_twitch_event(
+        &mut self,
+        event: &TwitchEvent,
+        client: &MuniBotTwitchIRCClient,
+        agent: &TwitchAgent,
+        config: &Config,
+    ) -> Result<bool, TwitchHandlerError> {
+        // redemptions only go to the handlers their rewards are set up to run
+        if let TwitchEvent::Redemption { .. } = event {
+            return self
+                .redemption_handler
+                .handle_twitch_event(event, client, agent, config)
+                .await;
+        }
+
+        for event_handler in self.event_handlers.iter_mut() {
+            // skip handlers that aren't enabled in this channel
+            if !config.twitch.is_handler_enabled(
+                event.channel_login(),
+                event_handler.twitch_event_handler_name(),
+            ) {
+                continue;
+            }
+
+            match event_handler
+                .handle_twitch_event(event, client, agent, config)
+                .await
+            {
+                Ok(true) => return Ok(true),
+                Err(e) => error!("error in twitch event handler: {}", e),
+                _ => continue,
+            }
+        }
+
+        Ok(false)
+    }
+}
@@ -0,0 +1,73 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A map whose entries are forgotten some time after they're added. Entries
+/// are cloned out, so this is best for small values.
+#[derive(Debug)]
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<K, (Instant, V)>>,
+}
+
+impl<K: Eq + Hash, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the value for a key, if there is one and it hasn't expired.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.get_at(key, Instant::now())
+    }
+
+    /// Remembers a value for a key. Expired entries are cleaned up whenever a
+    /// value is added, so the cache doesn't grow forever.
+    pub fn insert(&self, key: K, value: V) {
+        self.insert_at(key, value, Instant::now())
+    }
+
+    fn get_at(&self, key: &K, now: Instant) -> Option<V> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(key)
+            .filter(|(added, _)| now.duration_since(*added) < self.ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    fn insert_at(&self, key: K, value: V, now: Instant) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.retain(|_, (added, _)| now.duration_since(*added) < self.ttl);
+        entries.insert(key, (now, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::TtlCache;
+
+    #[test]
+    fn test_entries_expire() {
+        let cache = TtlCache::new(Duration::from_secs(60));
+        let start = Instant::now();
+
+        cache.insert_at("muni", 1, start);
+        assert_eq!(
+            cache.get_at(&"muni", start + Duration::from_secs(59)),
+            Some(1)
+        );
+        assert_eq!(cache.get_at(&"muni", start + Duration::from_secs(60)), None);
+        assert_eq!(cache.get_at(&"someone", start), None);
+
+        // expired entries are cleaned up as new ones are added
+        cache.insert_at("someone", 2, start + Duration::from_secs(61));
+        assert_eq!(cache.entries.lock().unwrap().len(), 1);
+    }
+}
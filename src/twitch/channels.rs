@@ -0,0 +1,275 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Local};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use surrealdb::{Connection, RecordId, Surreal};
+use tokio::{
+    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+};
+use twitch_irc::message::ServerMessage;
+
+use super::{
+    agent::TwitchAgent,
+    bot::MuniBotTwitchIRCClient,
+    commands::TwitchCommand,
+    handler::{TwitchHandlerError, TwitchMessageHandler},
+};
+use crate::{
+    config::Config,
+    db::{DbItem, MuniBotDb},
+};
+
+const JOINED_CHANNEL_TABLE: &str = "twitch_joined_channel";
+
+/// A request to change which Twitch channels munibot is in, sent from outside
+/// the Twitch integration (e.g. from a Discord command).
+#[derive(Clone, Debug)]
+pub enum ChannelMembershipRequest {
+    Join(String),
+    Part(String),
+}
+
+/// A Twitch channel that munibot was asked to join at runtime. These are
+/// rejoined when munibot starts.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct JoinedChannel {
+    channel_login: String,
+    joined_at: DateTime<Local>,
+}
+
+#[async_trait]
+impl<C: Connection> DbItem<C> for JoinedChannel {
+    type GetQuery = String;
+    type Id = String;
+    type UpsertContent = Self;
+
+    const NAME: &'static str = JOINED_CHANNEL_TABLE;
+
+    fn get_id(&self) -> Self::Id {
+        self.channel_login.clone()
+    }
+
+    async fn get_from_db(
+        db: &Surreal<C>,
+        channel_login: Self::GetQuery,
+    ) -> Result<Option<Self>, surrealdb::Error> {
+        let mut result = db
+            .query("SELECT * FROM $thing;")
+            .bind((
+                "thing",
+                RecordId::from_table_key(JOINED_CHANNEL_TABLE, channel_login),
+            ))
+            .await?;
+
+        result.take::<Option<Self>>(0)
+    }
+}
+
+impl JoinedChannel {
+    pub fn new(channel_login: &str) -> Self {
+        Self {
+            channel_login: channel_login.to_lowercase(),
+            joined_at: Local::now(),
+        }
+    }
+
+    /// Returns the logins of every channel saved in the database.
+    pub async fn get_all_logins<C: Connection>(
+        db: &Surreal<C>,
+    ) -> Result<Vec<String>, surrealdb::Error> {
+        let channels: Vec<Self> = db
+            .query(format!("SELECT * FROM {JOINED_CHANNEL_TABLE};"))
+            .await?
+            .take(0)?;
+
+        Ok(channels.into_iter().map(|c| c.channel_login).collect())
+    }
+}
+
+/// Returns true if the login looks like one Twitch could have given out:
+/// 3 to 25 lowercase letters, digits and underscores.
+pub fn is_valid_login(channel_login: &str) -> bool {
+    (3..=25).contains(&channel_login.len())
+        && channel_login
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Joins a channel with the IRC client. This will error if the passed channel
+/// login name is malformed.
+pub fn join_channel(
+    client: &MuniBotTwitchIRCClient,
+    channel_login: &str,
+) -> Result<(), TwitchHandlerError> {
+    client
+        .join(channel_login.to_string())
+        .map_err(|e| TwitchHandlerError::Other(e.to_string()))?;
+    info!("twitch: joined channel {}", channel_login);
+    Ok(())
+}
+
+/// Leaves a channel with the IRC client.
+pub fn part_channel(client: &MuniBotTwitchIRCClient, channel_login: &str) {
+    client.part(channel_login.to_string());
+    info!("twitch: left channel {}", channel_login);
+}
+
+/// Starts a task that joins and parts channels as requests come in from other
+/// integrations. Requests are expected to have been persisted by their sender.
+/// Joins and parts are passed on to `eventsub` so the channels' events are
+/// subscribed to or unsubscribed from as well.
+pub fn start_membership_listener(
+    client: MuniBotTwitchIRCClient,
+    mut requests: UnboundedReceiver<ChannelMembershipRequest>,
+    eventsub: UnboundedSender<ChannelMembershipRequest>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(request) = requests.recv().await {
+            match request {
+                ChannelMembershipRequest::Join(channel_login) => {
+                    match join_channel(&client, &channel_login) {
+                        Ok(()) => {
+                            let _ = eventsub.send(ChannelMembershipRequest::Join(channel_login));
+                        }
+                        Err(e) => {
+                            error!("error joining {}'s twitch channel :( {}", channel_login, e)
+                        }
+                    }
+                }
+                ChannelMembershipRequest::Part(channel_login) => {
+                    part_channel(&client, &channel_login);
+                    let _ = eventsub.send(ChannelMembershipRequest::Part(channel_login));
+                }
+            }
+        }
+    })
+}
+
+const COMMANDS: [TwitchCommand; 2] = [TwitchCommand::new("join"), TwitchCommand::new("part")];
+
+/// Handles `!join` and `!part` in munibot's own channel, letting streamers
+/// invite munibot to their channel (or kick it out).
+pub struct ChannelMembershipHandler {
+    db: MuniBotDb,
+    eventsub: Option<UnboundedSender<ChannelMembershipRequest>>,
+}
+
+impl ChannelMembershipHandler {
+    pub fn new(db: MuniBotDb) -> Self {
+        Self { db, eventsub: None }
+    }
+
+    /// Passes `!join` and `!part` on to `eventsub`, so the channels' events
+    /// are subscribed to or unsubscribed from as well.
+    pub fn forward_membership_to(&mut self, eventsub: UnboundedSender<ChannelMembershipRequest>) {
+        self.eventsub = Some(eventsub);
+    }
+
+    fn forward_to_eventsub(&self, request: ChannelMembershipRequest) {
+        if let Some(eventsub) = &self.eventsub {
+            let _ = eventsub.send(request);
+        }
+    }
+
+    /// Returns the logins of every channel joined at runtime.
+    pub async fn saved_channels(&self) -> Result<Vec<String>, surrealdb::Error> {
+        JoinedChannel::get_all_logins(&self.db).await
+    }
+}
+
+#[async_trait]
+impl TwitchMessageHandler for ChannelMembershipHandler {
+    fn twitch_handler_name(&self) -> &'static str {
+        "membership"
+    }
+
+    fn twitch_commands(&self) -> &'static [TwitchCommand] {
+        &COMMANDS
+    }
+
+    async fn handle_twitch_message(
+        &mut self,
+        message: &ServerMessage,
+        client: &MuniBotTwitchIRCClient,
+        _agent: &TwitchAgent,
+        config: &Config,
+    ) -> Result<bool, TwitchHandlerError> {
+        let ServerMessage::Privmsg(m) = message else {
+            return Ok(false);
+        };
+
+        // these commands only work in munibot's own channel
+        if m.channel_login != config.twitch.twitch_user {
+            return Ok(false);
+        }
+
+        let command = m.message_text.trim();
+        let sender_login = m.sender.login.to_lowercase();
+
+        if command == "!join" {
+            if sender_login == config.twitch.twitch_user {
+                self.send_twitch_message(client, &m.channel_login, "i'm already here, silly")
+                    .await?;
+            } else {
+                let joined = JoinedChannel::new(&sender_login);
+                joined.upsert_in_db(&self.db, joined.clone()).await?;
+                join_channel(client, &sender_login)?;
+                self.forward_to_eventsub(ChannelMembershipRequest::Join(sender_login.clone()));
+
+                self.send_twitch_message(
+                    client,
+                    &m.channel_login,
+                    &format!(
+                        "okay {}! i'm on my way to your channel ^w^ type !part here if you ever want me to leave.",
+                        m.sender.name
+                    ),
+                )
+                .await?;
+            }
+
+            Ok(true)
+        } else if command == "!part" {
+            if sender_login == config.twitch.twitch_user {
+                self.send_twitch_message(client, &m.channel_login, "i can't leave my own channel!")
+                    .await?;
+            } else {
+                JoinedChannel::new(&sender_login)
+                    .delete_from_db(&self.db)
+                    .await?;
+                part_channel(client, &sender_login);
+                self.forward_to_eventsub(ChannelMembershipRequest::Part(sender_login.clone()));
+
+                let mut reply = format!(
+                    "okay {}, i've left your channel. it was fun while it lasted! <3",
+                    m.sender.name
+                );
+                if config.twitch.initial_channels.contains(&sender_login) {
+                    reply.push_str(" (your channel is in my config file though, so i'll be back next time i restart.)");
+                }
+                self.send_twitch_message(client, &m.channel_login, &reply)
+                    .await?;
+            }
+
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_valid_login;
+
+    #[test]
+    fn test_is_valid_login() {
+        assert!(is_valid_login("muni_corn"));
+        assert!(is_valid_login("abc"));
+        assert!(!is_valid_login("ab"));
+        assert!(!is_valid_login("Muni_Corn"));
+        assert!(!is_valid_login("muni corn"));
+        assert!(!is_valid_login("twitch.tv/muni_corn"));
+        assert!(!is_valid_login(&"a".repeat(26)));
+    }
+}
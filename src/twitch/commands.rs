@@ -0,0 +1,227 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use twitch_irc::message::PrivmsgMessage;
+
+use super::utils::ChatRole;
+
+/// How long a command has to wait before it can be used again, either by
+/// anyone in the channel or by the same chatter.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Cooldown {
+    pub global: Duration,
+    pub per_user: Duration,
+}
+
+impl Cooldown {
+    pub const NONE: Self = Self {
+        global: Duration::ZERO,
+        per_user: Duration::ZERO,
+    };
+
+    pub const fn global(global: Duration) -> Self {
+        Self {
+            global,
+            per_user: Duration::ZERO,
+        }
+    }
+}
+
+/// A chat command declared by a [`TwitchMessageHandler`]. Before a handler
+/// sees a message using one of its commands, the bot checks that the sender
+/// has the required role and that the command is off cooldown. Messages that
+/// don't pass are silently ignored.
+///
+/// [`TwitchMessageHandler`]: super::handler::TwitchMessageHandler
+#[derive(Clone, Copy, Debug)]
+pub struct TwitchCommand {
+    /// The name of the command, without the `!`. Aliases share the command's
+    /// cooldowns.
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub role: ChatRole,
+    pub cooldown: Cooldown,
+}
+
+impl TwitchCommand {
+    /// A command anyone can use at any time.
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            aliases: &[],
+            role: ChatRole::Everyone,
+            cooldown: Cooldown::NONE,
+        }
+    }
+
+    pub const fn aliases(mut self, aliases: &'static [&'static str]) -> Self {
+        self.aliases = aliases;
+        self
+    }
+
+    pub const fn role(mut self, role: ChatRole) -> Self {
+        self.role = role;
+        self
+    }
+
+    pub const fn global_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown.global = cooldown;
+        self
+    }
+
+    pub const fn user_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown.per_user = cooldown;
+        self
+    }
+
+    /// Returns true if the message starts with this command or one of its
+    /// aliases, like `!so someone`.
+    pub fn matches(&self, text: &str) -> bool {
+        text.split_whitespace()
+            .next()
+            .and_then(|word| word.strip_prefix('!'))
+            .is_some_and(|word| {
+                std::iter::once(&self.name)
+                    .chain(self.aliases)
+                    .any(|name| name.eq_ignore_ascii_case(word))
+            })
+    }
+
+    /// Returns true if the sender of the message is allowed to use this
+    /// command.
+    pub fn allows(&self, msg: &PrivmsgMessage) -> bool {
+        ChatRole::of(msg) >= self.role
+    }
+}
+
+/// Remembers when commands come off cooldown in each channel, and for whom.
+/// Only commands with a cooldown are remembered, and only until it's over.
+#[derive(Default)]
+pub struct CommandCooldowns {
+    ready_at: HashMap<(String, String), Instant>,
+    ready_at_for_user: HashMap<(String, String, String), Instant>,
+}
+
+impl CommandCooldowns {
+    /// Returns true if the command is off cooldown for the given user.
+    pub fn is_ready(&self, channel: &str, command: &str, user: &str) -> bool {
+        self.is_ready_at(channel, command, user, Instant::now())
+    }
+
+    /// Starts the command's cooldowns over, after it was used by the given
+    /// user.
+    pub fn start(&mut self, channel: &str, command: &str, user: &str, cooldown: Cooldown) {
+        self.start_at(channel, command, user, cooldown, Instant::now());
+    }
+
+    fn is_ready_at(&self, channel: &str, command: &str, user: &str, now: Instant) -> bool {
+        let elapsed = |ready_at: Option<&Instant>| ready_at.is_none_or(|ready_at| now >= *ready_at);
+
+        elapsed(
+            self.ready_at
+                .get(&(channel.to_string(), command.to_string())),
+        ) && elapsed(self.ready_at_for_user.get(&(
+            channel.to_string(),
+            command.to_string(),
+            user.to_string(),
+        )))
+    }
+
+    fn start_at(
+        &mut self,
+        channel: &str,
+        command: &str,
+        user: &str,
+        cooldown: Cooldown,
+        now: Instant,
+    ) {
+        // forget cooldowns that are already over, so these don't grow forever
+        self.ready_at.retain(|_, ready_at| *ready_at > now);
+        self.ready_at_for_user.retain(|_, ready_at| *ready_at > now);
+
+        if !cooldown.global.is_zero() {
+            self.ready_at.insert(
+                (channel.to_string(), command.to_string()),
+                now + cooldown.global,
+            );
+        }
+        if !cooldown.per_user.is_zero() {
+            self.ready_at_for_user.insert(
+                (channel.to_string(), command.to_string(), user.to_string()),
+                now + cooldown.per_user,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{CommandCooldowns, Cooldown, TwitchCommand};
+
+    const SHOUTOUT: TwitchCommand = TwitchCommand::new("so").aliases(&["shoutout"]);
+
+    #[test]
+    fn test_matches() {
+        assert!(SHOUTOUT.matches("!so muni_corn"));
+        assert!(SHOUTOUT.matches("!SHOUTOUT muni_corn"));
+        assert!(SHOUTOUT.matches("!so"));
+        assert!(!SHOUTOUT.matches("!soup"));
+        assert!(!SHOUTOUT.matches("so muni_corn"));
+        assert!(!SHOUTOUT.matches(""));
+    }
+
+    #[test]
+    fn test_cooldowns() {
+        let cooldown = Cooldown {
+            global: Duration::from_secs(10),
+            per_user: Duration::from_secs(60),
+        };
+        let mut cooldowns = CommandCooldowns::default();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(cooldowns.is_ready_at("muni_corn", "so", "linokii", start));
+        cooldowns.start_at("muni_corn", "so", "linokii", cooldown, start);
+
+        // nobody can use it until the global cooldown is up
+        assert!(!cooldowns.is_ready_at("muni_corn", "so", "someone", at(5)));
+        assert!(cooldowns.is_ready_at("muni_corn", "so", "someone", at(10)));
+
+        // the same user has to wait longer
+        assert!(!cooldowns.is_ready_at("muni_corn", "so", "linokii", at(30)));
+        assert!(cooldowns.is_ready_at("muni_corn", "so", "linokii", at(60)));
+
+        // other channels and commands aren't affected
+        assert!(cooldowns.is_ready_at("someone", "so", "linokii", at(1)));
+        assert!(cooldowns.is_ready_at("muni_corn", "mso", "linokii", at(1)));
+    }
+
+    #[test]
+    fn test_cooldowns_are_forgotten() {
+        let mut cooldowns = CommandCooldowns::default();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // commands without cooldowns aren't remembered at all
+        cooldowns.start_at("muni_corn", "hug", "linokii", Cooldown::NONE, start);
+        assert!(cooldowns.ready_at.is_empty());
+        assert!(cooldowns.ready_at_for_user.is_empty());
+
+        cooldowns.start_at(
+            "muni_corn",
+            "so",
+            "linokii",
+            Cooldown::global(Duration::from_secs(10)),
+            start,
+        );
+        assert_eq!(cooldowns.ready_at.len(), 1);
+
+        // once it's over, it's dropped the next time anything is used
+        cooldowns.start_at("muni_corn", "hug", "linokii", Cooldown::NONE, at(10));
+        assert!(cooldowns.ready_at.is_empty());
+    }
+}
@@ -0,0 +1,436 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use futures_util::StreamExt;
+use log::{error, info, warn};
+use tokio::{
+    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    task::JoinHandle,
+    time::{sleep_until, Instant},
+};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use twitch_api::{
+    eventsub::{
+        channel::{
+            ChannelCheerV1, ChannelFollowV2, ChannelPointsCustomRewardRedemptionAddV1,
+            ChannelRaidV1, ChannelSubscribeV1,
+        },
+        Event, EventsubWebsocketData, Message as EventMessage, Payload,
+    },
+    types::{EventSubId, SubscriptionTier, UserId},
+};
+
+use super::{agent::TwitchAgent, channels::ChannelMembershipRequest};
+
+const EVENTSUB_URL: &str = "wss://eventsub.wss.twitch.tv/ws";
+
+/// How long to wait before reconnecting after the connection drops.
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// How long to wait for a message before deciding the connection is dead, if
+/// Twitch doesn't tell us.
+const DEFAULT_KEEPALIVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Extra time allowed on top of Twitch's keepalive timeout, for network lag.
+const KEEPALIVE_GRACE: Duration = Duration::from_secs(5);
+
+/// Something that happened in a Twitch channel, as told by EventSub.
+#[derive(Clone, Debug)]
+pub enum TwitchEvent {
+    Follow {
+        channel_login: String,
+        user_login: String,
+        user_name: String,
+    },
+    Subscribe {
+        channel_login: String,
+        user_login: String,
+        user_name: String,
+        tier: SubscriptionTier,
+        is_gift: bool,
+    },
+    Cheer {
+        channel_login: String,
+        /// `None` if the cheer was anonymous.
+        user_login: Option<String>,
+        user_name: Option<String>,
+        bits: i64,
+        message: String,
+    },
+    /// Another broadcaster raided the channel.
+    Raid {
+        channel_login: String,
+        from_id: UserId,
+        from_login: String,
+        from_name: String,
+        viewers: i64,
+    },
+    Redemption {
+        channel_login: String,
+        redemption_id: String,
+        reward_id: String,
+        reward_title: String,
+        user_login: String,
+        user_name: String,
+        /// What the viewer typed, if the reward asks for input.
+        user_input: String,
+    },
+}
+
+impl TwitchEvent {
+    /// The login of the channel the event happened in.
+    pub fn channel_login(&self) -> &str {
+        match self {
+            TwitchEvent::Follow { channel_login, .. }
+            | TwitchEvent::Subscribe { channel_login, .. }
+            | TwitchEvent::Cheer { channel_login, .. }
+            | TwitchEvent::Raid { channel_login, .. }
+            | TwitchEvent::Redemption { channel_login, .. } => channel_login,
+        }
+    }
+
+    /// Converts an EventSub notification into an event, if it's one munibot
+    /// cares about.
+    fn from_eventsub(event: Event) -> Option<Self> {
+        match event {
+            Event::ChannelFollowV2(Payload {
+                message: EventMessage::Notification(n),
+                ..
+            }) => Some(TwitchEvent::Follow {
+                channel_login: n.broadcaster_user_login.to_string(),
+                user_login: n.user_login.to_string(),
+                user_name: n.user_name.to_string(),
+            }),
+            Event::ChannelSubscribeV1(Payload {
+                message: EventMessage::Notification(n),
+                ..
+            }) => Some(TwitchEvent::Subscribe {
+                channel_login: n.broadcaster_user_login.to_string(),
+                user_login: n.user_login.to_string(),
+                user_name: n.user_name.to_string(),
+                tier: n.tier,
+                is_gift: n.is_gift,
+            }),
+            Event::ChannelCheerV1(Payload {
+                message: EventMessage::Notification(n),
+                ..
+            }) => Some(TwitchEvent::Cheer {
+                channel_login: n.broadcaster_user_login.to_string(),
+                user_login: n.user_login.map(|login| login.to_string()),
+                user_name: n.user_name.map(|name| name.to_string()),
+                bits: n.bits,
+                message: n.message,
+            }),
+            Event::ChannelRaidV1(Payload {
+                message: EventMessage::Notification(n),
+                ..
+            }) => Some(TwitchEvent::Raid {
+                channel_login: n.to_broadcaster_user_login.to_string(),
+                from_id: n.from_broadcaster_user_id,
+                from_login: n.from_broadcaster_user_login.to_string(),
+                from_name: n.from_broadcaster_user_name.to_string(),
+                viewers: n.viewers,
+            }),
+            Event::ChannelPointsCustomRewardRedemptionAddV1(Payload {
+                message: EventMessage::Notification(n),
+                ..
+            }) => Some(TwitchEvent::Redemption {
+                channel_login: n.broadcaster_user_login.to_string(),
+                redemption_id: n.id.to_string(),
+                reward_id: n.reward.id.to_string(),
+                reward_title: n.reward.title,
+                user_login: n.user_login.to_string(),
+                user_name: n.user_name.to_string(),
+                user_input: n.user_input,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// The channels events are wanted in, with the EventSub subscriptions made for
+/// each one on the current connection.
+type Subscriptions = HashMap<UserId, Vec<EventSubId>>;
+
+/// Connects to EventSub and subscribes to events in the given channels,
+/// sending them to `events` as they come in. Channels joined or left later are
+/// sent to `membership` by login, and subscribed to or unsubscribed from on the
+/// live connection. Reconnects (and resubscribes) whenever the connection
+/// drops, until `events` is closed.
+///
+/// Twitch only sends some events to channels that have authorized munibot
+/// (subs, cheers and redemptions need the broadcaster's own token, and follows
+/// need munibot to be a moderator), so subscriptions that fail are skipped.
+pub fn start_eventsub_client(
+    agent: Arc<TwitchAgent<'static>>,
+    broadcaster_ids: Vec<UserId>,
+    mut membership: UnboundedReceiver<ChannelMembershipRequest>,
+    events: UnboundedSender<TwitchEvent>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut subscriptions: Subscriptions = broadcaster_ids
+            .into_iter()
+            .map(|broadcaster_id| (broadcaster_id, Vec::new()))
+            .collect();
+        let mut reconnect_url = None;
+        loop {
+            // subscriptions carry over when twitch asks us to move to a new
+            // url, so we only subscribe on fresh connections
+            let (url, subscribe) = match reconnect_url.take() {
+                Some(url) => (url, false),
+                None => (EVENTSUB_URL.to_string(), true),
+            };
+
+            match run_session(
+                &agent,
+                &mut subscriptions,
+                &mut membership,
+                &events,
+                &url,
+                subscribe,
+            )
+            .await
+            {
+                Ok(Some(url)) => {
+                    info!("twitch asked eventsub to reconnect. moving over");
+                    reconnect_url = Some(url);
+                    continue;
+                }
+                Ok(None) => warn!("eventsub connection closed"),
+                Err(e) => error!("eventsub connection failed :( {e}"),
+            }
+
+            if events.is_closed() {
+                break;
+            }
+            info!("reconnecting to eventsub in {}s", RECONNECT_DELAY.as_secs());
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    })
+}
+
+/// Runs a single EventSub connection. Returns the url to reconnect to if
+/// Twitch asks us to move, or `None` if the connection was closed.
+async fn run_session(
+    agent: &TwitchAgent<'static>,
+    subscriptions: &mut Subscriptions,
+    membership: &mut UnboundedReceiver<ChannelMembershipRequest>,
+    events: &UnboundedSender<TwitchEvent>,
+    url: &str,
+    subscribe: bool,
+) -> anyhow::Result<Option<String>> {
+    // subscriptions made on an old connection went away with it
+    if subscribe {
+        subscriptions.values_mut().for_each(Vec::clear);
+    }
+
+    let (mut socket, _) = connect_async(url).await?;
+    let mut keepalive_timeout = DEFAULT_KEEPALIVE_TIMEOUT + KEEPALIVE_GRACE;
+    let mut deadline = Instant::now() + keepalive_timeout;
+    let mut session_id: Option<String> = None;
+
+    // channels joined before twitch welcomes a reconnected session, which
+    // won't have carried over from the old one
+    let mut unsubscribed = Vec::new();
+
+    loop {
+        // frames already waiting in the socket win over the deadline, since
+        // subscribing can take long enough for it to pass while they wait
+        let frame = tokio::select! {
+            biased;
+            frame = socket.next() => match frame {
+                Some(frame) => frame?,
+                None => return Ok(None),
+            },
+            _ = sleep_until(deadline) => anyhow::bail!("no keepalive from twitch in time"),
+            Some(request) = membership.recv() => {
+                match request {
+                    ChannelMembershipRequest::Join(channel_login) => {
+                        let Some(broadcaster_id) = broadcaster_id(agent, &channel_login).await
+                        else {
+                            continue;
+                        };
+                        if subscriptions.contains_key(&broadcaster_id) {
+                            continue;
+                        }
+
+                        match &session_id {
+                            Some(session_id) => {
+                                subscribe_all(agent, subscriptions, &[broadcaster_id], session_id)
+                                    .await;
+                            }
+                            // fresh sessions subscribe to everything once
+                            // welcomed
+                            None if !subscribe => {
+                                subscriptions.insert(broadcaster_id.clone(), Vec::new());
+                                unsubscribed.push(broadcaster_id);
+                            }
+                            None => {
+                                subscriptions.insert(broadcaster_id, Vec::new());
+                            }
+                        }
+                    }
+                    ChannelMembershipRequest::Part(channel_login) => {
+                        let Some(broadcaster_id) = broadcaster_id(agent, &channel_login).await
+                        else {
+                            continue;
+                        };
+                        unsubscribed.retain(|id| *id != broadcaster_id);
+                        if let Some(subscription_ids) = subscriptions.remove(&broadcaster_id) {
+                            unsubscribe_all(agent, &broadcaster_id, subscription_ids).await;
+                        }
+                    }
+                }
+                deadline = Instant::now() + keepalive_timeout;
+                continue;
+            }
+        };
+        deadline = Instant::now() + keepalive_timeout;
+        let text = match frame {
+            Message::Text(text) => text,
+            Message::Close(_) => return Ok(None),
+            _ => continue,
+        };
+
+        match Event::parse_websocket(&text)? {
+            EventsubWebsocketData::Welcome { payload, .. } => {
+                if let Some(secs) = payload.session.keepalive_timeout_seconds {
+                    keepalive_timeout = Duration::from_secs(secs as u64) + KEEPALIVE_GRACE;
+                }
+                if subscribe {
+                    let broadcaster_ids: Vec<UserId> = subscriptions.keys().cloned().collect();
+                    subscribe_all(agent, subscriptions, &broadcaster_ids, &payload.session.id)
+                        .await;
+                } else if !unsubscribed.is_empty() {
+                    subscribe_all(agent, subscriptions, &unsubscribed, &payload.session.id).await;
+                    unsubscribed.clear();
+                }
+                deadline = Instant::now() + keepalive_timeout;
+                session_id = Some(payload.session.id.to_string());
+            }
+            EventsubWebsocketData::Notification { payload, .. } => {
+                if let Some(event) = TwitchEvent::from_eventsub(payload)
+                    && events.send(event).is_err()
+                {
+                    return Ok(None);
+                }
+            }
+            EventsubWebsocketData::Revocation { metadata, .. } => warn!(
+                "twitch revoked an eventsub subscription to {:?}",
+                metadata.subscription_type
+            ),
+            EventsubWebsocketData::Reconnect { payload, .. } => {
+                if let Some(url) = payload.session.reconnect_url {
+                    return Ok(Some(url.into_owned()));
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Looks up the user ID of a channel joined or left at runtime.
+async fn broadcaster_id(agent: &TwitchAgent<'static>, channel_login: &str) -> Option<UserId> {
+    match agent.get_user_from_login(channel_login).await {
+        Ok(Some(user)) => Some(user.id),
+        Ok(None) => {
+            warn!("twitch channel {channel_login} doesn't seem to exist");
+            None
+        }
+        Err(e) => {
+            error!("couldn't look up twitch channel {channel_login} for eventsub :( {e}");
+            None
+        }
+    }
+}
+
+/// Subscribes the session to every event munibot cares about, in every given
+/// channel, and remembers the subscriptions so they can be deleted later.
+async fn subscribe_all(
+    agent: &TwitchAgent<'static>,
+    subscriptions: &mut Subscriptions,
+    broadcaster_ids: &[UserId],
+    session_id: &str,
+) {
+    let bot_id = agent.get_bot_id().clone();
+    let mut subscribed = 0;
+    for broadcaster_id in broadcaster_ids {
+        let results = [
+            (
+                "follows",
+                agent
+                    .subscribe_to_event(
+                        ChannelFollowV2::new(broadcaster_id.clone(), bot_id.clone()),
+                        session_id,
+                    )
+                    .await,
+            ),
+            (
+                "subs",
+                agent
+                    .subscribe_to_event(
+                        ChannelSubscribeV1::broadcaster_user_id(broadcaster_id.clone()),
+                        session_id,
+                    )
+                    .await,
+            ),
+            (
+                "cheers",
+                agent
+                    .subscribe_to_event(
+                        ChannelCheerV1::broadcaster_user_id(broadcaster_id.clone()),
+                        session_id,
+                    )
+                    .await,
+            ),
+            (
+                "raids",
+                agent
+                    .subscribe_to_event(
+                        ChannelRaidV1::to_broadcaster_user_id(broadcaster_id.clone()),
+                        session_id,
+                    )
+                    .await,
+            ),
+            (
+                "redemptions",
+                agent
+                    .subscribe_to_event(
+                        ChannelPointsCustomRewardRedemptionAddV1::broadcaster_user_id(
+                            broadcaster_id.clone(),
+                        ),
+                        session_id,
+                    )
+                    .await,
+            ),
+        ];
+
+        let subscription_ids = subscriptions.entry(broadcaster_id.clone()).or_default();
+        for (name, result) in results {
+            match result {
+                Ok(subscription_id) => subscription_ids.push(subscription_id),
+                Err(e) => {
+                    warn!("couldn't subscribe to {name} for broadcaster {broadcaster_id}: {e}")
+                }
+            }
+        }
+        subscribed += subscription_ids.len();
+    }
+    info!(
+        "subscribed to {subscribed} eventsub events across {} channels",
+        broadcaster_ids.len()
+    );
+}
+
+/// Deletes the subscriptions made for a channel munibot left.
+async fn unsubscribe_all(
+    agent: &TwitchAgent<'static>,
+    broadcaster_id: &UserId,
+    subscription_ids: Vec<EventSubId>,
+) {
+    for subscription_id in subscription_ids {
+        if let Err(e) = agent.unsubscribe_from_event(&subscription_id).await {
+            warn!("couldn't delete eventsub subscription {subscription_id} for broadcaster {broadcaster_id}: {e}");
+        }
+    }
+    info!("unsubscribed from eventsub events for broadcaster {broadcaster_id}");
+}
@@ -3,7 +3,11 @@ use std::fmt::Display;
 use async_trait::async_trait;
 use twitch_irc::message::ServerMessage;
 
-use super::agent::{TwitchAgent, TwitchAgentError};
+use super::{
+    agent::{TwitchAgent, TwitchAgentError},
+    commands::TwitchCommand,
+    eventsub::TwitchEvent,
+};
 use crate::{
     config::Config,
     twitch::bot::{MuniBotTwitchIRCClient, MuniBotTwitchIRCError},
@@ -11,6 +15,17 @@ use crate::{
 
 #[async_trait]
 pub trait TwitchMessageHandler: Send {
+    /// The name of this handler, used to enable or disable it per channel in
+    /// the configuration.
+    fn twitch_handler_name(&self) -> &'static str;
+
+    /// The chat commands this handler responds to. Messages using one of them
+    /// only reach the handler if the sender has the command's role and the
+    /// command is off cooldown. Custom commands can't take their names.
+    fn twitch_commands(&self) -> &'static [TwitchCommand] {
+        &[]
+    }
+
     async fn send_twitch_message(
         &mut self,
         irc_client: &MuniBotTwitchIRCClient,
@@ -35,6 +50,25 @@ pub trait TwitchMessageHandler: Send {
     ) -> Result<bool, TwitchHandlerError>;
 }
 
+#[async_trait]
+pub trait TwitchEventHandler: Send {
+    /// The name of this handler, used to enable or disable it per channel in
+    /// the configuration.
+    fn twitch_event_handler_name(&self) -> &'static str;
+
+    /// Handle an EventSub event, like a follow or a raid. Returns `true` if
+    /// something was done to handle the event, or `false` if the event was
+    /// ignored (or if the event is allowed to also be handled by other
+    /// handlers).
+    async fn handle_twitch_event(
+        &mut self,
+        event: &TwitchEvent,
+        irc_client: &MuniBotTwitchIRCClient,
+        agent: &TwitchAgent,
+        config: &Config,
+    ) -> Result<bool, TwitchHandlerError>;
+}
+
 #[derive(Debug)]
 pub enum TwitchHandlerError {
     SendMessage(MuniBotTwitchIRCError),
@@ -1,51 +1,384 @@
+use std::{sync::Arc, time::Duration};
+
 use async_trait::async_trait;
-use twitch_irc::login::{StaticLoginCredentials, TokenStorage, UserAccessToken};
-use twitch_oauth2::{AccessToken, UserToken};
+use log::{info, warn};
+use rand::{distributions::Alphanumeric, Rng};
+use surrealdb::RecordId;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+    sync::Mutex,
+};
+use twitch_api::types::UserId;
+use twitch_irc::login::{
+    GetAccessTokenResponse, LoginCredentials, RefreshingLoginCredentials, TokenStorage,
+    UserAccessToken,
+};
+use twitch_oauth2::{AccessToken, Scope, TwitchToken, UserToken};
+use url::Url;
+
+use super::{agent::TwitchAgentError, get_auth_code_url, REDIRECT_URI, SCOPE};
+use crate::db::MuniBotDb;
+
+const TWITCH_TOKEN_TABLE: &str = "twitch_token";
+
+/// Helix calls get a fresh access token once the current one is this close to
+/// expiring.
+const REFRESH_MARGIN: Duration = Duration::from_mins(10);
+
+pub type MuniBotTwitchCredentials = RefreshingLoginCredentials<TwitchTokenStorage>;
 
-#[derive(Debug)]
+/// The client ID and secret of munibot's Twitch application.
+#[derive(Clone, Debug)]
+pub struct TwitchAppCredentials {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+impl TwitchAppCredentials {
+    /// Reads the credentials from the `TWITCH_CLIENT_ID` and
+    /// `TWITCH_CLIENT_SECRET` environment variables.
+    pub fn from_env() -> Result<Self, std::env::VarError> {
+        Ok(Self {
+            client_id: std::env::var("TWITCH_CLIENT_ID")?,
+            client_secret: std::env::var("TWITCH_CLIENT_SECRET")?,
+        })
+    }
+}
+
+/// Keeps munibot's Twitch tokens in the database, so they survive restarts and
+/// refreshed tokens are never lost.
+#[derive(Clone, Debug)]
 pub struct TwitchTokenStorage {
-    pub user_access_token: UserAccessToken,
+    db: MuniBotDb,
+    login: String,
+}
+
+impl TwitchTokenStorage {
+    pub fn new(db: MuniBotDb, login: &str) -> Self {
+        Self {
+            db,
+            login: login.to_lowercase(),
+        }
+    }
+
+    fn record_id(&self) -> RecordId {
+        RecordId::from_table_key(TWITCH_TOKEN_TABLE, self.login.clone())
+    }
+
+    /// Returns the saved token, if munibot has been authorized before.
+    pub async fn get_token(&self) -> Result<Option<UserAccessToken>, surrealdb::Error> {
+        self.db
+            .query("SELECT VALUE token FROM $thing;")
+            .bind(("thing", self.record_id()))
+            .await?
+            .take(0)
+    }
+
+    pub async fn save_token(&self, token: &UserAccessToken) -> Result<(), surrealdb::Error> {
+        self.db
+            .query("UPSERT $thing CONTENT { token: $user_token };")
+            .bind(("thing", self.record_id()))
+            .bind(("user_token", token.clone()))
+            .await?
+            .check()?;
+        Ok(())
+    }
 }
 
 #[async_trait]
 impl TokenStorage for TwitchTokenStorage {
-    type LoadError = std::io::Error;
-    type UpdateError = std::io::Error;
+    type LoadError = TwitchTokenError;
+    type UpdateError = TwitchTokenError;
 
     async fn load_token(&mut self) -> Result<UserAccessToken, Self::LoadError> {
-        Ok(self.user_access_token.clone())
+        self.get_token().await?.ok_or(TwitchTokenError::Missing)
     }
 
     async fn update_token(&mut self, token: &UserAccessToken) -> Result<(), Self::UpdateError> {
-        token.clone_into(&mut self.user_access_token);
+        self.save_token(token).await?;
+        info!("twitch token refreshed");
         Ok(())
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
+pub enum TwitchTokenError {
+    #[error("database error while handling twitch tokens: {0}")]
+    Db(#[from] surrealdb::Error),
+
+    #[error("no twitch token has been saved yet")]
+    Missing,
+}
+
+/// Hands out valid Twitch tokens for both IRC and Helix. Tokens are refreshed
+/// (and saved) automatically as they near expiry, so munibot never needs a
+/// restart to pick up new ones.
+#[derive(Clone, Debug)]
 pub struct TwitchAuth {
-    access_token: UserToken,
-    login_credentials: StaticLoginCredentials,
+    credentials: MuniBotTwitchCredentials,
+    http_client: reqwest::Client,
+    bot_id: UserId,
+    user_token: Arc<Mutex<UserToken>>,
 }
 
 impl TwitchAuth {
-    pub async fn new(login_name: &str, token: &str) -> anyhow::Result<Self> {
+    pub async fn new(credentials: MuniBotTwitchCredentials) -> Result<Self, TwitchAgentError> {
         let http_client = reqwest::Client::new();
-        let access_token = UserToken::from_token(&http_client, AccessToken::from(token)).await?;
-        let login_credentials =
-            StaticLoginCredentials::new(login_name.to_string(), Some(token.to_string()));
+        let user_token = Self::validate(&credentials, &http_client).await?;
 
         Ok(Self {
-            access_token,
-            login_credentials,
+            credentials,
+            http_client,
+            bot_id: user_token.user_id.clone(),
+            user_token: Arc::new(Mutex::new(user_token)),
         })
     }
 
-    pub fn get_user_token(&self) -> &UserToken {
-        &self.access_token
+    /// Returns a valid user token for Helix requests, refreshing it first if
+    /// it's about to expire.
+    pub async fn get_user_token(&self) -> Result<UserToken, TwitchAgentError> {
+        let mut user_token = self.user_token.lock().await;
+        if user_token.expires_in() < REFRESH_MARGIN {
+            *user_token = Self::validate(&self.credentials, &self.http_client).await?;
+        }
+        Ok(user_token.clone())
+    }
+
+    /// The ID of munibot's own Twitch account.
+    pub fn get_bot_id(&self) -> &UserId {
+        &self.bot_id
+    }
+
+    pub fn get_login_credentials(&self) -> &MuniBotTwitchCredentials {
+        &self.credentials
+    }
+
+    /// Gets the current access token from the credentials (which refresh it if
+    /// needed) and validates it with Twitch.
+    async fn validate(
+        credentials: &MuniBotTwitchCredentials,
+        http_client: &reqwest::Client,
+    ) -> Result<UserToken, TwitchAgentError> {
+        let token = credentials
+            .get_credentials()
+            .await
+            .map_err(|e| TwitchAgentError::CredentialsError(e.to_string()))?
+            .token
+            .ok_or(TwitchAgentError::MissingCredentials)?;
+
+        UserToken::from_token(http_client, AccessToken::from(token))
+            .await
+            .map_err(|e| TwitchAgentError::CredentialsError(e.to_string()))
+    }
+}
+
+/// Makes sure munibot has a saved Twitch token with every scope it needs. If
+/// it doesn't, this logs a link for munibot's owner to visit and waits for
+/// Twitch to send them back to [`REDIRECT_URI`] with an authorization code,
+/// which is then traded for tokens.
+pub async fn ensure_token(
+    storage: &TwitchTokenStorage,
+    app: &TwitchAppCredentials,
+) -> anyhow::Result<()> {
+    if storage.get_token().await?.is_some() {
+        // tokens keep the scopes they were authorized with, so a token saved
+        // before munibot needed a new scope has to be authorized again
+        let credentials = MuniBotTwitchCredentials::init_with_username(
+            Some(storage.login.clone()),
+            app.client_id.clone(),
+            app.client_secret.clone(),
+            storage.clone(),
+        );
+        let token = TwitchAuth::validate(&credentials, &reqwest::Client::new()).await?;
+        let missing = missing_scopes(token.scopes());
+        if missing.is_empty() {
+            return Ok(());
+        }
+        warn!(
+            "munibot's twitch token is missing scopes it needs now: {}",
+            missing.join(", ")
+        );
+    }
+
+    let state: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(24)
+        .map(char::from)
+        .collect();
+    let url = get_auth_code_url(&app.client_id, &state);
+    warn!("munibot isn't authorized on twitch yet! visit {url} to authorize it");
+
+    let code = wait_for_auth_code(&state).await?;
+    let response = reqwest::Client::new()
+        .post("https://id.twitch.tv/oauth2/token")
+        .form(&[
+            ("client_id", app.client_id.as_str()),
+            ("client_secret", app.client_secret.as_str()),
+            ("code", code.as_str()),
+            ("grant_type", "authorization_code"),
+            ("redirect_uri", REDIRECT_URI),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let response: GetAccessTokenResponse = serde_json::from_str(&response)?;
+
+    storage.save_token(&response.into()).await?;
+    info!("twitch authorization complete! tokens will refresh on their own from now on");
+    Ok(())
+}
+
+/// Returns the scopes munibot asks for that weren't granted.
+fn missing_scopes(granted: &[Scope]) -> Vec<String> {
+    SCOPE
+        .iter()
+        .filter(|scope| !granted.iter().any(|g| g.as_str() == scope.as_str()))
+        .map(|scope| scope.to_string())
+        .collect()
+}
+
+/// Listens on [`REDIRECT_URI`] until Twitch redirects the owner back with an
+/// authorization code.
+async fn wait_for_auth_code(state: &str) -> anyhow::Result<String> {
+    let redirect = Url::parse(REDIRECT_URI)?;
+    let host = redirect.host_str().unwrap_or("localhost");
+    let port = redirect.port_or_known_default().unwrap_or(80);
+    let listener = TcpListener::bind((host, port)).await?;
+
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let mut buf = vec![0; 4096];
+        let len = stream.read(&mut buf).await?;
+        let request = String::from_utf8_lossy(&buf[..len]);
+
+        let result = parse_auth_redirect(&request, state);
+        let (status, body) = match &result {
+            Ok(_) => (
+                "200 OK",
+                "munibot is authorized! you can close this tab now.",
+            ),
+            Err(e) => ("400 Bad Request", e.as_str()),
+        };
+        let response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        if let Err(e) = stream.write_all(response.as_bytes()).await {
+            warn!("couldn't respond to twitch authorization redirect: {e}");
+        }
+
+        match result {
+            Ok(code) => return Ok(code),
+            Err(e) => warn!("ignoring bad twitch authorization redirect: {e}"),
+        }
+    }
+}
+
+/// Pulls the authorization code out of the raw HTTP request Twitch redirected
+/// to, making sure the state matches the one we sent.
+fn parse_auth_redirect(request: &str, state: &str) -> Result<String, String> {
+    let path = request
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .ok_or("that doesn't look like an http request")?;
+
+    let redirect = Url::parse(REDIRECT_URI).map_err(|e| e.to_string())?;
+    let url = redirect.join(path).map_err(|e| e.to_string())?;
+    if url.path() != redirect.path() {
+        return Err(format!("nothing lives at {}", url.path()));
+    }
+
+    let query = |key: &str| {
+        url.query_pairs()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.into_owned())
+    };
+    if let Some(error) = query("error") {
+        let description = query("error_description").unwrap_or(error);
+        return Err(format!("twitch said no: {description}"));
+    }
+    if query("state").as_deref() != Some(state) {
+        return Err("the state in the redirect doesn't match. try the link again?".to_string());
     }
+    query("code").ok_or_else(|| "twitch didn't send an authorization code".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use twitch_irc::login::UserAccessToken;
+
+    use twitch_oauth2::Scope;
+
+    use super::{missing_scopes, parse_auth_redirect, TwitchTokenStorage, SCOPE};
+    use crate::db::test_db;
+
+    #[test]
+    fn test_parse_auth_redirect() {
+        let request = "GET /twitch?code=abc123&scope=chat%3Aread&state=xyz HTTP/1.1\r\nHost: localhost:6864\r\n\r\n";
+        assert_eq!(
+            parse_auth_redirect(request, "xyz"),
+            Ok("abc123".to_string())
+        );
+        assert!(parse_auth_redirect(request, "other").is_err());
+
+        let denied =
+            "GET /twitch?error=access_denied&error_description=nope&state=xyz HTTP/1.1\r\n\r\n";
+        assert_eq!(
+            parse_auth_redirect(denied, "xyz"),
+            Err("twitch said no: nope".to_string())
+        );
+
+        let favicon = "GET /favicon.ico HTTP/1.1\r\n\r\n";
+        assert!(parse_auth_redirect(favicon, "xyz").is_err());
+    }
+
+    #[test]
+    fn test_missing_scopes() {
+        assert!(missing_scopes(&SCOPE).is_empty());
+
+        let granted: Vec<Scope> = SCOPE
+            .iter()
+            .filter(|scope| **scope != Scope::ModeratorManageWarnings)
+            .cloned()
+            .collect();
+        assert_eq!(missing_scopes(&granted), ["moderator:manage:warnings"]);
+
+        // scopes twitch_oauth2 knows by name still match the ones munibot
+        // spells out
+        let granted: Vec<Scope> = SCOPE
+            .iter()
+            .map(|scope| Scope::parse(scope.to_string()))
+            .collect();
+        assert!(missing_scopes(&granted).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_token_storage() {
+        let db = test_db().await;
+        let storage = TwitchTokenStorage::new(db, "MuniBot");
+        assert!(storage.get_token().await.unwrap().is_none());
+
+        let token = UserAccessToken {
+            access_token: "access".to_string(),
+            refresh_token: "refresh".to_string(),
+            created_at: Utc::now(),
+            expires_at: None,
+        };
+        storage.save_token(&token).await.unwrap();
+
+        let refreshed = UserAccessToken {
+            access_token: "access2".to_string(),
+            ..token
+        };
+        storage.save_token(&refreshed).await.unwrap();
 
-    pub fn get_login_credentials(&self) -> &StaticLoginCredentials {
-        &self.login_credentials
+        let saved = storage.get_token().await.unwrap().unwrap();
+        assert_eq!(saved.access_token, "access2");
+        assert_eq!(saved.refresh_token, "refresh");
     }
 }
@@ -0,0 +1,73 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use twitch_irc::message::PrivmsgMessage;
+
+/// Returns true if the sender of the message is a moderator or the
+/// broadcaster of the channel the message was sent in.
+pub fn is_moderator(msg: &PrivmsgMessage) -> bool {
+    msg.badges
+        .iter()
+        .any(|badge| badge.name == "moderator" || badge.name == "broadcaster")
+}
+
+/// How much standing a chatter has in a channel, from their badges. Roles are
+/// ordered, so a moderator can do anything a subscriber can.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatRole {
+    #[default]
+    Everyone,
+    Subscriber,
+    Vip,
+    Moderator,
+    Broadcaster,
+}
+
+impl ChatRole {
+    /// Returns the highest role the sender of the message has.
+    pub fn of(msg: &PrivmsgMessage) -> Self {
+        msg.badges
+            .iter()
+            .map(|badge| match badge.name.as_str() {
+                "broadcaster" => ChatRole::Broadcaster,
+                "moderator" => ChatRole::Moderator,
+                "vip" => ChatRole::Vip,
+                "subscriber" | "founder" => ChatRole::Subscriber,
+                _ => ChatRole::Everyone,
+            })
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+impl FromStr for ChatRole {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "everyone" | "all" => Ok(ChatRole::Everyone),
+            "subscriber" | "sub" => Ok(ChatRole::Subscriber),
+            "vip" => Ok(ChatRole::Vip),
+            "moderator" | "mod" => Ok(ChatRole::Moderator),
+            "broadcaster" | "streamer" => Ok(ChatRole::Broadcaster),
+            _ => Err(format!(
+                "{s} isn't a role. try everyone, sub, vip, mod, or broadcaster"
+            )),
+        }
+    }
+}
+
+impl Display for ChatRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChatRole::Everyone => write!(f, "everyone"),
+            ChatRole::Subscriber => write!(f, "sub"),
+            ChatRole::Vip => write!(f, "vip"),
+            ChatRole::Moderator => write!(f, "mod"),
+            ChatRole::Broadcaster => write!(f, "broadcaster"),
+        }
+    }
+}